@@ -0,0 +1,494 @@
+use std::f32::consts::PI;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{Format, Sound};
+use super::dsp::Effect;
+
+/* Tempos the estimator looks for, in beats per minute */
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+
+/* Seconds of onset strength a live analyzer keeps around for the tempo and the beats to be found in */
+const HISTORY: f32 = 10.0;
+
+/* Onset picking: how far back the adaptive threshold looks, how far above it a peak must
+ * rise, and how close two onsets can be, in seconds */
+const THRESHOLD_WINDOW: f32 = 0.5;
+const THRESHOLD_RATIO:  f32 = 1.5;
+const THRESHOLD_FLOOR:  f32 = 0.5;
+const MIN_ONSET_GAP:    f32 = 0.1;
+
+/// Transforms a block of complex numbers in place, its length must be a power of two
+pub fn fft(real: &mut [f32], imaginary: &mut [f32]){
+	let n = real.len();
+	if !n.is_power_of_two() || imaginary.len() != n{
+		error!("Can't transform {} real and {} imaginary parts, they should be as many and a power of two", n, imaginary.len());
+		return;
+	}
+
+	// Bit reversal permutation
+	let mut j = 0;
+	for i in 1..n{
+		let mut bit = n >> 1;
+		while j & bit != 0 { j ^= bit; bit >>= 1 }
+		j |= bit;
+		if i < j { real.swap(i, j); imaginary.swap(i, j) }
+	}
+
+	let mut length = 2;
+	while length <= n{
+		let angle = -2.0 * PI / length as f32;
+		let mut start = 0;
+		while start < n{
+			for k in 0..length / 2{
+				let (sin, cos) = (angle * k as f32).sin_cos();
+				let (a, b) = (start + k, start + k + length / 2);
+				let (re, im) = (real[b] * cos - imaginary[b] * sin, real[b] * sin + imaginary[b] * cos);
+
+				real[b] = real[a] - re;
+				imaginary[b] = imaginary[a] - im;
+				real[a] += re;
+				imaginary[a] += im;
+			}
+			start += length;
+		}
+		length <<= 1;
+	}
+}
+
+/// Magnitudes of the frequencies in a window of audio, from 0Hz up to half the sample rate
+#[derive(Clone, PartialEq, Debug)]
+pub struct Spectrum{
+	rate: usize,
+	magnitudes: Vec<f32> /* Amplitude of each bin, where a full scale sine reads as 1.0 */
+}
+impl Spectrum{
+	/* Hann windowed spectrum of mono samples in the -1.0 to 1.0 range */
+	fn compute(samples: &[f32], rate: usize) -> Spectrum{
+		let n = samples.len();
+		let window = |i: usize| 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos();
+
+		let mut real = samples.iter().enumerate().map(|(i, s)| *s * window(i)).collect::<Vec<f32>>();
+		let mut imaginary = vec![0.0; n];
+		fft(&mut real, &mut imaginary);
+
+		// The Hann window halves the amplitude, and only half of the bins are kept
+		let scale = 4.0 / n as f32;
+		Spectrum{
+			rate: rate,
+			magnitudes: (0..n / 2).map(|i| (real[i] * real[i] + imaginary[i] * imaginary[i]).sqrt() * scale).collect()
+		}
+	}
+
+	/// Spectrum of a window of a sound, centered at some time, in seconds
+	pub fn at(sound: &Sound, time: f64, window: usize) -> Spectrum{
+		let window = window.next_power_of_two();
+		let channels = sound.format().channels();
+		let center = (time * sound.format().samplerate() as f64) as isize;
+
+		let samples = (0..window as isize).map(|i| {
+			let frame = center - window as isize / 2 + i;
+			if frame < 0 || frame as usize >= sound.frames() { return 0.0 }
+			let frame = &sound.data()[frame as usize * channels..(frame as usize + 1) * channels];
+			frame.iter().fold(0.0, |acc, s| acc + *s as f32) / (channels as f32 * 32768.0)
+		}).collect::<Vec<f32>>();
+		Spectrum::compute(&samples, sound.format().samplerate())
+	}
+
+	pub fn magnitudes(&self) -> &[f32]{ &self.magnitudes[..] }
+	pub fn bins(&self) -> usize{ self.magnitudes.len() }
+
+	/// Center frequency of a bin, in Hz
+	pub fn frequency(&self, bin: usize) -> f32{ bin as f32 * self.rate as f32 / (self.magnitudes.len() * 2) as f32 }
+
+	/// Average magnitude of the bins between two frequencies, handy for bar visualizers
+	pub fn band(&self, low: f32, high: f32) -> f32{
+		let resolution = self.rate as f32 / (self.magnitudes.len() * 2) as f32;
+		let first = (low / resolution).floor() as usize;
+		let last  = ::std::cmp::min((high / resolution).ceil() as usize, self.magnitudes.len());
+		if last <= first { return 0.0 }
+		self.magnitudes[first..last].iter().fold(0.0, |acc, m| acc + *m) / (last - first) as f32
+	}
+}
+
+/**
+ * Listens to audio as it's fed, keeping track of its spectrum, its onsets and its tempo.
+ *
+ * Onsets are found where the energy of the spectrum rises sharply above its recent
+ * average, and the tempo is the period the onset strength repeats itself at best.
+ * An analyzer can also be inserted on a bus as an effect, to follow what's playing,
+ * in which case one made by `live` only keeps the last few seconds to look for onsets and beats in.
+ */
+pub struct Analyzer{
+	format: Format,
+	window: usize,
+	hop:    usize, /* Frames between the start of consecutive windows */
+
+	pending:  Vec<f32>, /* Mono frames that haven't made it through a full window yet */
+	spectrum: Spectrum,
+	previous: Vec<f32>, /* Log magnitudes of the last window */
+
+	envelope: Vec<f32>,   /* Onset strength of the windows still in the history */
+	dropped:  usize,      /* Windows that went past the history, before the first one in the envelope */
+	onsets:   Vec<f64>,
+	history:  Option<f32> /* Seconds kept, everything is kept when there's no limit */
+}
+impl Analyzer{
+	pub fn new(format: Format) -> Analyzer{ Analyzer::with_window(format, 1024, 512) }
+
+	/// An analyzer with a custom window size, rounded up to a power of two, and hop size
+	pub fn with_window(format: Format, window: usize, hop: usize) -> Analyzer{
+		let window = ::std::cmp::max(window, 2).next_power_of_two();
+		let hop = if hop == 0 || hop > window { window / 2 } else { hop };
+
+		// Silence before the start lets the first hop of audio be the newest in a window of its own
+		let mut pending = Vec::with_capacity(window);
+		pending.resize(window - hop, 0.0);
+		Analyzer{
+			format: format,
+			window: window,
+			hop:    hop,
+
+			pending:  pending,
+			spectrum: Spectrum{ rate: format.samplerate(), magnitudes: vec![0.0; window / 2] },
+			previous: vec![0.0; window / 2],
+
+			envelope: Vec::new(),
+			dropped:  0,
+			onsets:   Vec::new(),
+			history:  None
+		}
+	}
+
+	/// An analyzer to insert on a bus as an effect, which only keeps the last ten seconds it heard
+	pub fn live(format: Format) -> Rc<RefCell<Analyzer>>{
+		let mut analyzer = Analyzer::new(format);
+		analyzer.history = Some(HISTORY);
+		Rc::new(RefCell::new(analyzer))
+	}
+
+	/// Runs a whole sound through a new analyzer
+	pub fn analyze(sound: &Sound) -> Analyzer{
+		let mut analyzer = Analyzer::new(sound.format());
+		analyzer.feed(sound.data());
+		analyzer
+	}
+
+	pub fn format(&self) -> Format{ self.format }
+
+	/// Forgets everything heard so far, getting ready for audio in a new format
+	pub fn reset(&mut self, format: Format){
+		let history = self.history;
+		*self = Analyzer::with_window(format, self.window, self.hop);
+		self.history = history;
+	}
+
+	/// Feeds interleaved samples in the analyzer's format
+	pub fn feed(&mut self, samples: &[i16]){
+		let channels = self.format.channels();
+		for frame in samples.chunks(channels){
+			let mono = frame.iter().fold(0.0, |acc, s| acc + *s as f32) / (channels as f32 * 32768.0);
+			self.push(mono);
+		}
+	}
+
+	fn push(&mut self, sample: f32){
+		self.pending.push(sample);
+		if self.pending.len() < self.window { return }
+
+		self.spectrum = Spectrum::compute(&self.pending, self.format.samplerate());
+		self.pending.drain(..self.hop);
+
+		// Onset strength is how much louder the spectrum got, on a log scale so quiet bands count too
+		let mut strength = 0.0;
+		for (magnitude, previous) in self.spectrum.magnitudes.iter().zip(self.previous.iter_mut()){
+			let level = (1.0 + 1000.0 * *magnitude).ln();
+			if level > *previous { strength += level - *previous }
+			*previous = level;
+		}
+		self.envelope.push(strength);
+
+		self.pick_onset();
+		self.forget();
+	}
+
+	/* Drops the windows, and the onsets in them, that went past the history */
+	fn forget(&mut self){
+		let history = match self.history{ Some(history) => history, None => return };
+		let length = (history * self.hop_rate()).ceil() as usize;
+		if self.envelope.len() <= length { return }
+
+		let excess = self.envelope.len() - length;
+		self.envelope.drain(..excess);
+		self.dropped += excess;
+
+		let start = self.time_of(self.dropped);
+		self.onsets.retain(|onset| *onset >= start);
+	}
+
+	/* Checks whether the window before the last one was a peak standing out of its surroundings */
+	fn pick_onset(&mut self){
+		let count = self.envelope.len();
+		if count < 2 { return }
+		let before = if count > 2 { self.envelope[count - 3] } else { 0.0 };
+		let (peak, after) = (self.envelope[count - 2], self.envelope[count - 1]);
+		if peak <= before || peak < after { return }
+
+		let span = ::std::cmp::max((THRESHOLD_WINDOW * self.hop_rate()) as usize, 1);
+		let history = &self.envelope[count.saturating_sub(span + 2)..count - 2];
+		let mean = if history.is_empty() { 0.0 } else { history.iter().fold(0.0, |acc, s| acc + *s) / history.len() as f32 };
+		if peak < mean * THRESHOLD_RATIO + THRESHOLD_FLOOR { return }
+
+		let time = self.time_of(self.dropped + count - 2);
+		match self.onsets.last(){
+			Some(last) if time - *last < MIN_ONSET_GAP as f64 => {},
+			_ => self.onsets.push(time)
+		}
+	}
+
+	/* Number of windows analyzed every second */
+	fn hop_rate(&self) -> f32{ self.format.samplerate() as f32 / self.hop as f32 }
+
+	/* Time at which the newest audio of a window starts, where onsets it catches happen, counting every window so far */
+	fn time_of(&self, window: usize) -> f64{
+		(window * self.hop) as f64 / self.format.samplerate() as f64
+	}
+
+	/// Spectrum of the latest full window
+	pub fn spectrum(&self) -> &Spectrum{ &self.spectrum }
+
+	/// Times at which notes or hits start, in seconds, over the last ten seconds or so for a live analyzer
+	pub fn onsets(&self) -> &[f64]{ &self.onsets[..] }
+
+	/// Seconds of audio analyzed so far
+	pub fn duration(&self) -> f64{
+		if self.envelope.is_empty() { 0.0 } else { self.time_of(self.dropped + self.envelope.len() - 1) + self.hop as f64 / self.format.samplerate() as f64 }
+	}
+
+	/// Estimates the tempo from the onset strength heard lately, if there's enough of it
+	pub fn bpm(&self) -> Option<f32>{
+		self.period().map(|period| 60.0 * self.hop_rate() / period)
+	}
+
+	/* Beat period, in windows, found by autocorrelating the onset strength */
+	fn period(&self) -> Option<f32>{
+		let rate = self.hop_rate();
+		let (shortest, longest) = ((60.0 * rate / MAX_BPM).floor() as usize, (60.0 * rate / MIN_BPM).ceil() as usize);
+		if shortest < 1 || self.envelope.len() < longest * 2 { return None }
+
+		let correlation = |lag: usize| self.envelope.iter().zip(&self.envelope[lag..]).fold(0.0, |acc, (a, b)| acc + a * b);
+		let scores = (shortest..longest + 1).map(correlation).collect::<Vec<f32>>();
+
+		// Weigh tempos towards 120, so halves and doubles of the actual tempo lose out
+		let mut best: Option<(usize, f32)> = None;
+		for lag in shortest..longest + 1{
+			let bpm = 60.0 * rate / lag as f32;
+			let weight = (-0.5 * (bpm / 120.0).log2().powi(2)).exp();
+			let score = scores[lag - shortest] * weight;
+			if score > 0.0 && best.map(|(_, s)| score > s).unwrap_or(true) { best = Some((lag, score)) }
+		}
+
+		// Refine the lag a few beats away, where being a window off matters less, then in
+		// between windows with a parabola through the neighbours of the peak found there
+		best.map(|(lag, _)| {
+			let mut multiple = 4;
+			while multiple > 1 && (lag + 1) * multiple + 1 >= self.envelope.len() / 2 { multiple -= 1 }
+
+			let around = lag * multiple;
+			let peak = (around + 1 - multiple..around + multiple).fold(around, |peak, lag| if correlation(lag) > correlation(peak) { lag } else { peak });
+			let (a, b, c) = (correlation(peak - 1), correlation(peak), correlation(peak + 1));
+			let curvature = a - 2.0 * b + c;
+			let offset = if curvature < 0.0 { 0.5 * (a - c) / curvature } else { 0.0 };
+			(peak as f32 + if offset.abs() < 1.0 { offset } else { 0.0 }) / multiple as f32
+		})
+	}
+
+	/// Times of the beats heard lately, in seconds, following the estimated tempo
+	pub fn beats(&self) -> Vec<f64>{
+		let period = match self.period(){ Some(period) => period, None => return Vec::new() };
+
+		// Slide a comb of beats along the onset strength, keeping the phase that lines up best
+		let strength_at = |phase: f32| {
+			let mut sum = 0.0;
+			let mut position = phase;
+			while (position.round() as usize) < self.envelope.len(){
+				sum += self.envelope[position.round() as usize];
+				position += period;
+			}
+			sum
+		};
+		let steps = (period * 4.0).ceil() as usize;
+		let phase = (0..steps).map(|step| step as f32 / 4.0)
+			.fold((0.0, -1.0), |(best, score), phase| {
+				let s = strength_at(phase);
+				if s > score { (phase, s) } else { (best, score) }
+			}).0;
+
+		let mut beats = Vec::new();
+		let mut position = phase;
+		while (position.round() as usize) < self.envelope.len(){
+			let window = position.floor() as usize;
+			let fract = (position - window as f32) as f64 * self.hop as f64 / self.format.samplerate() as f64;
+			beats.push(self.time_of(self.dropped + window) + fract);
+			position += period;
+		}
+		beats
+	}
+}
+
+/* Sharing an analyzer lets it sit on a bus while the game keeps reading from it */
+impl Effect for Rc<RefCell<Analyzer>>{
+	fn process(&mut self, samples: &mut [f32], format: Format){
+		let mut analyzer = self.borrow_mut();
+		if analyzer.format != format { analyzer.reset(format) }
+
+		let channels = format.channels();
+		for frame in samples.chunks(channels){
+			let mono = frame.iter().fold(0.0, |acc, s| acc + *s) / (channels as f32 * 32768.0);
+			analyzer.push(mono);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use std::rc::Rc;
+	use super::{fft, Analyzer, Spectrum};
+	use super::super::{Sound, Format, Mixer};
+
+	/* Some seconds of short tone bursts, one every beat at some tempo */
+	fn clicks(format: Format, bpm: usize, seconds: usize) -> Sound{
+		use std::f32::consts::PI;
+		let rate = format.samplerate();
+		let mut data = Vec::new();
+		for frame in 0..rate * seconds{
+			let since = frame % (rate * 60 / bpm);
+			let value = if since < rate / 20{
+				let decay = 1.0 - since as f32 / (rate / 20) as f32;
+				((2.0 * PI * 1000.0 * frame as f32 / rate as f32).sin() * 20000.0 * decay) as i16
+			}else{ 0 };
+			for _ in 0..format.channels(){ data.push(value) }
+		}
+		Sound::from_samples(format, data)
+	}
+
+	#[test]
+	fn spectrum(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let (mut real, mut imaginary) = (vec![1.0, 0.0, 0.0, 0.0], vec![0.0; 4]);
+		fft(&mut real, &mut imaginary);
+		assert_eq!(real, vec![1.0; 4]);
+
+		// Lengths it can't transform are left alone
+		let (mut real, mut imaginary) = (vec![1.0, 0.0, 0.0], vec![0.0; 3]);
+		fft(&mut real, &mut imaginary);
+		assert_eq!(real, vec![1.0, 0.0, 0.0]);
+
+		use std::f32::consts::PI;
+		let sound = Sound::from_samples(Format::new(1, 1024), (0..4096)
+			.map(|i| ((2.0 * PI * 100.0 * i as f32 / 1024.0).sin() * 16384.0) as i16)
+			.collect());
+		let spectrum = Spectrum::at(&sound, 2.0, 1024);
+		assert_eq!(spectrum.bins(), 512);
+		assert_eq!(spectrum.frequency(100), 100.0);
+
+		let peak = (0..spectrum.bins()).fold(0, |best, bin| if spectrum.magnitudes()[bin] > spectrum.magnitudes()[best] { bin } else { best });
+		assert_eq!(peak, 100);
+		assert!((spectrum.magnitudes()[100] - 0.5).abs() < 0.01);
+		assert!(spectrum.band(90.0, 110.0) > spectrum.band(300.0, 400.0) * 10.0);
+	}
+
+	#[test]
+	fn onsets_and_tempo(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let analyzer = Analyzer::analyze(&clicks(Format::new(1, 11025), 120, 8));
+		let hop = 512.0 / 11025.0;
+
+		// Every burst is caught, within a window hop of where it starts
+		assert_eq!(analyzer.onsets().len(), 16);
+		for (i, onset) in analyzer.onsets().iter().enumerate(){
+			assert!((*onset - i as f64 * 0.5).abs() <= hop, "Onset {} at {}", i, onset);
+		}
+
+		let bpm = analyzer.bpm().unwrap();
+		assert!((bpm - 120.0).abs() < 2.0, "Estimated {} BPM", bpm);
+
+		let beats = analyzer.beats();
+		assert!(beats.len() >= 15);
+		for beat in &beats{
+			let nearest = (*beat / 0.5).round() * 0.5;
+			assert!((*beat - nearest).abs() <= hop * 1.5, "Beat at {}", beat);
+		}
+
+		// Tempos away from 120 are found just as well
+		for &tempo in &[90, 150]{
+			let analyzer = Analyzer::analyze(&clicks(Format::new(1, 11025), tempo, 8));
+			let bpm = analyzer.bpm().unwrap();
+			assert!((bpm - tempo as f32).abs() < 2.0, "Estimated {} BPM for {}", bpm, tempo);
+
+			let period = 60.0 / tempo as f64;
+			assert_eq!(analyzer.onsets().len(), (8.0 / period).ceil() as usize);
+			for beat in &analyzer.beats(){
+				let nearest = (*beat / period).round() * period;
+				assert!((*beat - nearest).abs() <= hop * 1.5, "Beat at {} for {}", beat, tempo);
+			}
+		}
+	}
+
+	#[test]
+	fn history(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// A long running live analyzer only keeps the last few seconds around
+		let sound = clicks(Format::new(1, 11025), 120, 30);
+		let analyzer = Analyzer::live(sound.format());
+		for chunk in sound.data().chunks(4096){ analyzer.borrow_mut().feed(chunk) }
+		let analyzer = analyzer.borrow();
+		assert!(analyzer.envelope.len() <= (10.0 * 11025.0 / 512.0f32).ceil() as usize);
+		assert!((analyzer.duration() - 30.0).abs() < 0.1);
+
+		// Yet everything in there is still where it happened
+		assert!(analyzer.onsets().len() >= 19 && analyzer.onsets().len() <= 21);
+		assert!(analyzer.onsets().iter().all(|onset| *onset >= 19.0 && (*onset / 0.5 - (*onset / 0.5).round()).abs() <= 0.1));
+		assert!((analyzer.bpm().unwrap() - 120.0).abs() < 2.0);
+		assert!(analyzer.beats().iter().all(|beat| *beat >= 19.0 && *beat <= 30.0));
+
+		// While analyzing a whole sound keeps everything from the start
+		let analyzer = Analyzer::analyze(&sound);
+		assert_eq!(analyzer.onsets().len(), 60);
+		assert!(analyzer.onsets()[0] < 0.1);
+		let beats = analyzer.beats();
+		assert!(beats[0] < 0.1 && beats.len() >= 59);
+		assert!(beats.iter().all(|beat| (*beat / 0.5 - (*beat / 0.5).round()).abs() <= 0.1));
+	}
+
+	#[test]
+	fn streaming(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Feeding in odd pieces finds the same onsets as feeding everything at once
+		let sound = clicks(Format::new(2, 11025), 120, 8);
+		let offline = Analyzer::analyze(&sound);
+		let mut streaming = Analyzer::new(sound.format());
+		for chunk in sound.data().chunks(778){ streaming.feed(chunk) }
+		assert_eq!(streaming.onsets(), offline.onsets());
+
+		// An analyzer inserted on the master mix listens to whatever is played
+		let analyzer = Analyzer::live(Format::new(2, 11025));
+		let mut mixer = Mixer::new(Format::new(2, 11025));
+		mixer.add_effect(Box::new(analyzer.clone()));
+		mixer.play(Rc::new(sound));
+
+		let mut output = vec![0i16; 2 * 11025];
+		mixer.render(&mut output);
+		assert_eq!(analyzer.borrow().onsets(), &offline.onsets()[..2]);
+		assert!(analyzer.borrow().spectrum().band(900.0, 1100.0) >= 0.0);
+	}
+}
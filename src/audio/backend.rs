@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use super::hound;
+use super::{Format, Mixer, AudioError};
+
+/// Somewhere mixed audio can be sent to, be it a sound card, a file or nowhere at all
+pub trait AudioBackend{
+	/// Format the backend expects its samples in
+	fn format(&self) -> Format;
+
+	/// Hands a block of interleaved samples over to the backend
+	fn submit(&mut self, samples: &[i16]) -> Result<(), AudioError>;
+
+	/// Flushes whatever the backend still holds, once no more samples will be submitted
+	fn finish(&mut self) -> Result<(), AudioError>{ Ok(()) }
+}
+
+/// Discards every sample, keeping only a count of how many frames went through
+pub struct NullBackend{
+	format: Format,
+	frames: usize
+}
+impl NullBackend{
+	pub fn new(format: Format) -> NullBackend{
+		NullBackend{
+			format: format,
+			frames: 0
+		}
+	}
+
+	pub fn frames(&self) -> usize{ self.frames }
+}
+impl AudioBackend for NullBackend{
+	fn format(&self) -> Format{ self.format }
+	fn submit(&mut self, samples: &[i16]) -> Result<(), AudioError>{
+		self.frames += samples.len() / self.format.channels();
+		Ok(())
+	}
+}
+
+/// Records every sample into a 16-bit Wave file
+pub struct WavFileBackend{
+	format: Format,
+	writer: Option<hound::WavWriter<BufWriter<File>>>
+}
+impl WavFileBackend{
+	pub fn create(path: &str, format: Format) -> Result<WavFileBackend, AudioError>{
+		let spec = hound::WavSpec{
+			channels:        format.channels() as u16,
+			sample_rate:     format.samplerate() as u32,
+			bits_per_sample: 16,
+			sample_format:   hound::SampleFormat::Int
+		};
+
+		match hound::WavWriter::create(path, spec){
+			Ok(writer) => Ok(WavFileBackend{
+				format: format,
+				writer: Some(writer)
+			}),
+			Err(what) => {
+				error!(r#"Could not create Wave file at "{}": {:?}"#, path, what);
+				Err(AudioError::Wave(what))
+			}
+		}
+	}
+}
+impl AudioBackend for WavFileBackend{
+	fn format(&self) -> Format{ self.format }
+	fn submit(&mut self, samples: &[i16]) -> Result<(), AudioError>{
+		if let Some(ref mut writer) = self.writer{
+			for sample in samples{
+				if let Err(what) = writer.write_sample(*sample){
+					error!("Could not write sample to Wave file: {:?}", what);
+					return Err(AudioError::Wave(what))
+				}
+			}
+		}
+		Ok(())
+	}
+	fn finish(&mut self) -> Result<(), AudioError>{
+		match self.writer.take(){
+			Some(writer) => writer.finalize().map_err(|what| {
+				error!("Could not finalize Wave file: {:?}", what);
+				AudioError::Wave(what)
+			}),
+			None => Ok(())
+		}
+	}
+}
+
+/// Drives a `Mixer`, feeding whatever it renders into a backend
+pub struct Output{
+	mixer:   Mixer,
+	backend: Box<AudioBackend>,
+
+	pending: f64,     /* Fraction of a frame owed to the backend from previous advances */
+	buffer: Vec<i16>
+}
+impl Output{
+	/// Creates an output with a mixer in the backend's format
+	pub fn new(backend: Box<AudioBackend>) -> Output{
+		Output{
+			mixer:   Mixer::new(backend.format()),
+			backend: backend,
+
+			pending: 0.0,
+			buffer: Vec::new()
+		}
+	}
+
+	pub fn mixer(&mut self) -> &mut Mixer{ &mut self.mixer }
+	pub fn backend(&mut self) -> &mut AudioBackend{ &mut *self.backend }
+
+	/// Mixes and submits exactly `frames` frames
+	pub fn render(&mut self, frames: usize) -> Result<(), AudioError>{
+		self.buffer.resize(frames * self.mixer.format().channels(), 0);
+		self.mixer.render(&mut self.buffer[..]);
+		self.backend.submit(&self.buffer[..])
+	}
+
+	/// Mixes and submits as many frames as fit in `delta` seconds
+	pub fn advance(&mut self, delta: f64) -> Result<(), AudioError>{
+		self.pending += delta * self.mixer.format().samplerate() as f64;
+
+		let frames = self.pending.floor();
+		self.pending -= frames;
+		self.render(frames as usize)
+	}
+
+	pub fn finish(&mut self) -> Result<(), AudioError>{ self.backend.finish() }
+}
+
+#[cfg(test)]
+mod tests{
+	use std::rc::Rc;
+	use super::{Output, NullBackend, WavFileBackend};
+	use super::super::{Sound, Codec, Format};
+
+	#[test]
+	fn null(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut output = Output::new(Box::new(NullBackend::new(Format::new(2, 100))));
+		output.advance(0.255).unwrap();
+		assert_eq!(output.buffer.len(), 50);
+
+		// Half a frame was left over from the last advance
+		output.advance(0.255).unwrap();
+		assert_eq!(output.buffer.len(), 52);
+	}
+
+	#[test]
+	fn wave_file(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use std::env;
+		let mut path = env::temp_dir();
+		path.push("bbman_backend_wave.wav");
+		let path = path.to_str().unwrap().to_owned();
+
+		let format = Format::new(1, 100);
+		let sound = Rc::new(Sound::from_samples(format, vec![10, 20, 30, 40]));
+
+		{
+			let mut output = Output::new(Box::new(WavFileBackend::create(&path, format).unwrap()));
+			output.mixer().play(sound.clone());
+			output.mixer().play(sound.clone());
+			output.render(3).unwrap();
+			output.render(3).unwrap();
+			output.finish().unwrap();
+		}
+
+		let recorded = Sound::new(Codec::Wave, &path).unwrap();
+		assert_eq!(recorded.format(), format);
+		assert_eq!(recorded.data(), &[20, 40, 60, 80, 0, 0]);
+	}
+}
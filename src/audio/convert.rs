@@ -0,0 +1,199 @@
+use std::f64::consts::PI;
+
+use super::Format;
+
+/// How samples in between two source frames are reconstructed when resampling
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Interpolation{
+	/// Straight line between neighbouring frames, cheap but lets some aliasing through
+	Linear,
+	/// Blackman windowed sinc, with the given number of zero crossings on each side
+	Sinc(usize)
+}
+impl Default for Interpolation{
+	fn default() -> Interpolation{ Interpolation::Sinc(16) }
+}
+
+/**
+ * Interpolation the links of a chained stream are conformed to its first link's format with.
+ * Links get converted a packet at a time, so the result is the same whether the stream is
+ * loaded whole or streamed, and a sinc kernel would be cut short at the edge of every packet.
+ */
+pub const CHAINED_INTERPOLATION: Interpolation = Interpolation::Linear;
+
+/* Mixing coefficient for the center and surround channels of a 5.1 downmix (-3dB) */
+const SURROUND_GAIN: f32 = ::std::f32::consts::FRAC_1_SQRT_2;
+
+/**
+ * Remaps a single frame between channel layouts, the layouts being given by the
+ * slice lengths. Mono is spread into the front speakers, stereo folds into mono
+ * by averaging and 5.1 (in FL, FR, FC, LFE, BL, BR order) is downmixed with the
+ * center and surround channels at -3dB, dropping the LFE.
+ *
+ * Any other combination maps channels one to one, silencing the extra ones.
+ */
+pub fn remap_frame(input: &[f32], output: &mut [f32]){
+	match (input.len(), output.len()){
+		(a, b) if a == b => output.copy_from_slice(input),
+		(1, _) => {
+			for (channel, sample) in output.iter_mut().enumerate(){ *sample = if channel < 2 { input[0] } else { 0.0 } }
+		},
+		(_, 1) => {
+			let mut stereo = [0.0; 2];
+			remap_frame(input, &mut stereo);
+			output[0] = (stereo[0] + stereo[1]) / 2.0;
+		},
+		(6, 2) => {
+			// Normalized so a full scale signal in every channel can't clip
+			let normal = 1.0 + SURROUND_GAIN * 2.0;
+			output[0] = (input[0] + input[2] * SURROUND_GAIN + input[4] * SURROUND_GAIN) / normal;
+			output[1] = (input[1] + input[2] * SURROUND_GAIN + input[5] * SURROUND_GAIN) / normal;
+		},
+		_ => {
+			for (channel, sample) in output.iter_mut().enumerate(){ *sample = if channel < input.len() { input[channel] } else { 0.0 } }
+		}
+	}
+}
+
+/// Remaps every frame of interleaved data between channel counts
+pub fn remap(data: &[i16], from: usize, to: usize) -> Vec<i16>{
+	if from == to { return data.to_vec() }
+
+	let mut input  = vec![0.0f32; from];
+	let mut output = vec![0.0f32; to];
+	let mut remapped = Vec::with_capacity(data.len() / from * to);
+	for frame in data.chunks(from).filter(|frame| frame.len() == from){
+		for (i, s) in input.iter_mut().zip(frame){ *i = *s as f32 }
+		remap_frame(&input, &mut output);
+		for s in &output{ remapped.push(saturate(*s as f64)) }
+	}
+
+	remapped
+}
+
+/// Resamples interleaved data from one sample rate to another
+pub fn resample(data: &[i16], channels: usize, from: usize, to: usize, interpolation: Interpolation) -> Vec<i16>{
+	let frames = data.len() / channels;
+	if from == to || frames == 0 { return data[..frames * channels].to_vec() }
+
+	let step   = from as f64 / to as f64;
+	let length = frames * to / from;
+	let mut resampled = Vec::with_capacity(length * channels);
+	match interpolation{
+		Interpolation::Linear => for frame in 0..length{
+			let position = frame as f64 * step;
+			let index = position.floor() as usize;
+			let next  = if index + 1 < frames { index + 1 } else { index };
+			let fract = position - index as f64;
+			for channel in 0..channels{
+				let a = data[index * channels + channel] as f64;
+				let b = data[next  * channels + channel] as f64;
+				resampled.push(saturate(a + (b - a) * fract));
+			}
+		},
+		Interpolation::Sinc(zeros) => {
+			// When going down in rate, the cutoff has to move below the new Nyquist frequency
+			let cutoff = if step > 1.0 { 1.0 / step } else { 1.0 };
+			let radius = zeros as f64 / cutoff;
+
+			let mut sums = vec![0.0f64; channels];
+			for frame in 0..length{
+				let position = frame as f64 * step;
+				let first = (position - radius).ceil().max(0.0) as usize;
+				let last  = ((position + radius).floor() as usize).min(frames - 1);
+
+				for sum in sums.iter_mut(){ *sum = 0.0 }
+				for index in first..last + 1{
+					let weight = kernel(position - index as f64, cutoff, radius);
+					for (channel, sum) in sums.iter_mut().enumerate(){
+						*sum += data[index * channels + channel] as f64 * weight;
+					}
+				}
+				for sum in &sums{ resampled.push(saturate(*sum)) }
+			}
+		}
+	}
+
+	resampled
+}
+
+/// Converts interleaved data between formats, remapping channels and resampling
+pub fn convert(data: &[i16], from: Format, to: Format, interpolation: Interpolation) -> Vec<i16>{
+	if from == to { return data.to_vec() }
+
+	// Do the expensive resampling on whichever side has the fewest channels
+	if to.channels() <= from.channels(){
+		let remapped = remap(data, from.channels(), to.channels());
+		resample(&remapped, to.channels(), from.samplerate(), to.samplerate(), interpolation)
+	}else{
+		let resampled = resample(data, from.channels(), from.samplerate(), to.samplerate(), interpolation);
+		remap(&resampled, from.channels(), to.channels())
+	}
+}
+
+/* Low-pass sinc at a given cutoff, under a Blackman window of a given radius */
+fn kernel(distance: f64, cutoff: f64, radius: f64) -> f64{
+	if distance.abs() >= radius { return 0.0 }
+
+	let x = PI * cutoff * distance;
+	let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+
+	let w = PI * distance / radius;
+	let window = 0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+
+	cutoff * sinc * window
+}
+
+fn saturate(sample: f64) -> i16{
+	if sample >= 32767.0 { 32767 } else if sample <= -32768.0 { -32768 } else { sample.round() as i16 }
+}
+
+#[cfg(test)]
+mod tests{
+	use super::{remap, resample, convert, Interpolation};
+	use super::super::Format;
+
+	#[test]
+	fn channels(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		assert_eq!(remap(&[10, 20], 1, 2), vec![10, 10, 20, 20]);
+		assert_eq!(remap(&[10, 20, -4, 4], 2, 1), vec![15, 0]);
+		assert_eq!(remap(&[10, 20], 2, 6), vec![10, 20, 0, 0, 0, 0]);
+
+		// Front left, center, LFE and back right only
+		assert_eq!(remap(&[1000, 0, 1000, 5000, 0, 1000], 6, 2), vec![707, 586]);
+	}
+
+	#[test]
+	fn linear(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		assert_eq!(resample(&[0, 100, 200, 300], 1, 100, 200, Interpolation::Linear), vec![0, 50, 100, 150, 200, 250, 300, 300]);
+		assert_eq!(resample(&[0, 1, 100, 101, 200, 201, 300, 301], 2, 200, 100, Interpolation::Linear), vec![0, 1, 200, 201]);
+	}
+
+	#[test]
+	fn sinc(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// A 440Hz tone, resampled from 22050Hz to 48000Hz, must still be the same tone
+		use std::f64::consts::PI;
+		let tone = |rate: usize, frames: usize| (0..frames)
+			.map(|i| ((2.0 * PI * 440.0 * i as f64 / rate as f64).sin() * 16000.0).round() as i16)
+			.collect::<Vec<i16>>();
+
+		let resampled = convert(&tone(22050, 2205), Format::new(1, 22050), Format::new(2, 48000), Interpolation::default());
+		let expected  = tone(48000, 4800);
+		assert_eq!(resampled.len(), expected.len() * 2);
+
+		// Only look away from the edges, where the kernel runs out of input
+		for frame in 100..4700{
+			assert!((resampled[frame * 2] as i32 - expected[frame] as i32).abs() < 64, "Frame {} is off", frame);
+			assert_eq!(resampled[frame * 2], resampled[frame * 2 + 1]);
+		}
+	}
+}
@@ -0,0 +1,438 @@
+use std::f32::consts::PI;
+use std::cmp::max;
+
+use super::Format;
+
+/**
+ * An audio effect that can be inserted into a voice, a bus or the master mix.
+ *
+ * Samples are interleaved in the given format and lie in the `i16` range, but are
+ * kept as floats so effects can go over it and leave clipping to the end of the chain.
+ */
+pub trait Effect{
+	/// Processes a block of samples in place
+	fn process(&mut self, samples: &mut [f32], format: Format);
+
+	/// Forgets about every sample processed so far, such as echoes and reverb tails
+	fn reset(&mut self) { }
+
+	/// Whether the effect still has something to play out once its input goes silent
+	fn is_ringing(&self) -> bool { false }
+}
+
+/* Level under which a sample rounds to zero once converted back to i16 */
+const SILENCE: f32 = 0.5;
+
+/* Loudest sample in a buffer */
+fn peak(samples: &[f32]) -> f32{ samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs())) }
+
+/// Frequency responses available for a `Biquad`
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FilterKind{ LowPass, HighPass, BandPass }
+
+/// Second order IIR filter, from the Audio EQ Cookbook
+pub struct Biquad{
+	kind: FilterKind,
+	frequency: f32, /* Cutoff, or center for band-pass, in Hz */
+	q: f32,
+
+	coefficients: [f32; 5],       /* b0, b1, b2, a1 and a2, normalized by a0   */
+	history: Vec<[f32; 4]>,       /* x1, x2, y1 and y2 of every channel        */
+	configured: Option<Format>    /* Format the coefficients were computed for */
+}
+impl Biquad{
+	pub fn new(kind: FilterKind, frequency: f32, q: f32) -> Biquad{
+		Biquad{
+			kind: kind,
+			frequency: frequency,
+			q: q,
+
+			coefficients: [1.0, 0.0, 0.0, 0.0, 0.0],
+			history: Vec::new(),
+			configured: None
+		}
+	}
+
+	pub fn low_pass(frequency: f32, q: f32)  -> Biquad{ Biquad::new(FilterKind::LowPass,  frequency, q) }
+	pub fn high_pass(frequency: f32, q: f32) -> Biquad{ Biquad::new(FilterKind::HighPass, frequency, q) }
+	pub fn band_pass(frequency: f32, q: f32) -> Biquad{ Biquad::new(FilterKind::BandPass, frequency, q) }
+
+	pub fn frequency(&self) -> f32{ self.frequency }
+	pub fn set_frequency(&mut self, frequency: f32){
+		self.frequency  = frequency;
+		self.configured = None;
+	}
+
+	fn configure(&mut self, format: Format){
+		let w0 = 2.0 * PI * self.frequency / format.samplerate() as f32;
+		let (sin, cos) = (w0.sin(), w0.cos());
+		let alpha = sin / (2.0 * self.q);
+
+		let (b0, b1, b2) = match self.kind{
+			FilterKind::LowPass  => ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0),
+			FilterKind::HighPass => ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0),
+			FilterKind::BandPass => (alpha, 0.0, -alpha)
+		};
+		let (a0, a1, a2) = (1.0 + alpha, -2.0 * cos, 1.0 - alpha);
+
+		self.coefficients = [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0];
+		if self.history.len() != format.channels(){ self.history = vec![[0.0; 4]; format.channels()] }
+		self.configured = Some(format);
+	}
+}
+impl Effect for Biquad{
+	fn process(&mut self, samples: &mut [f32], format: Format){
+		if self.configured != Some(format) { self.configure(format) }
+
+		let (b0, b1, b2) = (self.coefficients[0], self.coefficients[1], self.coefficients[2]);
+		let (a1, a2) = (self.coefficients[3], self.coefficients[4]);
+		for frame in samples.chunks_mut(format.channels()){
+			for (sample, h) in frame.iter_mut().zip(self.history.iter_mut()){
+				let x = *sample;
+				let y = b0 * x + b1 * h[0] + b2 * h[1] - a1 * h[2] - a2 * h[3];
+
+				*h = [x, h[0], y, h[2]];
+				*sample = y;
+			}
+		}
+	}
+
+	fn reset(&mut self){
+		for h in self.history.iter_mut(){ *h = [0.0; 4] }
+	}
+}
+
+/// Repeats the signal after a delay, each repetition quieter than the last
+pub struct Delay{
+	time:     f32, /* Seconds between repetitions                         */
+	feedback: f32, /* How much of each repetition goes into the next one  */
+	wet:      f32, /* Volume of the repetitions                           */
+	dry:      f32, /* Volume of the original signal                       */
+
+	buffer: Vec<f32>, /* Interleaved ring buffer of delayed frames */
+	cursor: usize,
+	configured: Option<Format>
+}
+impl Delay{
+	pub fn new(time: f32, feedback: f32, wet: f32) -> Delay{
+		Delay{
+			time:     time,
+			feedback: feedback,
+			wet:      wet,
+			dry:      1.0,
+
+			buffer: Vec::new(),
+			cursor: 0,
+			configured: None
+		}
+	}
+
+	pub fn set_dry(&mut self, dry: f32){ self.dry = dry }
+}
+impl Effect for Delay{
+	fn process(&mut self, samples: &mut [f32], format: Format){
+		let channels = format.channels();
+		if self.configured != Some(format){
+			let frames = max((self.time * format.samplerate() as f32).round() as usize, 1);
+			self.buffer = vec![0.0; frames * channels];
+			self.cursor = 0;
+			self.configured = Some(format);
+		}
+
+		for frame in samples.chunks_mut(channels){
+			for (channel, sample) in frame.iter_mut().enumerate(){
+				let delayed = self.buffer[self.cursor + channel];
+				self.buffer[self.cursor + channel] = *sample + delayed * self.feedback;
+				*sample = *sample * self.dry + delayed * self.wet;
+			}
+			self.cursor = (self.cursor + channels) % self.buffer.len();
+		}
+	}
+
+	fn reset(&mut self){
+		for sample in self.buffer.iter_mut(){ *sample = 0.0 }
+	}
+
+	fn is_ringing(&self) -> bool{ peak(&self.buffer) * self.wet.abs() >= SILENCE }
+}
+
+/* Freeverb tunings, in frames at 44100Hz */
+const REVERB_COMBS:     [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const REVERB_ALLPASSES: [usize; 4] = [556, 441, 341, 225];
+const REVERB_SPREAD: usize = 23;  /* Extra length for odd channels, to decorrelate them */
+const REVERB_INPUT:  f32 = 0.015; /* Keeps the sum of the parallel combs from exploding */
+
+struct Comb{ buffer: Vec<f32>, cursor: usize, store: f32 }
+struct Allpass{ buffer: Vec<f32>, cursor: usize }
+
+/// Freeverb, a Schroeder reverb made of parallel low-passed combs followed by allpasses
+pub struct Reverb{
+	room_size: f32, /* From 0.0 to 1.0, longer tails for bigger rooms       */
+	damping:   f32, /* From 0.0 to 1.0, how quickly high frequencies die out */
+	wet:       f32,
+	dry:       f32,
+
+	combs:     Vec<Vec<Comb>>,    /* Every comb, for every channel     */
+	allpasses: Vec<Vec<Allpass>>, /* Every allpass, for every channel  */
+	configured: Option<Format>
+}
+impl Reverb{
+	pub fn new(room_size: f32, damping: f32, wet: f32) -> Reverb{
+		Reverb{
+			room_size: room_size,
+			damping:   damping,
+			wet:       wet,
+			dry:       1.0,
+
+			combs:     Vec::new(),
+			allpasses: Vec::new(),
+			configured: None
+		}
+	}
+
+	pub fn set_dry(&mut self, dry: f32){ self.dry = dry }
+}
+impl Effect for Reverb{
+	fn process(&mut self, samples: &mut [f32], format: Format){
+		let channels = format.channels();
+		if self.configured != Some(format){
+			let scale = format.samplerate() as f32 / 44100.0;
+			let length = |tuning: usize, channel: usize| max(((tuning + (channel % 2) * REVERB_SPREAD) as f32 * scale) as usize, 1);
+
+			self.combs = (0..channels).map(|channel| REVERB_COMBS.iter()
+				.map(|tuning| Comb{ buffer: vec![0.0; length(*tuning, channel)], cursor: 0, store: 0.0 })
+				.collect()).collect();
+			self.allpasses = (0..channels).map(|channel| REVERB_ALLPASSES.iter()
+				.map(|tuning| Allpass{ buffer: vec![0.0; length(*tuning, channel)], cursor: 0 })
+				.collect()).collect();
+			self.configured = Some(format);
+		}
+
+		let feedback = self.room_size * 0.28 + 0.7;
+		let damp = self.damping * 0.4;
+		let wet = self.wet * 3.0;
+
+		for frame in samples.chunks_mut(channels){
+			for (channel, sample) in frame.iter_mut().enumerate(){
+				let input = *sample * REVERB_INPUT;
+
+				let mut output = 0.0;
+				for comb in self.combs[channel].iter_mut(){
+					let delayed = comb.buffer[comb.cursor];
+					comb.store = delayed * (1.0 - damp) + comb.store * damp;
+					comb.buffer[comb.cursor] = input + comb.store * feedback;
+					comb.cursor = (comb.cursor + 1) % comb.buffer.len();
+					output += delayed;
+				}
+				for allpass in self.allpasses[channel].iter_mut(){
+					let delayed = allpass.buffer[allpass.cursor];
+					allpass.buffer[allpass.cursor] = output + delayed * 0.5;
+					allpass.cursor = (allpass.cursor + 1) % allpass.buffer.len();
+					output = delayed - output;
+				}
+
+				*sample = *sample * self.dry + output * wet;
+			}
+		}
+	}
+
+	fn reset(&mut self){
+		for comb in self.combs.iter_mut().flat_map(|combs| combs.iter_mut()){
+			for sample in comb.buffer.iter_mut(){ *sample = 0.0 }
+			comb.store = 0.0;
+		}
+		for allpass in self.allpasses.iter_mut().flat_map(|allpasses| allpasses.iter_mut()){
+			for sample in allpass.buffer.iter_mut(){ *sample = 0.0 }
+		}
+	}
+
+	fn is_ringing(&self) -> bool{
+		// Every comb adds into the allpasses, which can at most double what goes through them
+		let combs = self.combs.iter().flat_map(|combs| combs.iter())
+			.fold(0.0f32, |loudest, comb| loudest.max(peak(&comb.buffer)).max(comb.store.abs()));
+		let allpasses = self.allpasses.iter().flat_map(|allpasses| allpasses.iter())
+			.fold(0.0f32, |loudest, allpass| loudest.max(peak(&allpass.buffer)));
+		(combs * REVERB_COMBS.len() as f32 + allpasses) * 2.0 * self.wet.abs() * 3.0 >= SILENCE
+	}
+}
+
+/// Lowers both the bit depth and the sample rate of the signal, for that crunchy sound
+pub struct Bitcrusher{
+	bits: u32,         /* Bits of resolution kept, out of 16                    */
+	downsample: usize, /* Every sample is held for this many frames             */
+
+	held: Vec<f32>,
+	counter: usize
+}
+impl Bitcrusher{
+	pub fn new(bits: u32, downsample: usize) -> Bitcrusher{
+		Bitcrusher{
+			bits: if bits < 1 { 1 } else if bits > 16 { 16 } else { bits },
+			downsample: if downsample < 1 { 1 } else { downsample },
+
+			held: Vec::new(),
+			counter: 0
+		}
+	}
+}
+impl Effect for Bitcrusher{
+	fn process(&mut self, samples: &mut [f32], format: Format){
+		let channels = format.channels();
+		if self.held.len() != channels { self.held = vec![0.0; channels]; self.counter = 0 }
+
+		let step = (1 << (16 - self.bits)) as f32;
+		for frame in samples.chunks_mut(channels){
+			if self.counter == 0{
+				for (held, sample) in self.held.iter_mut().zip(frame.iter()){ *held = (*sample / step).floor() * step }
+			}
+			self.counter = (self.counter + 1) % self.downsample;
+
+			for (sample, held) in frame.iter_mut().zip(&self.held){ *sample = *held }
+		}
+	}
+
+	fn reset(&mut self){ self.counter = 0 }
+}
+
+/// Keeps the signal's peaks under a threshold, reacting instantly and recovering smoothly
+pub struct Limiter{
+	threshold: f32, /* Highest peak let through, from 0.0 to 1.0 of full scale */
+	release:   f32, /* Seconds it takes for the gain to recover                */
+
+	envelope: f32
+}
+impl Limiter{
+	pub fn new(threshold: f32, release: f32) -> Limiter{
+		Limiter{
+			threshold: threshold,
+			release:   release,
+
+			envelope: 0.0
+		}
+	}
+}
+impl Effect for Limiter{
+	fn process(&mut self, samples: &mut [f32], format: Format){
+		let threshold = self.threshold * 32768.0;
+		let release = if self.release > 0.0 { (-1.0 / (self.release * format.samplerate() as f32)).exp() } else { 0.0 };
+
+		for frame in samples.chunks_mut(format.channels()){
+			let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+			self.envelope = if peak > self.envelope { peak } else { self.envelope * release + peak * (1.0 - release) };
+
+			if self.envelope > threshold{
+				let gain = threshold / self.envelope;
+				for sample in frame.iter_mut(){ *sample *= gain }
+			}
+		}
+	}
+
+	fn reset(&mut self){ self.envelope = 0.0 }
+}
+
+/// Runs interleaved `i16` samples through an effect, saturating the result
+pub fn apply(data: &[i16], format: Format, effect: &mut Effect) -> Vec<i16>{
+	let mut samples = data.iter().map(|s| *s as f32).collect::<Vec<f32>>();
+	effect.process(&mut samples, format);
+
+	samples.iter().map(|s| {
+		if *s >= 32767.0 { 32767 } else if *s <= -32768.0 { -32768 } else { s.round() as i16 }
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests{
+	use super::{Effect, Biquad, Delay, Reverb, Bitcrusher, Limiter};
+	use super::super::{Sound, Format};
+
+	/* A second of a sine wave at the given frequency */
+	fn tone(frequency: f32) -> Sound{
+		use std::f32::consts::PI;
+		Sound::from_samples(Format::new(1, 44100), (0..44100)
+			.map(|i| ((2.0 * PI * frequency * i as f32 / 44100.0).sin() * 16000.0) as i16)
+			.collect())
+	}
+
+	fn rms(data: &[i16]) -> f32{
+		(data.iter().fold(0.0, |acc, s| acc + (*s as f32) * (*s as f32)) / data.len() as f32).sqrt()
+	}
+
+	#[test]
+	fn filters(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let (low, high) = (tone(100.0), tone(8000.0));
+		let ratio = |sound: &Sound, effect: &mut Effect| rms(&sound.process(effect).data()[4410..]) / rms(&sound.data()[4410..]);
+
+		assert!(ratio(&low,  &mut Biquad::low_pass(500.0, 0.707)) > 0.9);
+		assert!(ratio(&high, &mut Biquad::low_pass(500.0, 0.707)) < 0.05);
+
+		assert!(ratio(&low,  &mut Biquad::high_pass(2000.0, 0.707)) < 0.05);
+		assert!(ratio(&high, &mut Biquad::high_pass(2000.0, 0.707)) > 0.9);
+
+		assert!(ratio(&tone(1000.0), &mut Biquad::band_pass(1000.0, 2.0)) > 0.9);
+		assert!(ratio(&high,         &mut Biquad::band_pass(1000.0, 2.0)) < 0.2);
+	}
+
+	#[test]
+	fn echo(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut impulse = vec![0i16; 10];
+		impulse[0] = 1000;
+		let sound = Sound::from_samples(Format::new(1, 100), impulse);
+
+		let echoed = sound.process(&mut Delay::new(0.03, 0.5, 0.5));
+		assert_eq!(echoed.data(), &[1000, 0, 0, 500, 0, 0, 250, 0, 0, 125]);
+	}
+
+	#[test]
+	fn reverb(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut impulse = vec![0i16; 44100];
+		impulse[0] = 16000;
+		let sound = Sound::from_samples(Format::new(2, 44100), impulse);
+
+		let mut reverb = Reverb::new(0.8, 0.5, 0.3);
+		let reverberated = sound.process(&mut reverb);
+		assert_eq!(reverberated.data()[0], 16000);
+
+		// There's a tail after the impulse, that dies down over time
+		let early = rms(&reverberated.data()[4410..8820]);
+		let late  = rms(&reverberated.data()[39690..]);
+		assert!(early > 10.0);
+		assert!(late < early);
+
+		// Still ringing after a second, but not once the tail is dropped
+		assert!(reverb.is_ringing());
+		reverb.reset();
+		assert!(!reverb.is_ringing());
+	}
+
+	#[test]
+	fn bitcrusher(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Sound::from_samples(Format::new(1, 100), vec![1000, 2000, 3000, -1000, -5000]);
+		let crushed = sound.process(&mut Bitcrusher::new(6, 2));
+		assert_eq!(crushed.data(), &[0, 0, 2048, 2048, -5120]);
+	}
+
+	#[test]
+	fn limiter(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = tone(440.0);
+		let loud = Sound::from_samples(sound.format(), sound.data().iter().map(|s| s.saturating_mul(2)).collect());
+
+		let limited = loud.process(&mut Limiter::new(0.5, 0.1));
+		assert!(limited.data().iter().all(|s| (*s as i32).abs() <= 16384));
+		assert!(rms(limited.data()) > rms(sound.data()) * 0.9);
+	}
+}
@@ -0,0 +1,1087 @@
+use std::rc::{Rc, Weak};
+
+use super::{Format, Sound, Music};
+use super::convert;
+use super::dsp::Effect;
+
+/// Number of sample frames pulled from a music stream at a time
+const STREAM_FRAMES: usize = 1024;
+
+/// Identifies a voice playing in a `Mixer`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct VoiceHandle(usize);
+
+enum Source{
+	Sound(Rc<Sound>),
+	Music{
+		music:  Music,
+		buffer: Vec<i16>, /* Frames pulled from the stream but not yet played */
+		start:  usize     /* Frame index of the first frame in the buffer      */
+	}
+}
+impl Source{
+	fn format(&self) -> Format{
+		match *self{
+			Source::Sound(ref sound) => sound.format(),
+			Source::Music{ ref music, .. } => music.format()
+		}
+	}
+
+	/* Writes every channel of a frame into `out`, returning false if the frame is past the end */
+	fn fetch(&mut self, frame: usize, out: &mut [f32]) -> bool{
+		match *self{
+			Source::Sound(ref sound) => {
+				let channels = sound.format().channels();
+				let data = sound.data();
+				if (frame + 1) * channels > data.len() { return false }
+
+				for (o, s) in out.iter_mut().zip(&data[frame * channels..(frame + 1) * channels]){ *o = *s as f32 }
+				true
+			},
+			Source::Music{ ref mut music, ref mut buffer, ref mut start } => {
+				let channels = music.format().channels();
+				if frame < *start{
+					/* Going backwards, when looping, means the stream has to be moved */
+					if music.seek(frame).is_err() { return false }
+					buffer.clear();
+					*start = frame;
+				}
+
+				while frame >= *start + buffer.len() / channels{
+					// Throw away frames that have already been played
+					let played = ::std::cmp::min(frame - *start, buffer.len() / channels);
+					buffer.drain(..played * channels);
+					*start += played;
+
+					let length = buffer.len();
+					buffer.resize(length + STREAM_FRAMES * channels, 0);
+					let pulled = music.fill(&mut buffer[length..]);
+					buffer.truncate(length + pulled);
+
+					if pulled == 0 { return false }
+				}
+
+				let index = (frame - *start) * channels;
+				for (o, s) in out.iter_mut().zip(&buffer[index..index + channels]){ *o = *s as f32 }
+				true
+			}
+		}
+	}
+
+}
+
+struct Voice{
+	id: usize,
+	source: Source,
+	origin:   Option<Weak<Sound>>, /* Sound the voice was started from, before any conversion */
+	priority: i32,
+
+	volume:  f32,  /* Linear gain                                       */
+	gain:    f32,  /* Linear gain on top of the volume, left alone by fades */
+	pan:     f32,  /* Stereo position, from -1.0 (left) to 1.0 (right) */
+	pitch:   f32,  /* Playback speed multiplier                         */
+	looping: bool,
+	paused:  bool,
+
+	loop_start: usize,         /* Frame playback jumps back to when looping            */
+	loop_end:   Option<usize>, /* Frame that triggers the jump, or the end of the source */
+	fade: Option<Fade>,
+	effects: Vec<Box<Effect>>, /* Inserts applied to this voice alone */
+
+	position: f64, /* Current frame in the source, fractional when pitched */
+	finished: bool,
+	level:    f32, /* Peak the voice reached in the last render, infinite until it's rendered */
+
+	bus: Option<usize> /* Bus the voice is routed through, or straight into the master mix */
+}
+impl Voice{
+	/* Whether the voice was started from the given sound, which has to be alive to tell */
+	fn plays(&self, sound: *const Sound) -> bool{
+		match self.origin.as_ref().and_then(|origin| origin.upgrade()){
+			Some(original) => &*original as *const Sound == sound,
+			None => false
+		}
+	}
+}
+
+/* A volume ramp, moving a fixed step towards its target every frame */
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Fade{
+	target: f32,
+	step:   f32,
+	stop:   bool /* Whether the voice is stopped once the target is reached */
+}
+
+/// How a mixer picks the voice to cut when there's no room for a new one
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Stealing{
+	/// The voice that has been playing for the longest
+	Oldest,
+	/// The voice that peaked the lowest during the last render
+	Quietest,
+	/// The voice with the lowest priority, the oldest of them if there's a tie
+	LowestPriority
+}
+
+/// Restrictions on how a sound is played, set on a mixer for each sound
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SoundLimits{
+	pub priority:      i32,           /* Voices only steal voices of equal or lower priority        */
+	pub max_instances: Option<usize>, /* Voices of this sound that can be playing at once          */
+	pub cooldown:      f32            /* Seconds during which triggering the sound again is ignored */
+}
+impl Default for SoundLimits{
+	fn default() -> SoundLimits{
+		SoundLimits{
+			priority:      0,
+			max_instances: None,
+			cooldown:      0.0
+		}
+	}
+}
+
+/* Limits and trigger time of a sound, for as long as it's alive */
+struct Trigger{
+	sound:  Weak<Sound>,
+	limits: Option<SoundLimits>, /* Own limits, or the mixer's defaults */
+	last:   Option<usize>        /* Mixer clock when the sound last started a voice */
+}
+
+/// Names of the buses every mixer starts out with
+pub const MUSIC_BUS: &'static str = "music";
+pub const SFX_BUS:   &'static str = "sfx";
+pub const VOICE_BUS: &'static str = "voice";
+pub const UI_BUS:    &'static str = "ui";
+
+/// Brings a bus down while another bus, the sidechain, is playing something
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ducking{
+	pub sidechain: String, /* Name of the bus that triggers the ducking                        */
+	pub gain:      f32,    /* Gain the bus is brought down to while ducked                     */
+	pub threshold: f32,    /* Sidechain peak, from 0.0 to 1.0, above which the bus gets ducked */
+	pub attack:    f32,    /* Seconds it takes to go down to the ducked gain                   */
+	pub release:   f32     /* Seconds it takes to come back up once the sidechain goes quiet   */
+}
+impl Ducking{
+	pub fn new(sidechain: &str, gain: f32) -> Ducking{
+		Ducking{
+			sidechain: sidechain.to_owned(),
+			gain:      gain,
+			threshold: 0.001,
+			attack:    0.05,
+			release:   0.5
+		}
+	}
+}
+
+/// A group of voices sharing volume, mute and solo controls
+pub struct Bus{
+	name: String,
+	volume: f32,
+	muted:  bool,
+	solo:   bool,
+
+	ducking:  Option<Ducking>,
+	envelope: f32,    /* Current ducking gain, eased towards its target */
+	effects:  Vec<Box<Effect>>,
+
+	mix: Vec<f32> /* Voices routed through this bus, before any bus gain */
+}
+impl Bus{
+	fn new(name: &str) -> Bus{
+		Bus{
+			name:   name.to_owned(),
+			volume: 1.0,
+			muted:  false,
+			solo:   false,
+
+			ducking:  None,
+			envelope: 1.0,
+			effects:  Vec::new(),
+
+			mix: Vec::new()
+		}
+	}
+
+	pub fn name(&self) -> &str{ &self.name }
+
+	pub fn volume(&self) -> f32{ self.volume }
+	pub fn set_volume(&mut self, volume: f32){ self.volume = volume }
+
+	pub fn is_muted(&self) -> bool{ self.muted }
+	pub fn set_muted(&mut self, muted: bool){ self.muted = muted }
+
+	/// While any bus is soloed, only soloed buses are heard
+	pub fn is_solo(&self) -> bool{ self.solo }
+	pub fn set_solo(&mut self, solo: bool){ self.solo = solo }
+
+	pub fn ducking(&self) -> Option<&Ducking>{ self.ducking.as_ref() }
+	pub fn set_ducking(&mut self, ducking: Option<Ducking>){
+		if ducking.is_none() { self.envelope = 1.0 }
+		self.ducking = ducking;
+	}
+
+	/// Effects applied, in order, to everything routed through the bus
+	pub fn effects_mut(&mut self) -> &mut Vec<Box<Effect>>{ &mut self.effects }
+	pub fn add_effect(&mut self, effect: Box<Effect>){ self.effects.push(effect) }
+}
+
+/// Plays many sounds at once, rendering them into a single interleaved stream
+pub struct Mixer{
+	format: Format,
+	volume: f32,
+
+	voices:  Vec<Voice>,
+	next_id: usize,
+	buses:   Vec<Bus>,
+	effects: Vec<Box<Effect>>, /* Inserts on the master mix */
+
+	max_voices: Option<usize>,
+	stealing:   Stealing,
+	defaults:   SoundLimits,  /* Limits of sounds that don't have their own */
+	triggers:   Vec<Trigger>,
+	clock:      usize,        /* Frames rendered so far */
+
+	conversions: Vec<(Weak<Sound>, Rc<Sound>)>, /* Sounds already converted to the mixer's format */
+	mix:    Vec<f32>, /* Accumulation buffer, reused between renders              */
+	insert: Vec<f32>  /* Scratch buffer for voices that have effects of their own */
+}
+impl Mixer{
+	pub fn new(format: Format) -> Mixer{
+		Mixer{
+			format: format,
+			volume: 1.0,
+
+			voices:  Vec::new(),
+			next_id: 0,
+			buses:   vec![Bus::new(MUSIC_BUS), Bus::new(SFX_BUS), Bus::new(VOICE_BUS), Bus::new(UI_BUS)],
+			effects: Vec::new(),
+
+			max_voices: None,
+			stealing:   Stealing::Oldest,
+			defaults:   SoundLimits::default(),
+			triggers:   Vec::new(),
+			clock:      0,
+
+			conversions: Vec::new(),
+			mix:    Vec::new(),
+			insert: Vec::new()
+		}
+	}
+
+	pub fn format(&self) -> Format{ self.format }
+
+	pub fn volume(&self) -> f32{ self.volume }
+	pub fn set_volume(&mut self, volume: f32){ self.volume = volume }
+
+	/// Effects applied, in order, to the master mix before its volume
+	pub fn effects_mut(&mut self) -> &mut Vec<Box<Effect>>{ &mut self.effects }
+	pub fn add_effect(&mut self, effect: Box<Effect>){ self.effects.push(effect) }
+
+	/// Most voices that can be playing at once, counting paused ones, or None for no limit
+	pub fn max_voices(&self) -> Option<usize>{ self.max_voices }
+	pub fn set_max_voices(&mut self, max: Option<usize>){ self.max_voices = max }
+
+	/// Policy used to make room when a voice or a sound is over its limit
+	pub fn stealing(&self) -> Stealing{ self.stealing }
+	pub fn set_stealing(&mut self, stealing: Stealing){ self.stealing = stealing }
+
+	/// Limits of every sound that wasn't given its own
+	pub fn default_limits(&self) -> SoundLimits{ self.defaults }
+	pub fn set_default_limits(&mut self, limits: SoundLimits){ self.defaults = limits }
+
+	/// Limits a sound is played with
+	pub fn limits(&self, sound: &Rc<Sound>) -> SoundLimits{
+		match self.trigger(&**sound){
+			Some(index) => self.triggers[index].limits.unwrap_or(self.defaults),
+			None => self.defaults
+		}
+	}
+	pub fn set_limits(&mut self, sound: &Rc<Sound>, limits: SoundLimits){
+		let index = self.trigger_mut(sound);
+		self.triggers[index].limits = Some(limits);
+	}
+
+	fn trigger(&self, sound: *const Sound) -> Option<usize>{
+		self.triggers.iter().position(|trigger| match trigger.sound.upgrade(){
+			Some(original) => &*original as *const Sound == sound,
+			None => false
+		})
+	}
+
+	/* Finds the trigger of a sound, creating one if needed */
+	fn trigger_mut(&mut self, sound: &Rc<Sound>) -> usize{
+		// Forget about sounds that no longer exist
+		self.triggers.retain(|trigger| trigger.sound.upgrade().is_some());
+
+		match self.trigger(&**sound){
+			Some(index) => index,
+			None => {
+				self.triggers.push(Trigger{ sound: Rc::downgrade(sound), limits: None, last: None });
+				self.triggers.len() - 1
+			}
+		}
+	}
+
+	/**
+	 * Starts playing a sound, returning a handle that controls its voice.
+	 *
+	 * Sounds in a different format than the mixer's are converted the first time
+	 * they're played, and the conversion is kept around for as long as the
+	 * original sound is alive.
+	 *
+	 * If the sound is still cooling down, or there's no room for it and no voice it's
+	 * allowed to steal, nothing is played and the handle refers to a stopped voice.
+	 */
+	pub fn play(&mut self, sound: Rc<Sound>) -> VoiceHandle{
+		let origin = &*sound as *const Sound;
+		let limits = self.limits(&sound);
+
+		let cooldown = (limits.cooldown * self.format.samplerate() as f32) as usize;
+		let index = self.trigger_mut(&sound);
+		if let Some(last) = self.triggers[index].last{
+			if self.clock - last < cooldown{
+				debug!("Ignoring a sound triggered again while cooling down");
+				return self.reject()
+			}
+		}
+
+		if let Some(max) = limits.max_instances{
+			while self.voices.iter().filter(|voice| voice.plays(origin)).count() >= max{
+				if !self.steal(Some(origin), limits.priority) {
+					debug!("Ignoring a sound that is already playing {} times", max);
+					return self.reject()
+				}
+			}
+		}
+		if !self.make_room(limits.priority) { return self.reject() }
+		self.triggers[index].last = Some(self.clock);
+
+		let origin = Rc::downgrade(&sound);
+		let sound = self.conform(sound);
+		self.add(Source::Sound(sound), Some(origin), limits.priority)
+	}
+
+	/* Steals voices until there's room for one more, returning false if it couldn't */
+	fn make_room(&mut self, priority: i32) -> bool{
+		if let Some(max) = self.max_voices{
+			while self.voices.len() >= max{
+				if !self.steal(None, priority) {
+					debug!("Ignoring a voice, all {} voices are taken by more important ones", max);
+					return false
+				}
+			}
+		}
+		true
+	}
+
+	/* Stops a voice no more important than `priority`, out of those playing `origin` if given */
+	fn steal(&mut self, origin: Option<*const Sound>, priority: i32) -> bool{
+		let stealing = self.stealing;
+		let victim = self.voices.iter().enumerate()
+			.filter(|&(_, voice)| voice.priority <= priority && origin.map(|origin| voice.plays(origin)).unwrap_or(true))
+			.fold(None, |best: Option<(usize, &Voice)>, (index, voice)| {
+				let better = match best{
+					None => true,
+					Some((_, best)) => match stealing{
+						Stealing::Oldest         => voice.id < best.id,
+						Stealing::Quietest       => voice.level < best.level || (voice.level == best.level && voice.id < best.id),
+						Stealing::LowestPriority => voice.priority < best.priority || (voice.priority == best.priority && voice.id < best.id)
+					}
+				};
+				if better { Some((index, voice)) } else { best }
+			})
+			.map(|(index, _)| index);
+
+		match victim{
+			Some(index) => { self.voices.remove(index); true },
+			None => false
+		}
+	}
+
+	/* A handle to a voice that never started */
+	fn reject(&mut self) -> VoiceHandle{
+		self.next_id += 1;
+		VoiceHandle(self.next_id - 1)
+	}
+
+	fn conform(&mut self, sound: Rc<Sound>) -> Rc<Sound>{
+		if sound.format() == self.format { return sound }
+
+		// Forget about conversions of sounds that no longer exist
+		self.conversions.retain(|&(ref original, _)| original.upgrade().is_some());
+
+		let pointer = &*sound as *const Sound;
+		for &(ref original, ref converted) in &self.conversions{
+			if let Some(original) = original.upgrade(){
+				if &*original as *const Sound == pointer { return converted.clone() }
+			}
+		}
+
+		debug!("Converting sound from {:?} to {:?}", sound.format(), self.format);
+		let converted = Rc::new(sound.convert(self.format));
+		self.conversions.push((Rc::downgrade(&sound), converted.clone()));
+		converted
+	}
+
+	/**
+	 * Starts streaming music, returning a handle that controls its voice.
+	 *
+	 * Music starts out with the highest priority, since cutting a song off is far more
+	 * noticeable than losing a sound effect, but it still takes up a voice.
+	 */
+	pub fn play_music(&mut self, music: Music) -> VoiceHandle{
+		if !self.make_room(::std::i32::MAX) { return self.reject() }
+		self.add(Source::Music{
+			music:  music,
+			buffer: Vec::new(),
+			start:  0
+		}, None, ::std::i32::MAX)
+	}
+
+	fn add(&mut self, source: Source, origin: Option<Weak<Sound>>, priority: i32) -> VoiceHandle{
+		let id = self.next_id;
+		self.next_id += 1;
+
+		self.voices.push(Voice{
+			id: id,
+			source: source,
+			origin:   origin,
+			priority: priority,
+
+			volume:  1.0,
+			gain:    1.0,
+			pan:     0.0,
+			pitch:   1.0,
+			looping: false,
+			paused:  false,
+
+			loop_start: 0,
+			loop_end:   None,
+			fade: None,
+			effects: Vec::new(),
+
+			position: 0.0,
+			finished: false,
+			level:    ::std::f32::INFINITY,
+
+			bus: None
+		});
+		VoiceHandle(id)
+	}
+
+	fn voice_mut(&mut self, handle: VoiceHandle) -> Option<&mut Voice>{
+		self.voices.iter_mut().find(|voice| voice.id == handle.0)
+	}
+
+	/// Creates a new bus, unless one with the same name already exists
+	pub fn add_bus(&mut self, name: &str){
+		if self.bus(name).is_none() { self.buses.push(Bus::new(name)) }
+	}
+
+	pub fn bus(&self, name: &str) -> Option<&Bus>{ self.buses.iter().find(|bus| bus.name == name) }
+	pub fn bus_mut(&mut self, name: &str) -> Option<&mut Bus>{ self.buses.iter_mut().find(|bus| bus.name == name) }
+	pub fn buses(&self) -> &[Bus]{ &self.buses[..] }
+
+	/// Routes a voice through a bus, returning false if there's no bus with that name
+	pub fn route(&mut self, handle: VoiceHandle, bus: &str) -> bool{
+		let index = match self.buses.iter().position(|b| b.name == bus){
+			Some(index) => index,
+			None => {
+				warn!(r#"Tried to route a voice through inexistent bus "{}""#, bus);
+				return false
+			}
+		};
+
+		if let Some(voice) = self.voice_mut(handle){ voice.bus = Some(index) }
+		true
+	}
+
+	/// Whether the voice is still around, be it paused or not, or still ringing out through its inserts
+	pub fn is_playing(&self, handle: VoiceHandle) -> bool{
+		self.voices.iter().any(|voice| voice.id == handle.0)
+	}
+
+	/// Number of voices currently in the mixer
+	pub fn voices(&self) -> usize{ self.voices.len() }
+
+	pub fn stop(&mut self, handle: VoiceHandle){
+		self.voices.retain(|voice| voice.id != handle.0);
+	}
+	pub fn stop_all(&mut self){ self.voices.clear() }
+
+	pub fn pause(&mut self, handle: VoiceHandle) { if let Some(voice) = self.voice_mut(handle){ voice.paused = true  } }
+	pub fn resume(&mut self, handle: VoiceHandle){ if let Some(voice) = self.voice_mut(handle){ voice.paused = false } }
+
+	pub fn set_voice_volume(&mut self, handle: VoiceHandle, volume: f32){
+		if let Some(voice) = self.voice_mut(handle){ voice.volume = volume; voice.fade = None }
+	}
+	/// Scales a voice on top of its volume, without stopping any fade it's going through
+	pub fn set_voice_gain(&mut self, handle: VoiceHandle, gain: f32){
+		if let Some(voice) = self.voice_mut(handle){ voice.gain = gain }
+	}
+	pub fn set_pan(&mut self, handle: VoiceHandle, pan: f32){
+		if let Some(voice) = self.voice_mut(handle){ voice.pan = if pan < -1.0 { -1.0 } else if pan > 1.0 { 1.0 } else { pan } }
+	}
+	/// Changes how important a voice is when voices get stolen
+	pub fn set_priority(&mut self, handle: VoiceHandle, priority: i32){
+		if let Some(voice) = self.voice_mut(handle){ voice.priority = priority }
+	}
+	pub fn set_pitch(&mut self, handle: VoiceHandle, pitch: f32){
+		if let Some(voice) = self.voice_mut(handle){ voice.pitch = if pitch < 0.0 { 0.0 } else { pitch } }
+	}
+	pub fn set_looping(&mut self, handle: VoiceHandle, looping: bool){
+		if let Some(voice) = self.voice_mut(handle){ voice.looping = looping }
+	}
+
+	/**
+	 * Sets the region a looping voice repeats, in source frames. Playing starts from the
+	 * beginning of the source regardless, so anything before `start` works as an intro.
+	 */
+	pub fn set_loop_region(&mut self, handle: VoiceHandle, start: usize, end: Option<usize>){
+		if let Some(voice) = self.voice_mut(handle){
+			voice.loop_start = start;
+			voice.loop_end   = end;
+		}
+	}
+
+	/// Moves the volume of a voice to a new value, linearly over some seconds
+	pub fn fade(&mut self, handle: VoiceHandle, volume: f32, seconds: f32){
+		let frames = seconds * self.format.samplerate() as f32;
+		if let Some(voice) = self.voice_mut(handle){
+			voice.fade = Some(Fade{
+				target: volume,
+				step:   if frames >= 1.0 { (volume - voice.volume).abs() / frames } else { ::std::f32::INFINITY },
+				stop:   false
+			});
+		}
+	}
+
+	/// Effects applied, in order, to a single voice before it reaches its bus
+	pub fn voice_effects_mut(&mut self, handle: VoiceHandle) -> Option<&mut Vec<Box<Effect>>>{
+		self.voice_mut(handle).map(|voice| &mut voice.effects)
+	}
+	pub fn add_voice_effect(&mut self, handle: VoiceHandle, effect: Box<Effect>){
+		if let Some(voice) = self.voice_mut(handle){ voice.effects.push(effect) }
+	}
+
+	/// Fades a voice into silence, stopping it afterwards
+	pub fn fade_out(&mut self, handle: VoiceHandle, seconds: f32){
+		self.fade(handle, 0.0, seconds);
+		if let Some(voice) = self.voice_mut(handle){
+			if let Some(ref mut fade) = voice.fade { fade.stop = true }
+		}
+	}
+
+	/**
+	 * Mixes every playing voice into `output`, an interleaved buffer in the mixer's format.
+	 *
+	 * Voices are summed at full precision and only clamped into the `i16` range at
+	 * the very end, so loud overlapping sounds saturate instead of wrapping around.
+	 */
+	pub fn render(&mut self, output: &mut [i16]){
+		let channels = self.format.channels();
+		let frames = output.len() / channels;
+
+		self.mix.clear();
+		self.mix.resize(frames * channels, 0.0);
+		for bus in self.buses.iter_mut(){
+			bus.mix.clear();
+			bus.mix.resize(frames * channels, 0.0);
+		}
+
+		for voice in self.voices.iter_mut().filter(|voice| !voice.paused){
+			let mix = match voice.bus{
+				Some(bus) => &mut self.buses[bus].mix[..],
+				None      => &mut self.mix[..]
+			};
+			if voice.effects.is_empty() { mix_voice(voice, self.format, mix); continue }
+
+			// Voices with inserts are rendered on their own, so the effects only touch them
+			self.insert.clear();
+			self.insert.resize(frames * channels, 0.0);
+			if !voice.finished { mix_voice(voice, self.format, &mut self.insert) }
+			for effect in voice.effects.iter_mut(){ effect.process(&mut self.insert, self.format) }
+			for (m, s) in mix.iter_mut().zip(&self.insert){ *m += *s }
+		}
+		// Voices that are done stick around until the echoes and tails of their inserts die out
+		self.voices.retain(|voice| !voice.finished || voice.effects.iter().any(|effect| effect.is_ringing()));
+		self.mix_buses();
+		for effect in self.effects.iter_mut(){ effect.process(&mut self.mix, self.format) }
+		self.clock += frames;
+
+		for (o, s) in output.iter_mut().zip(&self.mix){
+			*o = saturate(*s * self.volume);
+		}
+		for o in output[frames * channels..].iter_mut(){ *o = 0 }
+	}
+
+	/* Applies the gain of every bus and adds them into the master mix */
+	fn mix_buses(&mut self){
+		let channels = self.format.channels();
+		let rate = self.format.samplerate() as f32;
+		let soloing = self.buses.iter().any(|bus| bus.solo);
+
+		for bus in self.buses.iter_mut(){
+			for effect in bus.effects.iter_mut(){ effect.process(&mut bus.mix, self.format) }
+		}
+
+		// Sidechains are triggered by the signal of a bus after its effects and mute, but before its volume
+		let peaks = self.buses.iter()
+			.map(|bus| if bus.muted || (soloing && !bus.solo) { 0.0 } else { bus.mix.iter().fold(0.0f32, |peak, s| peak.max(s.abs())) / 32768.0 })
+			.collect::<Vec<f32>>();
+		let triggers = self.buses.iter()
+			.map(|bus| bus.ducking.as_ref().map(|ducking| {
+				match self.buses.iter().position(|b| b.name == ducking.sidechain){
+					Some(sidechain) => peaks[sidechain] > ducking.threshold,
+					None => false
+				}
+			}))
+			.collect::<Vec<Option<bool>>>();
+
+		for (bus, trigger) in self.buses.iter_mut().zip(triggers){
+			let gain = if bus.muted || (soloing && !bus.solo) { 0.0 } else { bus.volume };
+
+			// Ease the ducking envelope towards its target one frame at a time
+			let (target, coefficient) = match (bus.ducking.as_ref(), trigger){
+				(Some(ducking), Some(triggered)) => {
+					let target = if triggered { ducking.gain } else { 1.0 };
+					let time = if target < bus.envelope { ducking.attack } else { ducking.release };
+					(target, if time > 0.0 { 1.0 - (-1.0 / (time * rate)).exp() } else { 1.0 })
+				},
+				_ => (1.0, 1.0)
+			};
+
+			for (frame, mixed) in bus.mix.chunks(channels).zip(self.mix.chunks_mut(channels)){
+				bus.envelope += (target - bus.envelope) * coefficient;
+				for (m, s) in mixed.iter_mut().zip(frame){ *m += *s * gain * bus.envelope }
+			}
+		}
+	}
+}
+
+/* Renders a voice on top of what's already in `mix` */
+fn mix_voice(voice: &mut Voice, format: Format, mix: &mut [f32]){
+	let source_format = voice.source.format();
+	let step = voice.pitch as f64 * source_format.samplerate() as f64 / format.samplerate() as f64;
+
+	let mut a = vec![0.0f32; source_format.channels()];
+	let mut b = vec![0.0f32; source_format.channels()];
+	let mut remapped = vec![0.0f32; format.channels()];
+	voice.level = 0.0;
+	for frame in mix.chunks_mut(format.channels()){
+		if let Some(fade) = voice.fade{
+			if (fade.target - voice.volume).abs() <= fade.step{
+				voice.volume = fade.target;
+				voice.fade = None;
+				if fade.stop { voice.finished = true; return }
+			}else if fade.target > voice.volume { voice.volume += fade.step }
+			else { voice.volume -= fade.step }
+		}
+
+		// Jump back once the end of the loop region is reached
+		if let (true, Some(end)) = (voice.looping, voice.loop_end){
+			if end > voice.loop_start && voice.position >= end as f64{
+				voice.position -= (end - voice.loop_start) as f64;
+			}
+		}
+
+		let index = voice.position.floor() as usize;
+		if !voice.source.fetch(index, &mut a){
+			// Loop back only if at least one frame of the loop was played, otherwise give up
+			let looped = voice.looping && index > voice.loop_start;
+			if looped { voice.position -= (index - voice.loop_start) as f64 }
+
+			if !looped || !voice.source.fetch(voice.position.floor() as usize, &mut a){
+				voice.finished = true;
+				return
+			}
+		}
+		let index = voice.position.floor() as usize;
+		let fract = (voice.position - index as f64) as f32;
+
+		// Interpolate towards the next frame, if there's one
+		if fract > 0.0 && voice.source.fetch(index + 1, &mut b){
+			for (a, b) in a.iter_mut().zip(&b){ *a += (*b - *a) * fract }
+		}
+
+		// Per channel gains, with a balance law that keeps centered sounds at unity
+		let volume = voice.volume * voice.gain;
+		let left  = volume * if voice.pan > 0.0 { 1.0 - voice.pan } else { 1.0 };
+		let right = volume * if voice.pan < 0.0 { 1.0 + voice.pan } else { 1.0 };
+
+		convert::remap_frame(&a, &mut remapped);
+		if frame.len() == 1{
+			frame[0] += remapped[0] * (left + right) / 2.0;
+		}else{
+			frame[0] += remapped[0] * left;
+			frame[1] += remapped[1] * right;
+			for (sample, channel) in frame.iter_mut().zip(&remapped).skip(2){ *sample += *channel * volume }
+		}
+		for channel in &remapped{ voice.level = voice.level.max(channel.abs() * volume) }
+
+		voice.position += step;
+	}
+}
+
+/* Converts a mixed sample back into the i16 range, saturating instead of wrapping */
+fn saturate(sample: f32) -> i16{
+	if sample >= 32767.0 { 32767 } else if sample <= -32768.0 { -32768 } else { sample.round() as i16 }
+}
+
+#[cfg(test)]
+mod tests{
+	use std::rc::Rc;
+	use super::{Mixer, Ducking, Stealing, SoundLimits, MUSIC_BUS, SFX_BUS, VOICE_BUS};
+	use super::super::{Sound, Format, Bitcrusher, Delay};
+
+	#[test]
+	fn mix(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let format = Format::new(2, 100);
+		let a = Rc::new(Sound::from_samples(Format::new(1, 100), vec![100, 200, 300]));
+		let b = Rc::new(Sound::from_samples(Format::new(2, 100), vec![1, 2, 3, 4]));
+
+		let mut mixer = Mixer::new(format);
+		mixer.play(a.clone());
+		let handle = mixer.play(b.clone());
+
+		let mut output = [0i16; 8];
+		mixer.render(&mut output);
+		assert_eq!(output, [101, 102, 203, 204, 300, 300, 0, 0]);
+		assert!(!mixer.is_playing(handle));
+		assert_eq!(mixer.voices(), 0);
+
+		// Hard left pan, half volume
+		let handle = mixer.play(a.clone());
+		mixer.set_pan(handle, -1.0);
+		mixer.set_voice_volume(handle, 0.5);
+		mixer.render(&mut output);
+		assert_eq!(output, [50, 0, 100, 0, 150, 0, 0, 0]);
+	}
+
+	#[test]
+	fn pitch_and_loop(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![0, 10, 20, 30]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+
+		let handle = mixer.play(sound.clone());
+		mixer.set_pitch(handle, 0.5);
+		let mut output = [0i16; 4];
+		mixer.render(&mut output);
+		assert_eq!(output, [0, 5, 10, 15]);
+		mixer.stop(handle);
+
+		let handle = mixer.play(sound.clone());
+		mixer.set_looping(handle, true);
+		let mut output = [0i16; 10];
+		mixer.render(&mut output);
+		assert_eq!(output, [0, 10, 20, 30, 0, 10, 20, 30, 0, 10]);
+
+		mixer.pause(handle);
+		mixer.render(&mut output);
+		assert_eq!(output, [0; 10]);
+		mixer.resume(handle);
+		mixer.render(&mut output);
+		assert_eq!(&output[..2], &[20, 30]);
+	}
+
+	#[test]
+	fn saturation(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![30000, -30000]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+		mixer.play(sound.clone());
+		mixer.play(sound.clone());
+
+		let mut output = [0i16; 2];
+		mixer.render(&mut output);
+		assert_eq!(output, [32767, -32768]);
+	}
+
+	#[test]
+	fn conversion(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 50), vec![1000; 100]));
+		let mut mixer = Mixer::new(Format::new(2, 100));
+		mixer.play(sound.clone());
+		mixer.play(sound.clone());
+		assert_eq!(mixer.conversions.len(), 1);
+
+		// Twice the rate, so the sound lasts twice as many frames
+		let mut output = [0i16; 404];
+		mixer.render(&mut output);
+		assert_eq!(&output[400..], &[0, 0, 0, 0]);
+		assert!(output[200..202].iter().all(|s| (*s - 2000).abs() < 20));
+		assert_eq!(mixer.voices(), 0);
+
+		drop(sound);
+		mixer.play(Rc::new(Sound::from_samples(Format::new(1, 50), vec![0; 10])));
+		assert_eq!(mixer.conversions.len(), 1);
+	}
+
+	#[test]
+	fn buses(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 8]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+		let lost = mixer.play(sound.clone());
+		assert!(!mixer.route(lost, "nowhere"));
+		mixer.stop(lost);
+
+		let music = mixer.play(sound.clone());
+		let sfx   = mixer.play(sound.clone());
+		assert!(mixer.route(music, MUSIC_BUS));
+		assert!(mixer.route(sfx, SFX_BUS));
+
+		let mut output = [0i16; 1];
+		mixer.bus_mut(MUSIC_BUS).unwrap().set_volume(0.5);
+		mixer.render(&mut output);
+		assert_eq!(output, [1500]);
+
+		mixer.bus_mut(SFX_BUS).unwrap().set_muted(true);
+		mixer.render(&mut output);
+		assert_eq!(output, [500]);
+
+		// Soloing overrides everything that isn't soloed, but not mutes
+		mixer.bus_mut(SFX_BUS).unwrap().set_muted(false);
+		mixer.bus_mut(SFX_BUS).unwrap().set_solo(true);
+		mixer.render(&mut output);
+		assert_eq!(output, [1000]);
+	}
+
+	#[test]
+	fn ducking(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 4]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+
+		let mut ducking = Ducking::new(VOICE_BUS, 0.25);
+		ducking.attack  = 0.0;
+		ducking.release = 0.0;
+		mixer.bus_mut(MUSIC_BUS).unwrap().set_ducking(Some(ducking));
+
+		let music = mixer.play(sound.clone());
+		mixer.route(music, MUSIC_BUS);
+		mixer.set_looping(music, true);
+
+		let mut output = [0i16; 4];
+		mixer.render(&mut output);
+		assert_eq!(output, [1000; 4]);
+
+		// Music dips while the voice plays and comes back right after
+		let voice = mixer.play(sound.clone());
+		mixer.route(voice, VOICE_BUS);
+		mixer.render(&mut output);
+		assert_eq!(output, [1250; 4]);
+		mixer.render(&mut output);
+		assert_eq!(output, [1000; 4]);
+
+		// A muted sidechain doesn't duck anything, and neither does one left out of a solo
+		mixer.bus_mut(VOICE_BUS).unwrap().set_muted(true);
+		let voice = mixer.play(sound.clone());
+		mixer.route(voice, VOICE_BUS);
+		mixer.render(&mut output);
+		assert_eq!(output, [1000; 4]);
+
+		mixer.bus_mut(VOICE_BUS).unwrap().set_muted(false);
+		mixer.bus_mut(MUSIC_BUS).unwrap().set_solo(true);
+		let voice = mixer.play(sound.clone());
+		mixer.route(voice, VOICE_BUS);
+		mixer.render(&mut output);
+		assert_eq!(output, [1000; 4]);
+	}
+
+	#[test]
+	fn loop_region_and_fades(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![0, 10, 20, 30, 40]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+
+		// The first frame works as an intro to a loop of two frames
+		let handle = mixer.play(sound.clone());
+		mixer.set_looping(handle, true);
+		mixer.set_loop_region(handle, 1, Some(3));
+		let mut output = [0i16; 8];
+		mixer.render(&mut output);
+		assert_eq!(output, [0, 10, 20, 10, 20, 10, 20, 10]);
+		mixer.stop(handle);
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 8]));
+		let handle = mixer.play(sound.clone());
+		mixer.set_looping(handle, true);
+		mixer.fade_out(handle, 0.04);
+		mixer.render(&mut output);
+		assert_eq!(output, [750, 500, 250, 0, 0, 0, 0, 0]);
+		assert!(!mixer.is_playing(handle));
+	}
+
+	#[test]
+	fn effects(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000, 0, 0, 0]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+
+		// The echo only applies to the voice it was inserted on
+		let echoed = mixer.play(sound.clone());
+		mixer.add_voice_effect(echoed, Box::new(Delay::new(0.02, 0.0, 0.5)));
+		let dry = mixer.play(Rc::new(Sound::from_samples(Format::new(1, 100), vec![0, 100, 0, 0])));
+		mixer.route(dry, SFX_BUS);
+
+		let mut output = [0i16; 4];
+		mixer.render(&mut output);
+		assert_eq!(output, [1000, 100, 500, 0]);
+
+		// Bus inserts see everything routed through the bus, master inserts see everything
+		let voice = mixer.play(sound.clone());
+		mixer.route(voice, SFX_BUS);
+		mixer.bus_mut(SFX_BUS).unwrap().add_effect(Box::new(Bitcrusher::new(8, 1)));
+		mixer.render(&mut output);
+		assert_eq!(output, [768, 0, 0, 0]);
+
+		mixer.bus_mut(SFX_BUS).unwrap().effects_mut().clear();
+		mixer.add_effect(Box::new(Bitcrusher::new(9, 1)));
+		mixer.play(sound.clone());
+		mixer.render(&mut output);
+		assert_eq!(output, [896, 0, 0, 0]);
+	}
+
+	#[test]
+	fn effect_tails(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+
+		// The echoes keep going after the sound itself is over
+		let echoed = mixer.play(sound.clone());
+		mixer.add_voice_effect(echoed, Box::new(Delay::new(0.02, 0.5, 0.5)));
+		let mut output = [0i16; 4];
+		mixer.render(&mut output);
+		assert_eq!(output, [1000, 0, 500, 0]);
+		mixer.render(&mut output);
+		assert_eq!(output, [250, 0, 125, 0]);
+		assert!(mixer.is_playing(echoed));
+
+		// Until they fade into silence
+		for _ in 0..4{ mixer.render(&mut output) }
+		assert!(!mixer.is_playing(echoed));
+		mixer.render(&mut output);
+		assert_eq!(output, [0, 0, 0, 0]);
+
+		// Effects without a tail let the voice go right away
+		let crushed = mixer.play(sound.clone());
+		mixer.add_voice_effect(crushed, Box::new(Bitcrusher::new(8, 1)));
+		mixer.render(&mut output);
+		assert!(!mixer.is_playing(crushed));
+	}
+
+	#[test]
+	fn voice_limits(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let boom  = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 50]));
+		let alarm = Rc::new(Sound::from_samples(Format::new(1, 100), vec![10; 50]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+		mixer.set_max_voices(Some(2));
+
+		// Past the limit, the oldest voice makes room for the newest
+		let first  = mixer.play(boom.clone());
+		let second = mixer.play(boom.clone());
+		let third  = mixer.play(boom.clone());
+		assert_eq!(mixer.voices(), 2);
+		assert!(!mixer.is_playing(first) && mixer.is_playing(second) && mixer.is_playing(third));
+
+		// Voices can't steal from more important ones
+		mixer.set_limits(&alarm, SoundLimits{ priority: 1, ..SoundLimits::default() });
+		let important = mixer.play(alarm.clone());
+		let other     = mixer.play(alarm.clone());
+		assert!(mixer.is_playing(important) && mixer.is_playing(other));
+		let rejected = mixer.play(boom.clone());
+		assert!(!mixer.is_playing(rejected));
+
+		// The quietest voice is only known once voices have been rendered
+		mixer.stop_all();
+		mixer.set_stealing(Stealing::Quietest);
+		let quiet = mixer.play(alarm.clone());
+		let loud  = mixer.play(boom.clone());
+		mixer.set_priority(quiet, 0);
+		let mut output = [0i16; 1];
+		mixer.render(&mut output);
+		mixer.play(boom.clone());
+		assert!(!mixer.is_playing(quiet) && mixer.is_playing(loud));
+
+		mixer.stop_all();
+		mixer.set_stealing(Stealing::LowestPriority);
+		let low = mixer.play(boom.clone());
+		mixer.play(alarm.clone());
+		mixer.play(alarm.clone());
+		assert!(!mixer.is_playing(low));
+		assert_eq!(mixer.voices(), 2);
+	}
+
+	#[test]
+	fn instances_and_cooldown(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let boom = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 50]));
+		let mut mixer = Mixer::new(Format::new(1, 100));
+		mixer.set_limits(&boom, SoundLimits{ max_instances: Some(1), cooldown: 0.05, ..SoundLimits::default() });
+
+		// Triggers within the cooldown are swallowed
+		let first = mixer.play(boom.clone());
+		let again = mixer.play(boom.clone());
+		assert!(!mixer.is_playing(again));
+		let mut output = [0i16; 4];
+		mixer.render(&mut output);
+		let again = mixer.play(boom.clone());
+		assert!(!mixer.is_playing(again));
+		assert!(mixer.is_playing(first));
+
+		// Once it's over, the new instance replaces the old one
+		mixer.render(&mut output);
+		let second = mixer.play(boom.clone());
+		assert!(mixer.is_playing(second) && !mixer.is_playing(first));
+		assert_eq!(mixer.voices(), 1);
+		assert_eq!(mixer.limits(&boom).max_instances, Some(1));
+
+		// Other sounds are untouched
+		let other = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 50]));
+		mixer.play(other.clone());
+		mixer.play(other.clone());
+		assert_eq!(mixer.voices(), 3);
+		assert_eq!(mixer.limits(&other), SoundLimits::default());
+
+		// Voices outlive their original sound when it had to be converted, but stop counting as its instances,
+		// even once another sound takes its place in memory
+		let stereo = Rc::new(Sound::from_samples(Format::new(2, 100), vec![1000; 100]));
+		mixer.play(stereo.clone());
+		mixer.stop_all();
+		mixer.set_default_limits(SoundLimits{ max_instances: Some(1), ..SoundLimits::default() });
+		let stale = mixer.play(Rc::new(Sound::from_samples(Format::new(2, 100), vec![1000; 100])));
+		mixer.play(stereo.clone());
+		let fresh = mixer.play(Rc::new(Sound::from_samples(Format::new(2, 100), vec![1000; 100])));
+		assert!(mixer.is_playing(stale) && mixer.is_playing(fresh));
+		assert_eq!(mixer.voices(), 3);
+	}
+}
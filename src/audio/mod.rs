@@ -0,0 +1,558 @@
+use std::io::{Read, Seek};
+
+/* Import codec libraries */
+extern crate hound;
+extern crate vorbis;
+pub use self::vorbis::VorbisQuality;
+
+mod music;
+pub use self::music::Music;
+
+pub mod convert;
+pub use self::convert::Interpolation;
+
+pub mod mixer;
+pub use self::mixer::{Mixer, VoiceHandle, Bus, Ducking, Stealing, SoundLimits};
+
+pub mod backend;
+pub use self::backend::{AudioBackend, NullBackend, WavFileBackend, Output};
+
+pub mod player;
+pub use self::player::{MusicPlayer, Track};
+
+pub mod spatial;
+pub use self::spatial::{Spatializer, Emitter, Attenuation};
+
+pub mod dsp;
+pub use self::dsp::{Effect, Biquad, Delay, Reverb, Bitcrusher, Limiter};
+
+pub mod synth;
+pub use self::synth::{SynthParams, Waveform, Preset};
+
+pub mod tracker;
+pub use self::tracker::{Module, ModulePlayer, ModuleKind};
+
+pub mod analysis;
+pub use self::analysis::{Analyzer, Spectrum};
+
+use std::io::Error as IoError;
+#[derive(Debug)]
+pub enum AudioError{
+	UnableToReadFile(IoError),
+	UnableToWriteFile(IoError),
+
+	/// The decoded stream describes a format the engine can't handle
+	UnsupportedFormat,
+	/// The stream is missing data or its pages are damaged
+	CorruptStream,
+
+	/// The Wave (RIFF) decoder failed
+	Wave(hound::Error),
+	/// The Ogg Vorbis decoder failed
+	Vorbis(vorbis::VorbisError),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Codec{ Vorbis, Wave, Tracker }
+impl Codec{
+	/**
+	 * Guesses the codec of a stream from its first bytes: RIFF for Wave, OggS for Vorbis,
+	 * and the signatures of MOD and XM modules for trackers. MOD keeps its signature 1080
+	 * bytes in, so it's only recognized when given at least that much of the stream.
+	 */
+	pub fn detect(header: &[u8]) -> Option<Codec>{
+		if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WAVE" { Some(Codec::Wave) }
+		else if header.starts_with(b"OggS") { Some(Codec::Vorbis) }
+		else if tracker::Module::detect(header) { Some(Codec::Tracker) }
+		else { None }
+	}
+
+	/** Decodes the whole stream into interleaved `i16` samples */
+	fn load_sound<R: Read + Seek>(&self, source: R) -> Result<(Format, Vec<i16>), AudioError>{
+		match self{
+			&Codec::Vorbis => {
+				use self::vorbis::{Decoder, VorbisError};
+				let mut decoder = match Decoder::new(source){
+					Ok(decoder) => decoder,
+					Err(what) => {
+						error!("Could not read Ogg Vorbis headers: {:?}", what);
+						return Err(AudioError::Vorbis(what))
+					}
+				};
+
+				// Chained streams may switch formats between links, which get conformed to the first one's
+				let mut stream: Option<(Format, Vec<i16>)> = None;
+				let mut last: Option<Format> = None;
+				for packet in decoder.packets(){
+					let packet = match packet{
+						Ok(packet) => packet,
+						Err(VorbisError::Hole) => {
+							error!("Ogg Vorbis stream has a hole in its data");
+							return Err(AudioError::CorruptStream)
+						},
+						Err(what) => {
+							error!("Could not decode Ogg Vorbis packet: {:?}", what);
+							return Err(AudioError::Vorbis(what))
+						}
+					};
+
+					if packet.channels == 0 || packet.rate == 0{
+						error!("Ogg Vorbis packet has an invalid format: {} channels at {}Hz", packet.channels, packet.rate);
+						return Err(AudioError::UnsupportedFormat)
+					}
+					let packet_format = Format{
+						channels: packet.channels as usize,
+						rate:     packet.rate as usize
+					};
+					if last.is_some() && last != Some(packet_format) { debug!("Ogg Vorbis stream changed format to {:?}", packet_format) }
+					last = Some(packet_format);
+
+					if let Some((format, ref mut data)) = stream{
+						if packet_format == format { data.extend_from_slice(&packet.data) }
+						else { data.append(&mut convert::convert(&packet.data, packet_format, format, convert::CHAINED_INTERPOLATION)) }
+						continue
+					}
+					stream = Some((packet_format, packet.data));
+				}
+
+				// The first link dictates the format of the whole sound
+				let (format, data) = match stream{
+					Some(stream) => stream,
+					None => {
+						error!("Ogg Vorbis stream has no audio packets");
+						return Err(AudioError::CorruptStream)
+					}
+				};
+
+				Ok((format, data))
+			},
+			&Codec::Wave => {
+				let (mut reader, format) = try!(open_wave(source));
+
+				let length = reader.len() as usize;
+				let mut data = Vec::with_capacity(length);
+				try!(read_wave(&mut reader, length, &mut data));
+
+				Ok((format, data))
+			},
+			&Codec::Tracker => {
+				let mut source = source;
+				let mut bytes = Vec::new();
+				if let Err(what) = source.read_to_end(&mut bytes){
+					error!("Could not read tracker module: {:?}", what);
+					return Err(AudioError::UnableToReadFile(what))
+				}
+
+				// Render the whole song, up to the point it ends or loops
+				let mut player = tracker::ModulePlayer::new(try!(tracker::Module::parse(&bytes)), tracker::TRACKER_RATE);
+				let mut data = Vec::new();
+				loop{
+					let start = data.len();
+					data.resize(start + 8192, 0);
+					let written = player.render(&mut data[start..]);
+					data.truncate(start + written);
+					if written < 8192 { break }
+				}
+
+				Ok((player.format(), data))
+			}
+		}
+	}
+}
+
+/* Reads the header of a Wave stream, making sure the engine can handle its format */
+fn open_wave<R: Read>(source: R) -> Result<(hound::WavReader<R>, Format), AudioError>{
+	let reader = match hound::WavReader::new(source){
+		Ok(reader) => reader,
+		Err(what) => {
+			error!("Could not read Wave header: {:?}", what);
+			return Err(AudioError::Wave(what))
+		}
+	};
+
+	let spec = reader.spec();
+	if spec.channels == 0 || spec.sample_rate == 0{
+		error!("Wave stream has an invalid format: {:?}", spec);
+		return Err(AudioError::UnsupportedFormat)
+	}
+
+	let format = Format{
+		channels: spec.channels as usize,
+		rate:     spec.sample_rate as usize
+	};
+	Ok((reader, format))
+}
+
+/* Decodes up to `limit` samples into `data`, converting them to i16 whatever their original depth */
+fn read_wave<R: Read>(reader: &mut hound::WavReader<R>, limit: usize, data: &mut Vec<i16>) -> Result<usize, AudioError>{
+	use self::hound::SampleFormat;
+	let spec = reader.spec();
+
+	let start = data.len();
+	match spec.sample_format{
+		SampleFormat::Float => for sample in reader.samples::<f32>().take(limit){
+			match sample{
+				Ok(sample) => data.push(float_to_i16(sample)),
+				Err(what) => {
+					error!("Could not decode Wave sample: {:?}", what);
+					return Err(AudioError::Wave(what))
+				}
+			}
+		},
+		SampleFormat::Int => for sample in reader.samples::<i32>().take(limit){
+			match sample{
+				Ok(sample) => data.push(int_to_i16(sample, spec.bits_per_sample)),
+				Err(what) => {
+					error!("Could not decode Wave sample: {:?}", what);
+					return Err(AudioError::Wave(what))
+				}
+			}
+		}
+	}
+
+	Ok(data.len() - start)
+}
+
+/* Rescales an integer sample of a given bit depth into the i16 range */
+fn int_to_i16(sample: i32, bits: u16) -> i16{
+	if bits <= 16 { (sample << (16 - bits)) as i16 }
+	else          { (sample >> (bits - 16)) as i16 }
+}
+
+/* Rescales a float sample in the [-1.0, 1.0] range into the i16 range */
+fn float_to_i16(sample: f32) -> i16{
+	let scaled = sample * 32767.0;
+	if scaled > 32767.0 { 32767 } else if scaled < -32768.0 { -32768 } else { scaled as i16 }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Format{
+	/// Number of channels
+	channels: usize,
+	/// Number of samples per second
+	rate: usize
+}
+impl Format{
+	pub fn new(channels: usize, rate: usize) -> Format{
+		Format{
+			channels: channels,
+			rate:     rate
+		}
+	}
+
+	pub fn bytes_per_second(&self) -> usize{
+		use std::mem::size_of;
+		self.channels * self.rate * size_of::<i16>()
+	}
+
+	pub fn samplerate(&self) -> usize{ self.rate     }
+	pub fn channels(&self)   -> usize{ self.channels }
+}
+
+
+
+#[derive(Debug)]
+pub struct Sound{
+	/// Audio format
+	format: Format,
+
+	/// Raw audio data, in `i16` samples
+	data: Vec<i16>
+}
+impl Sound{
+	pub fn new(codec: Codec, source: &str) -> Result<Sound, AudioError>{
+		use std::fs::File;
+		use std::io::BufReader;
+		match File::open(source){
+			Ok(file) => Sound::from_reader(codec, BufReader::new(file)),
+			Err(what) => {
+				error!(r#"Could not open sound at "{}": {:?}"#, source, what);
+				Err(AudioError::UnableToReadFile(what))
+			}
+		}
+	}
+
+	pub fn from_reader<R: Read + Seek>(codec: Codec, source: R) -> Result<Sound, AudioError>{
+		let (format, data) = try!(codec.load_sound(source));
+		Ok(Sound{
+			format: format,
+			data:   data
+		})
+	}
+
+	/// Wraps already decoded, interleaved samples
+	pub fn from_samples(format: Format, data: Vec<i16>) -> Sound{
+		Sound{
+			format: format,
+			data:   data
+		}
+	}
+
+	pub fn format(&self) -> Format{ self.format }
+	pub fn data(&self)   -> &[i16]{ &self.data[..] }
+
+	/// Number of sample frames (one sample for every channel) in the sound
+	pub fn frames(&self) -> usize{ self.data.len() / self.format.channels }
+
+	/// Length of the sound, in seconds
+	pub fn duration(&self) -> f64{ self.frames() as f64 / self.format.rate as f64 }
+
+	/// Converts the sound into another format, resampling with a windowed sinc
+	pub fn convert(&self, format: Format) -> Sound{
+		self.convert_with(format, Interpolation::default())
+	}
+
+	pub fn convert_with(&self, format: Format, interpolation: Interpolation) -> Sound{
+		Sound{
+			format: format,
+			data:   convert::convert(&self.data, self.format, format, interpolation)
+		}
+	}
+
+	/// Writes the sound into a 16-bit Wave file
+	pub fn write_wav(&self, path: &str) -> Result<(), AudioError>{
+		let spec = hound::WavSpec{
+			channels:        self.format.channels as u16,
+			sample_rate:     self.format.rate as u32,
+			bits_per_sample: 16,
+			sample_format:   hound::SampleFormat::Int
+		};
+
+		let mut writer = match hound::WavWriter::create(path, spec){
+			Ok(writer) => writer,
+			Err(what) => {
+				error!(r#"Could not create Wave file at "{}": {:?}"#, path, what);
+				return Err(AudioError::Wave(what))
+			}
+		};
+		for sample in &self.data{
+			if let Err(what) = writer.write_sample(*sample){
+				error!(r#"Could not write sample to Wave file at "{}": {:?}"#, path, what);
+				return Err(AudioError::Wave(what))
+			}
+		}
+		if let Err(what) = writer.finalize(){
+			error!(r#"Could not finalize Wave file at "{}": {:?}"#, path, what);
+			return Err(AudioError::Wave(what))
+		}
+		Ok(())
+	}
+
+	/// Encodes the sound into an in-memory Ogg Vorbis stream
+	pub fn encode_vorbis(&self, quality: VorbisQuality) -> Result<Vec<u8>, AudioError>{
+		if self.format.channels > 255{
+			error!("Ogg Vorbis can't hold {} channels", self.format.channels);
+			return Err(AudioError::UnsupportedFormat)
+		}
+
+		let mut encoder = match vorbis::Encoder::new(self.format.channels as u8, self.format.rate as u64, quality){
+			Ok(encoder) => encoder,
+			Err(what) => {
+				error!("Could not set up Ogg Vorbis encoder for {:?}: {:?}", self.format, what);
+				return Err(AudioError::Vorbis(what))
+			}
+		};
+
+		let mut stream = match encoder.encode(&self.data){
+			Ok(stream) => stream,
+			Err(what) => {
+				error!("Could not encode Ogg Vorbis stream: {:?}", what);
+				return Err(AudioError::Vorbis(what))
+			}
+		};
+		match encoder.flush(){
+			Ok(mut tail) => stream.append(&mut tail),
+			Err(what) => {
+				error!("Could not flush Ogg Vorbis stream: {:?}", what);
+				return Err(AudioError::Vorbis(what))
+			}
+		}
+
+		Ok(stream)
+	}
+
+	/// Writes the sound into an Ogg Vorbis file
+	pub fn write_vorbis(&self, path: &str, quality: VorbisQuality) -> Result<(), AudioError>{
+		use std::fs::File;
+		use std::io::Write;
+
+		let stream = try!(self.encode_vorbis(quality));
+		match File::create(path).and_then(|mut file| file.write_all(&stream)){
+			Ok(_) => Ok(()),
+			Err(what) => {
+				error!(r#"Could not write Ogg Vorbis file at "{}": {:?}"#, path, what);
+				Err(AudioError::UnableToWriteFile(what))
+			}
+		}
+	}
+
+	/// Runs the sound through an effect, giving back the processed copy
+	pub fn process(&self, effect: &mut Effect) -> Sound{
+		Sound{
+			format: self.format,
+			data:   dsp::apply(&self.data, self.format, effect)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::{Sound, Codec, Format, AudioError, VorbisQuality};
+	use super::hound::{WavWriter, WavSpec, SampleFormat};
+
+	/* Writes a temporary Wave file with the given spec and samples, returning its path */
+	fn wave(name: &str, spec: WavSpec, samples: &[i32]) -> String{
+		use std::env;
+		let mut path = env::temp_dir();
+		path.push(name);
+		let path = path.to_str().unwrap().to_owned();
+
+		let mut writer = WavWriter::create(&path, spec).unwrap();
+		for sample in samples{ writer.write_sample(*sample).unwrap(); }
+		writer.finalize().unwrap();
+
+		path
+	}
+
+	#[test]
+	fn wave_16bit(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let path = wave("bbman_wave_16bit.wav", WavSpec{
+			channels: 2,
+			sample_rate: 22050,
+			bits_per_sample: 16,
+			sample_format: SampleFormat::Int
+		}, &[0, 1, -1, 32767, -32768, 1234]);
+
+		let sound = Sound::new(Codec::Wave, &path).unwrap();
+		assert_eq!(sound.format(), Format::new(2, 22050));
+		assert_eq!(sound.data(), &[0, 1, -1, 32767, -32768, 1234]);
+		assert_eq!(sound.frames(), 3);
+	}
+
+	#[test]
+	fn wave_bit_depth(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let spec = |bits| WavSpec{
+			channels: 1,
+			sample_rate: 44100,
+			bits_per_sample: bits,
+			sample_format: SampleFormat::Int
+		};
+
+		let sound = Sound::new(Codec::Wave, &wave("bbman_wave_8bit.wav", spec(8), &[64, -128, 127])).unwrap();
+		assert_eq!(sound.data(), &[16384, -32768, 32512]);
+
+		let sound = Sound::new(Codec::Wave, &wave("bbman_wave_24bit.wav", spec(24), &[0x123456, -0x800000])).unwrap();
+		assert_eq!(sound.data(), &[0x1234, -32768]);
+
+		let sound = Sound::new(Codec::Wave, &wave("bbman_wave_32bit.wav", spec(32), &[0x12345678, -1])).unwrap();
+		assert_eq!(sound.data(), &[0x1234, -1]);
+	}
+
+	#[test]
+	fn wave_float(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use std::io::Cursor;
+		let stream = vec![
+			0x52, 0x49, 0x46, 0x46, 0x38, 0x00, 0x00, 0x00, 0x57, 0x41, 0x56, 0x45, /* RIFF, WAVE          */
+			0x66, 0x6D, 0x74, 0x20, 0x10, 0x00, 0x00, 0x00, 0x03, 0x00, 0x01, 0x00, /* fmt, float, mono    */
+			0x40, 0x1F, 0x00, 0x00, 0x00, 0x7D, 0x00, 0x00, 0x04, 0x00, 0x20, 0x00, /* 8000Hz, 32 bits     */
+			0x64, 0x61, 0x74, 0x61, 0x14, 0x00, 0x00, 0x00,                         /* data, 20 bytes      */
+			0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0xC0, 0x3F,                         /* 0.5, 1.5            */
+			0x00, 0x00, 0x80, 0xBF, 0x00, 0x00, 0x00, 0xC0,                         /* -1.0, -2.0          */
+			0x00, 0x00, 0x00, 0x00                                                  /* 0.0                 */
+		];
+
+		// Samples past full scale are clamped instead of wrapping around
+		let sound = Sound::from_reader(Codec::Wave, Cursor::new(stream)).unwrap();
+		assert_eq!(sound.format(), Format::new(1, 8000));
+		assert_eq!(sound.data(), &[16383, 32767, -32767, -32768, 0]);
+	}
+
+	#[test]
+	fn detect(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		assert_eq!(Codec::detect(b"RIFF\x24\x00\x00\x00WAVEfmt "), Some(Codec::Wave));
+		assert_eq!(Codec::detect(b"OggS\x00\x02"), Some(Codec::Vorbis));
+		assert_eq!(Codec::detect(b"RIFF\x24\x00\x00\x00AVI "), None);
+		assert_eq!(Codec::detect(b"ID3"), None);
+	}
+
+	#[test]
+	fn wave_round_trip(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use std::env;
+		let mut path = env::temp_dir();
+		path.push("bbman_wave_round_trip.wav");
+		let path = path.to_str().unwrap().to_owned();
+
+		let sound = Sound::from_samples(Format::new(2, 11025), vec![0, -1, 32767, -32768, 1234, -4321]);
+		sound.write_wav(&path).unwrap();
+
+		let decoded = Sound::new(Codec::Wave, &path).unwrap();
+		assert_eq!(decoded.format(), sound.format());
+		assert_eq!(decoded.data(), sound.data());
+	}
+
+	#[test]
+	fn vorbis_round_trip(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use std::f32::consts::PI;
+		use std::io::Cursor;
+		let sound = Sound::from_samples(Format::new(1, 44100), (0..44100)
+			.map(|i| ((2.0 * PI * 440.0 * i as f32 / 44100.0).sin() * 16000.0) as i16)
+			.collect());
+
+		let stream = sound.encode_vorbis(VorbisQuality::HighQuality).unwrap();
+		assert_eq!(Codec::detect(&stream), Some(Codec::Vorbis));
+
+		// Vorbis is lossy, so only the format, length and loudness are expected to survive
+		let decoded = Sound::from_reader(Codec::Vorbis, Cursor::new(stream)).unwrap();
+		assert_eq!(decoded.format(), sound.format());
+		assert!((decoded.frames() as isize - sound.frames() as isize).abs() < 4096);
+
+		let rms = |data: &[i16]| (data.iter().fold(0.0, |acc, s| acc + (*s as f64) * (*s as f64)) / data.len() as f64).sqrt();
+		let (original, decoded) = (rms(&sound.data()[4096..40000]), rms(&decoded.data()[4096..40000]));
+		assert!((decoded - original).abs() < original * 0.1);
+	}
+
+	#[test]
+	fn vorbis_chained(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Two silent links, 1280 frames of mono at 8000Hz and then 1024 frames of stereo at 11025Hz
+		let sound = Sound::new(Codec::Vorbis, "test/sounds/chained.ogg").unwrap();
+		assert_eq!(sound.format(), Format::new(1, 8000));
+
+		// The second link is conformed to the first a packet at a time, each of which may lose a frame to rounding
+		let expected = 1280 + 1024 * 8000 / 11025;
+		assert!(sound.frames() <= expected && sound.frames() + 8 >= expected);
+		assert!(sound.data().iter().all(|sample| *sample == 0));
+	}
+
+	#[test]
+	fn vorbis_corrupt(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use std::io::Cursor;
+		match Sound::from_reader(Codec::Vorbis, Cursor::new(b"OggS but not really a vorbis stream".to_vec())){
+			Err(AudioError::Vorbis(_)) => {},
+			other => panic!("Expected a Vorbis error, got {:?}", other)
+		}
+	}
+}
@@ -0,0 +1,494 @@
+use std::io::{self, Read, Seek, SeekFrom, Cursor};
+use std::fmt;
+
+use super::{hound, vorbis};
+use super::{Codec, Format, AudioError};
+use super::convert;
+use super::tracker::{Module, ModulePlayer, TRACKER_RATE};
+
+/* Any seekable source, erased so every Music has the same type */
+trait Input: Read + Seek {}
+impl<T: Read + Seek> Input for T {}
+
+/// Number of sample frames decoded every time the stream runs dry
+const CHUNK_FRAMES: usize = 4096;
+
+/* Where the samples of a Wave stream lie, so it can be seeked by byte offset */
+struct WaveLayout{
+	header: Vec<u8>,  /* RIFF header and format chunk, followed by the header of the data chunk */
+	start:  u64,      /* Offset of the first sample in the source */
+	length: u64,      /* Size of the samples, in bytes            */
+	block_align: u64  /* Size of a frame, in bytes                */
+}
+impl WaveLayout{
+	/* Walks the chunks of a Wave stream up to its samples, leaving the source right before them */
+	fn read(source: &mut Box<Input>) -> Result<WaveLayout, AudioError>{
+		let base = match source.seek(SeekFrom::Current(0)){
+			Ok(base) => base,
+			Err(what) => {
+				error!("Could not find where the Wave stream starts: {:?}", what);
+				return Err(AudioError::UnableToReadFile(what))
+			}
+		};
+
+		let mut header = vec![0u8; 12];
+		try!(read_header(source, &mut header[..]));
+		if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE"{
+			error!("Wave stream lacks a RIFF header");
+			return Err(AudioError::CorruptStream)
+		}
+
+		let mut offset = base + 12;
+		let mut block_align = 0;
+		loop{
+			let mut chunk = [0u8; 8];
+			try!(read_header(source, &mut chunk));
+			let size = u32_le(&chunk[4..8]) as u64;
+			offset += 8;
+
+			if &chunk[0..4] == b"data"{
+				if block_align == 0{
+					error!("Wave stream has no format before its samples");
+					return Err(AudioError::CorruptStream)
+				}
+				header.extend_from_slice(&chunk);
+				return Ok(WaveLayout{
+					header: header,
+					start:  offset,
+					length: size,
+					block_align: block_align
+				})
+			}
+
+			// Only the format is kept, and chunks are padded to an even size
+			let padded = size + size % 2;
+			let format = &chunk[0..4] == b"fmt ";
+			let read = if format{
+				header.extend_from_slice(&chunk);
+				(&mut *source).take(padded).read_to_end(&mut header).map(|read| read as u64)
+			}else{
+				io::copy(&mut (&mut *source).take(padded), &mut io::sink())
+			};
+			match read{
+				Ok(read) if read == padded => offset += padded,
+				Ok(_) => {
+					error!("Wave stream ends before its samples");
+					return Err(AudioError::CorruptStream)
+				},
+				Err(what) => {
+					error!("Could not read Wave header: {:?}", what);
+					return Err(AudioError::UnableToReadFile(what))
+				}
+			}
+			if format && size >= 14{
+				let at = header.len() - padded as usize;
+				block_align = (header[at + 12] as u16 | (header[at + 13] as u16) << 8) as u64;
+			}
+		}
+	}
+
+	/* Opens the stream so its first sample belongs to the given frame */
+	fn open(&self, mut source: Box<Input>, frame: usize) -> Result<(hound::WavReader<WaveSource>, Format), AudioError>{
+		let offset = ::std::cmp::min(frame as u64 * self.block_align, self.length);
+		if let Err(what) = source.seek(SeekFrom::Start(self.start + offset)){
+			error!("Could not seek Wave stream to frame {}: {:?}", frame, what);
+			return Err(AudioError::UnableToReadFile(what))
+		}
+
+		// The Wave reader can't seek, so it's handed a header that only counts the samples left
+		let remaining = self.length - offset;
+		let mut header = self.header.clone();
+		let (riff, data) = (header.len() as u64 - 8 + remaining, header.len() - 4);
+		put_u32_le(&mut header[4..8], riff as u32);
+		put_u32_le(&mut header[data..], remaining as u32);
+
+		super::open_wave(WaveSource{
+			header: Cursor::new(header),
+			source: source
+		})
+	}
+}
+
+/* The header of a Wave stream followed by its samples, from wherever the source was seeked to */
+struct WaveSource{
+	header: Cursor<Vec<u8>>,
+	source: Box<Input>
+}
+impl Read for WaveSource{
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>{
+		match try!(self.header.read(buffer)){
+			0 => self.source.read(buffer),
+			read => Ok(read)
+		}
+	}
+}
+
+fn read_header(source: &mut Box<Input>, buffer: &mut [u8]) -> Result<(), AudioError>{
+	source.read_exact(buffer).map_err(|what| {
+		error!("Could not read Wave header: {:?}", what);
+		AudioError::UnableToReadFile(what)
+	})
+}
+
+fn u32_le(bytes: &[u8]) -> u32{ bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24 }
+fn put_u32_le(bytes: &mut [u8], value: u32){
+	for (index, byte) in bytes.iter_mut().enumerate(){ *byte = (value >> (index * 8)) as u8 }
+}
+
+enum Stream{
+	Vorbis(vorbis::Decoder<Box<Input>>),
+	Wave(Option<hound::WavReader<WaveSource>>, WaveLayout),
+	Tracker(ModulePlayer)
+}
+
+/// A sound that is decoded in small chunks as it is played, instead of all at once
+pub struct Music{
+	codec:  Codec,
+	format: Format,
+	stream: Stream,
+
+	buffer: Vec<i16>, /* Decoded samples waiting to be pulled          */
+	cursor: usize,    /* Index of the next sample to pull from buffer  */
+	offset: usize,    /* Samples pulled since the start of the stream  */
+	finished: bool    /* Whether the decoder has run out of data       */
+}
+impl Music{
+	pub fn new(codec: Codec, source: &str) -> Result<Music, AudioError>{
+		use std::fs::File;
+		use std::io::BufReader;
+		match File::open(source){
+			Ok(file) => Music::from_reader(codec, BufReader::new(file)),
+			Err(what) => {
+				error!(r#"Could not open music at "{}": {:?}"#, source, what);
+				Err(AudioError::UnableToReadFile(what))
+			}
+		}
+	}
+
+	pub fn from_reader<R: Read + Seek + 'static>(codec: Codec, source: R) -> Result<Music, AudioError>{
+		let mut source = Box::new(source) as Box<Input>;
+		match codec{
+			Codec::Vorbis => {
+				let mut decoder = match vorbis::Decoder::new(source){
+					Ok(decoder) => decoder,
+					Err(what) => {
+						error!("Could not read Ogg Vorbis headers: {:?}", what);
+						return Err(AudioError::Vorbis(what))
+					}
+				};
+
+				// The format is only known once the first packet has been decoded
+				let (format, buffer) = match decoder.packets().next(){
+					Some(Ok(packet)) => {
+						if packet.channels == 0 || packet.rate == 0{
+							error!("Ogg Vorbis packet has an invalid format: {} channels at {}Hz", packet.channels, packet.rate);
+							return Err(AudioError::UnsupportedFormat)
+						}
+						(Format::new(packet.channels as usize, packet.rate as usize), packet.data)
+					},
+					Some(Err(what)) => {
+						error!("Could not decode Ogg Vorbis packet: {:?}", what);
+						return Err(AudioError::Vorbis(what))
+					},
+					None => {
+						error!("Ogg Vorbis stream has no audio packets");
+						return Err(AudioError::CorruptStream)
+					}
+				};
+
+				Ok(Music{
+					codec:  codec,
+					format: format,
+					stream: Stream::Vorbis(decoder),
+
+					buffer: buffer,
+					cursor: 0,
+					offset: 0,
+					finished: false
+				})
+			},
+			Codec::Wave => {
+				let layout = try!(WaveLayout::read(&mut source));
+				let (reader, format) = try!(layout.open(source, 0));
+				Ok(Music{
+					codec:  codec,
+					format: format,
+					stream: Stream::Wave(Some(reader), layout),
+
+					buffer: Vec::with_capacity(CHUNK_FRAMES * format.channels()),
+					cursor: 0,
+					offset: 0,
+					finished: false
+				})
+			},
+			Codec::Tracker => {
+				// Modules are tiny next to what they render into, so they're kept in memory
+				let mut bytes = Vec::new();
+				if let Err(what) = source.read_to_end(&mut bytes){
+					error!("Could not read tracker module: {:?}", what);
+					return Err(AudioError::UnableToReadFile(what))
+				}
+
+				let player = ModulePlayer::new(try!(Module::parse(&bytes)), TRACKER_RATE);
+				let format = player.format();
+				Ok(Music{
+					codec:  codec,
+					format: format,
+					stream: Stream::Tracker(player),
+
+					buffer: Vec::with_capacity(CHUNK_FRAMES * format.channels()),
+					cursor: 0,
+					offset: 0,
+					finished: false
+				})
+			}
+		}
+	}
+
+	pub fn codec(&self)  -> Codec { self.codec  }
+	pub fn format(&self) -> Format{ self.format }
+
+	/// Frame the next pulled sample belongs to
+	pub fn position(&self) -> usize{ self.offset / self.format.channels() }
+
+	/// Whether every sample in the stream has already been pulled
+	pub fn is_finished(&self) -> bool{ self.finished && self.cursor >= self.buffer.len() }
+
+	/**
+	 * Pulls interleaved samples into `output`, decoding more of the stream as needed.
+	 *
+	 * Returns the number of samples written, which is only smaller than the length
+	 * of `output` once the end of the stream has been reached.
+	 */
+	pub fn fill(&mut self, output: &mut [i16]) -> usize{
+		let mut written = 0;
+		while written < output.len(){
+			if self.cursor >= self.buffer.len() && !self.refill() { break }
+
+			let available = ::std::cmp::min(self.buffer.len() - self.cursor, output.len() - written);
+			output[written..written + available].copy_from_slice(&self.buffer[self.cursor..self.cursor + available]);
+
+			self.cursor += available;
+			written     += available;
+		}
+
+		self.offset += written;
+		written
+	}
+
+	/// Moves the stream so the next pulled sample belongs to the given frame
+	pub fn seek(&mut self, frame: usize) -> Result<(), AudioError>{
+		self.buffer.clear();
+		self.cursor = 0;
+		self.finished = false;
+
+		let channels = self.format.channels();
+		match self.stream{
+			Stream::Vorbis(ref mut decoder) => {
+				if let Err(what) = decoder.time_seek(frame as f64 / self.format.samplerate() as f64){
+					error!("Could not seek Ogg Vorbis stream to frame {}: {:?}", frame, what);
+					return Err(AudioError::Vorbis(what))
+				}
+			},
+			Stream::Wave(ref mut stream, ref layout) => {
+				let source = match stream.take(){
+					Some(reader) => reader.into_inner().source,
+					None => return Err(AudioError::CorruptStream)
+				};
+				let (reader, _) = try!(layout.open(source, frame));
+				*stream = Some(reader);
+			},
+			Stream::Tracker(ref mut player) => {
+				/* Modules can only be played from the start, so render up to the frame */
+				player.restart();
+				let mut remaining = frame * channels;
+				while remaining > 0{
+					self.buffer.resize(::std::cmp::min(remaining, CHUNK_FRAMES * channels), 0);
+					match player.render(&mut self.buffer){
+						0 => break,
+						rendered => remaining -= rendered
+					}
+				}
+				self.buffer.clear();
+			}
+		}
+
+		self.offset = frame * channels;
+		Ok(())
+	}
+
+	pub fn rewind(&mut self) -> Result<(), AudioError>{ self.seek(0) }
+
+	/* Decodes the next chunk of the stream into the buffer, returning false once it runs dry */
+	fn refill(&mut self) -> bool{
+		self.buffer.clear();
+		self.cursor = 0;
+		if self.finished { return false }
+
+		let limit = CHUNK_FRAMES * self.format.channels();
+		let result = match self.stream{
+			Stream::Vorbis(ref mut decoder) => decode_vorbis(decoder, self.format, limit, &mut self.buffer),
+			Stream::Wave(Some(ref mut reader), _) => super::read_wave(reader, limit, &mut self.buffer),
+			Stream::Wave(None, _) => Ok(0),
+			Stream::Tracker(ref mut player) => {
+				self.buffer.resize(limit, 0);
+				let rendered = player.render(&mut self.buffer);
+				self.buffer.truncate(rendered);
+				Ok(rendered)
+			}
+		};
+
+		match result{
+			Ok(0) => { self.finished = true; false },
+			Ok(_) => true,
+			Err(what) => {
+				error!("Music stream stopped due to a decoding error: {:?}", what);
+				self.finished = true;
+				false
+			}
+		}
+	}
+}
+impl fmt::Debug for Music{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+		write!(f, "Music {{ codec: {:?}, format: {:?}, position: {} }}", self.codec, self.format, self.position())
+	}
+}
+
+/* Decodes Vorbis packets until at least `limit` samples are buffered, conforming chained links to the stream's format */
+fn decode_vorbis(decoder: &mut vorbis::Decoder<Box<Input>>, format: Format, limit: usize, data: &mut Vec<i16>) -> Result<usize, AudioError>{
+	let start = data.len();
+	while data.len() - start < limit{
+		let packet = match decoder.packets().next(){
+			Some(Ok(packet)) => packet,
+			Some(Err(vorbis::VorbisError::Hole)) => {
+				error!("Ogg Vorbis stream has a hole in its data");
+				return Err(AudioError::CorruptStream)
+			},
+			Some(Err(what)) => return Err(AudioError::Vorbis(what)),
+			None => break
+		};
+
+		let packet_format = Format::new(packet.channels as usize, packet.rate as usize);
+		if packet_format == format { data.extend_from_slice(&packet.data) }
+		else if packet.channels == 0 || packet.rate == 0 { return Err(AudioError::UnsupportedFormat) }
+		else { data.extend_from_slice(&convert::convert(&packet.data, packet_format, format, convert::CHAINED_INTERPOLATION)) }
+	}
+
+	Ok(data.len() - start)
+}
+
+#[cfg(test)]
+mod tests{
+	use super::Music;
+	use super::super::{Sound, Codec, Format};
+
+	#[test]
+	fn wave_stream(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use super::super::hound::{WavWriter, WavSpec, SampleFormat};
+		use std::fs::File;
+		use std::io::{Read, Cursor};
+		use std::env;
+		let mut path = env::temp_dir();
+		path.push("bbman_music_stream.wav");
+		let path = path.to_str().unwrap().to_owned();
+
+		// Write a long enough stream for it to be decoded in multiple chunks
+		let mut writer = WavWriter::create(&path, WavSpec{
+			channels: 2,
+			sample_rate: 8000,
+			bits_per_sample: 16,
+			sample_format: SampleFormat::Int
+		}).unwrap();
+		for i in 0..20000{ writer.write_sample((i % 3000) as i16).unwrap(); }
+		writer.finalize().unwrap();
+
+		let sound = Sound::new(Codec::Wave, &path).unwrap();
+		let mut music = Music::new(Codec::Wave, &path).unwrap();
+		assert_eq!(music.format(), Format::new(2, 8000));
+
+		// Pulling in odd-sized pieces must yield the exact same data
+		let mut pulled = Vec::new();
+		let mut buffer = [0i16; 334];
+		loop{
+			let count = music.fill(&mut buffer);
+			pulled.extend_from_slice(&buffer[..count]);
+			if count < buffer.len() { break }
+		}
+		assert_eq!(&pulled[..], sound.data());
+		assert!(music.is_finished());
+		assert_eq!(music.position(), 10000);
+
+		// Seek back into the middle of the stream
+		music.seek(6000).unwrap();
+		assert_eq!(music.position(), 6000);
+		assert_eq!(music.fill(&mut buffer), buffer.len());
+		assert_eq!(&buffer[..], &sound.data()[12000..12334]);
+
+		// Right before the end, and past it
+		music.seek(9990).unwrap();
+		assert_eq!(music.fill(&mut buffer), 20);
+		assert_eq!(&buffer[..20], &sound.data()[19980..]);
+		music.seek(12000).unwrap();
+		assert_eq!(music.fill(&mut buffer), 0);
+		assert!(music.is_finished());
+
+		// Chunks other than the format and the samples are skipped over
+		let mut bytes = Vec::new();
+		File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+		let list = b"LIST\x03\x00\x00\x00abc\x00";
+		let mut padded = bytes[..36].to_vec();
+		padded.extend_from_slice(list);
+		padded.extend_from_slice(&bytes[36..]);
+		let mut music = Music::from_reader(Codec::Wave, Cursor::new(padded)).unwrap();
+		music.seek(6000).unwrap();
+		assert_eq!(music.fill(&mut buffer), buffer.len());
+		assert_eq!(&buffer[..], &sound.data()[12000..12334]);
+	}
+
+	/* Pulls everything left in a stream, in odd-sized pieces */
+	fn drain(music: &mut Music) -> Vec<i16>{
+		let mut pulled = Vec::new();
+		let mut buffer = [0i16; 77];
+		loop{
+			let count = music.fill(&mut buffer);
+			pulled.extend_from_slice(&buffer[..count]);
+			if count < buffer.len() { return pulled }
+		}
+	}
+
+	#[test]
+	fn vorbis_stream(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Both links are conformed to the first one's format a packet at a time, just like a whole sound
+		let sound = Sound::new(Codec::Vorbis, "test/sounds/chained.ogg").unwrap();
+		let mut music = Music::new(Codec::Vorbis, "test/sounds/chained.ogg").unwrap();
+		assert_eq!(music.format(), Format::new(1, 8000));
+		assert_eq!(&drain(&mut music)[..], sound.data());
+		assert!(music.is_finished());
+		assert_eq!(music.position(), sound.frames());
+
+		// Seeking into the middle of the first link picks up right where the sound is
+		music.seek(640).unwrap();
+		assert_eq!(music.position(), 640);
+		assert_eq!(&drain(&mut music)[..], &sound.data()[640..]);
+		assert!(music.is_finished());
+
+		// Seeking into the second link may round each packet it converts differently than the sound did
+		music.seek(1400).unwrap();
+		let rest = drain(&mut music);
+		let expected = sound.frames() - 1400;
+		assert!(rest.len() + 8 >= expected && rest.len() <= expected + 8);
+		assert!(rest.iter().all(|sample| *sample == 0));
+		assert_eq!(music.position(), 1400 + rest.len());
+
+		// And back to the start again
+		music.rewind().unwrap();
+		assert_eq!(&drain(&mut music)[..], sound.data());
+	}
+}
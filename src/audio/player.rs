@@ -0,0 +1,144 @@
+use super::{Codec, Music, Mixer, VoiceHandle, AudioError};
+use super::mixer::MUSIC_BUS;
+
+/// A piece of music, along with how it should loop
+#[derive(Clone, PartialEq, Debug)]
+pub struct Track{
+	pub path:  String,
+	pub codec: Codec,
+
+	pub looping:    bool,
+	pub loop_start: usize,         /* Frame the loop goes back to, anything before it plays once as an intro */
+	pub loop_end:   Option<usize>, /* Frame the loop ends at, or the end of the track                     */
+
+	pub volume: f32
+}
+impl Track{
+	/// A track that loops in its entirety
+	pub fn new(codec: Codec, path: &str) -> Track{
+		Track{
+			path:  path.to_owned(),
+			codec: codec,
+
+			looping:    true,
+			loop_start: 0,
+			loop_end:   None,
+
+			volume: 1.0
+		}
+	}
+}
+
+/// Plays one track at a time on the music bus, crossfading whenever the track changes
+pub struct MusicPlayer{
+	current:   Option<(VoiceHandle, Track)>,
+	crossfade: f32 /* Seconds it takes for one track to fade into the next */
+}
+impl MusicPlayer{
+	pub fn new() -> MusicPlayer{
+		MusicPlayer{
+			current:   None,
+			crossfade: 1.0
+		}
+	}
+
+	pub fn crossfade(&self) -> f32{ self.crossfade }
+	pub fn set_crossfade(&mut self, seconds: f32){ self.crossfade = seconds }
+
+	/// The track currently playing, if any and if it hasn't ended
+	pub fn current(&mut self, mixer: &Mixer) -> Option<&Track>{
+		if let Some((voice, _)) = self.current{
+			if !mixer.is_playing(voice) { self.current = None }
+		}
+		self.current.as_ref().map(|&(_, ref track)| track)
+	}
+
+	/// Crossfades into a track, unless it's already the one playing
+	pub fn play(&mut self, mixer: &mut Mixer, track: Track) -> Result<(), AudioError>{
+		let seconds = self.crossfade;
+		self.play_with_fade(mixer, track, seconds)
+	}
+
+	pub fn play_with_fade(&mut self, mixer: &mut Mixer, track: Track, seconds: f32) -> Result<(), AudioError>{
+		if self.current(mixer) == Some(&track) { return Ok(()) }
+
+		let music = try!(Music::new(track.codec, &track.path));
+		let voice = mixer.play_music(music);
+		mixer.route(voice, MUSIC_BUS);
+		mixer.set_looping(voice, track.looping);
+		mixer.set_loop_region(voice, track.loop_start, track.loop_end);
+
+		if seconds > 0.0{
+			mixer.set_voice_volume(voice, 0.0);
+			mixer.fade(voice, track.volume, seconds);
+		}else{
+			mixer.set_voice_volume(voice, track.volume);
+		}
+
+		self.stop(mixer, seconds);
+		self.current = Some((voice, track));
+		Ok(())
+	}
+
+	/// Fades the current track out over some seconds, cutting it right away if zero
+	pub fn stop(&mut self, mixer: &mut Mixer, seconds: f32){
+		if let Some((voice, _)) = self.current.take(){
+			if seconds > 0.0 { mixer.fade_out(voice, seconds) }
+			else { mixer.stop(voice) }
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::{MusicPlayer, Track};
+	use super::super::{Mixer, Codec, Format};
+
+	/* Writes a temporary Wave file filled with a constant, returning its path */
+	fn constant(name: &str, value: i16) -> String{
+		use super::super::hound::{WavWriter, WavSpec, SampleFormat};
+		use std::env;
+		let mut path = env::temp_dir();
+		path.push(name);
+		let path = path.to_str().unwrap().to_owned();
+
+		let mut writer = WavWriter::create(&path, WavSpec{
+			channels: 1,
+			sample_rate: 100,
+			bits_per_sample: 16,
+			sample_format: SampleFormat::Int
+		}).unwrap();
+		for _ in 0..16{ writer.write_sample(value).unwrap(); }
+		writer.finalize().unwrap();
+
+		path
+	}
+
+	#[test]
+	fn crossfade(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut mixer = Mixer::new(Format::new(1, 100));
+		let mut player = MusicPlayer::new();
+		player.set_crossfade(0.04);
+
+		let first  = Track::new(Codec::Wave, &constant("bbman_player_first.wav",  1000));
+		let second = Track::new(Codec::Wave, &constant("bbman_player_second.wav", 2000));
+
+		player.play(&mut mixer, first.clone()).unwrap();
+		let mut output = [0i16; 6];
+		mixer.render(&mut output);
+		assert_eq!(output, [250, 500, 750, 1000, 1000, 1000]);
+
+		// Asking for the same track again keeps it playing as it was
+		player.play(&mut mixer, first.clone()).unwrap();
+		assert_eq!(mixer.voices(), 1);
+
+		player.play(&mut mixer, second.clone()).unwrap();
+		mixer.render(&mut output);
+		assert_eq!(output, [1250, 1500, 1750, 2000, 2000, 2000]);
+		assert_eq!(mixer.voices(), 1);
+		assert_eq!(player.current(&mixer), Some(&second));
+	}
+}
@@ -0,0 +1,243 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use cgmath::Point2;
+
+use super::{Mixer, VoiceHandle};
+use super::super::Camera;
+
+/// How an emitter's volume falls off as it moves away from the listener
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Attenuation{
+	/// Always heard at full volume
+	None,
+	/// Falls in a straight line from full volume at the minimum distance to silence at the maximum
+	Linear,
+	/// Falls with the inverse of the distance, scaled by a rolloff factor
+	Inverse(f32),
+	/// Falls with the distance raised to the negative of a rolloff factor
+	Exponential(f32)
+}
+impl Attenuation{
+	/**
+	 * Calculates the gain at a given distance.
+	 *
+	 * Distances are clamped between the minimum and the maximum, so nothing gets louder
+	 * than full volume when close, and sounds stop fading past the maximum distance.
+	 */
+	pub fn gain(&self, distance: f32, min: f32, max: f32) -> f32{
+		let min = if min > 0.0 { min } else { ::std::f32::EPSILON };
+		let distance = if distance < min { min } else if distance > max { max } else { distance };
+
+		match *self{
+			Attenuation::None => 1.0,
+			Attenuation::Linear => if max > min { 1.0 - (distance - min) / (max - min) } else { 1.0 },
+			Attenuation::Inverse(rolloff) => min / (min + rolloff * (distance - min)),
+			Attenuation::Exponential(rolloff) => (distance / min).powf(-rolloff)
+		}
+	}
+}
+
+/// A voice positioned somewhere in the world
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Emitter{
+	pub position: Point2<f32>,
+	pub attenuation: Attenuation,
+
+	pub min_distance: f32, /* Distance up to which the emitter plays at full volume */
+	pub max_distance: f32, /* Distance after which the emitter stops fading         */
+
+	pub volume: f32 /* Volume before any attenuation */
+}
+impl Emitter{
+	pub fn new(x: f32, y: f32) -> Emitter{
+		Emitter{
+			position: Point2::new(x, y),
+			attenuation: Attenuation::Inverse(1.0),
+
+			min_distance: 1.0,
+			max_distance: 32.0,
+
+			volume: 1.0
+		}
+	}
+}
+
+/* Where the listener is, either put there or following a camera around */
+enum Listener{
+	Point(Point2<f32>),
+	Camera(Rc<RefCell<Camera>>)
+}
+
+/// Keeps the gain and pan of positioned voices in line with where the listener is
+pub struct Spatializer{
+	listener: Listener,
+	spread: f32, /* Horizontal distance at which emitters are panned all the way */
+
+	emitters: Vec<(VoiceHandle, Emitter)>
+}
+impl Spatializer{
+	pub fn new() -> Spatializer{
+		Spatializer{
+			listener: Listener::Point(Point2::new(0.0, 0.0)),
+			spread: 8.0,
+
+			emitters: Vec::new()
+		}
+	}
+
+	pub fn listener(&self) -> Point2<f32>{
+		match self.listener{
+			Listener::Point(point) => point,
+			Listener::Camera(ref camera) => {
+				let camera = camera.borrow();
+				Point2::new(camera.position().x, camera.position().y)
+			}
+		}
+	}
+
+	/// Places the listener at an explicit point, no longer following a camera
+	pub fn listen_from(&mut self, x: f32, y: f32){ self.listener = Listener::Point(Point2::new(x, y)) }
+
+	/// Has the listener follow the camera's translation, picking it up again on every update
+	pub fn set_listener_from(&mut self, camera: Rc<RefCell<Camera>>){ self.listener = Listener::Camera(camera) }
+
+	pub fn spread(&self) -> f32{ self.spread }
+	pub fn set_spread(&mut self, spread: f32){ self.spread = spread }
+
+	/// Positions a voice, replacing the emitter it had before, if any
+	pub fn attach(&mut self, voice: VoiceHandle, emitter: Emitter){
+		self.detach(voice);
+		self.emitters.push((voice, emitter));
+	}
+
+	/// Stops positioning a voice, leaving it with its current gain and pan
+	pub fn detach(&mut self, voice: VoiceHandle){
+		self.emitters.retain(|&(handle, _)| handle != voice);
+	}
+
+	pub fn emitter_mut(&mut self, voice: VoiceHandle) -> Option<&mut Emitter>{
+		self.emitters.iter_mut().find(|&&mut (handle, _)| handle == voice).map(|&mut (_, ref mut emitter)| emitter)
+	}
+
+	pub fn move_emitter(&mut self, voice: VoiceHandle, x: f32, y: f32){
+		if let Some(emitter) = self.emitter_mut(voice){ emitter.position = Point2::new(x, y) }
+	}
+
+	/// Updates the gain and pan of every positioned voice, forgetting the ones that stopped
+	pub fn update(&mut self, mixer: &mut Mixer){
+		self.emitters.retain(|&(handle, _)| mixer.is_playing(handle));
+
+		let listener = self.listener();
+		for &(handle, ref emitter) in &self.emitters{
+			let dx = emitter.position.x - listener.x;
+			let dy = emitter.position.y - listener.y;
+			let distance = (dx * dx + dy * dy).sqrt();
+
+			let gain = emitter.attenuation.gain(distance, emitter.min_distance, emitter.max_distance);
+			mixer.set_voice_gain(handle, emitter.volume * gain);
+			mixer.set_pan(handle, if self.spread > 0.0 { dx / self.spread } else { 0.0 });
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use std::rc::Rc;
+	use std::cell::RefCell;
+	use super::{Attenuation, Emitter, Spatializer};
+	use super::super::{Mixer, Sound, Format};
+	use super::super::super::Camera;
+
+	#[test]
+	fn attenuation(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		assert_eq!(Attenuation::Linear.gain(0.0, 2.0, 10.0), 1.0);
+		assert_eq!(Attenuation::Linear.gain(6.0, 2.0, 10.0), 0.5);
+		assert_eq!(Attenuation::Linear.gain(20.0, 2.0, 10.0), 0.0);
+
+		assert_eq!(Attenuation::Inverse(1.0).gain(4.0, 2.0, 10.0), 0.5);
+		assert_eq!(Attenuation::Inverse(1.0).gain(100.0, 2.0, 10.0), 0.2);
+		assert_eq!(Attenuation::Exponential(2.0).gain(4.0, 2.0, 10.0), 0.25);
+		assert_eq!(Attenuation::None.gain(100.0, 2.0, 10.0), 1.0);
+	}
+
+	#[test]
+	fn positioned_voices(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 16]));
+		let mut mixer = Mixer::new(Format::new(2, 100));
+		let mut spatial = Spatializer::new();
+		spatial.listen_from(10.0, 10.0);
+
+		// Panned all the way right, about halfway into the linear falloff
+		let voice = mixer.play(sound.clone());
+		let mut emitter = Emitter::new(18.0, 10.0);
+		emitter.attenuation = Attenuation::Linear;
+		emitter.max_distance = 16.0;
+		spatial.attach(voice, emitter);
+		spatial.update(&mut mixer);
+
+		let mut output = [0i16; 2];
+		mixer.render(&mut output);
+		assert_eq!(output, [0, 533]);
+
+		// Moving the listener on top of it centers it at full volume
+		spatial.listen_from(18.0, 10.0);
+		spatial.update(&mut mixer);
+		mixer.render(&mut output);
+		assert_eq!(output, [1000, 1000]);
+
+		// Positioning doesn't get in the way of fades
+		mixer.fade(voice, 0.0, 0.04);
+		spatial.update(&mut mixer);
+		mixer.render(&mut output);
+		assert_eq!(output, [750, 750]);
+		spatial.update(&mut mixer);
+		mixer.render(&mut output);
+		assert_eq!(output, [500, 500]);
+
+		mixer.stop(voice);
+		spatial.update(&mut mixer);
+		assert!(spatial.emitter_mut(voice).is_none());
+	}
+
+	#[test]
+	fn camera_listener(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use cgmath;
+		let sound = Rc::new(Sound::from_samples(Format::new(1, 100), vec![1000; 16]));
+		let mut mixer = Mixer::new(Format::new(2, 100));
+		let mut spatial = Spatializer::new();
+		let camera = Rc::new(RefCell::new(Camera::new(cgmath::ortho(0.0, 1280.0, 720.0, 0.0, 1.0, -1.0))));
+		spatial.set_listener_from(camera.clone());
+
+		let voice = mixer.play(sound);
+		let mut emitter = Emitter::new(18.0, 10.0);
+		emitter.attenuation = Attenuation::Linear;
+		emitter.max_distance = 16.0;
+		spatial.attach(voice, emitter);
+
+		// The listener keeps up with the camera as it moves past the emitter
+		let mut output = [0i16; 2];
+		for &(x, expected) in &[(10.0, [0, 533]), (18.0, [1000, 1000]), (26.0, [533, 0])]{
+			camera.borrow_mut().translate(x, 10.0, 0.0);
+			spatial.update(&mut mixer);
+			assert_eq!(spatial.listener(), cgmath::Point2::new(x, 10.0));
+			mixer.render(&mut output);
+			assert_eq!(output, expected);
+		}
+
+		// Until it's placed somewhere on its own
+		spatial.listen_from(10.0, 10.0);
+		camera.borrow_mut().translate(18.0, 10.0, 0.0);
+		spatial.update(&mut mixer);
+		mixer.render(&mut output);
+		assert_eq!(output, [0, 533]);
+	}
+}
@@ -0,0 +1,408 @@
+use std::f32::consts::PI;
+use json::{self, JsonValue};
+
+use super::{Sound, Format};
+use super::dsp::{self, Biquad};
+
+/// Basic shapes the synthesizer's oscillator can take
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Waveform{ Square, Sawtooth, Sine, Triangle, Noise }
+impl Waveform{
+	pub fn name(&self) -> &'static str{
+		match *self{
+			Waveform::Square   => "square",
+			Waveform::Sawtooth => "sawtooth",
+			Waveform::Sine     => "sine",
+			Waveform::Triangle => "triangle",
+			Waveform::Noise    => "noise"
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Waveform>{
+		match name{
+			"square"   => Some(Waveform::Square),
+			"sawtooth" => Some(Waveform::Sawtooth),
+			"sine"     => Some(Waveform::Sine),
+			"triangle" => Some(Waveform::Triangle),
+			"noise"    => Some(Waveform::Noise),
+			_ => None
+		}
+	}
+}
+
+/// Kinds of sounds the randomizer knows how to make
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Preset{ PickupCoin, LaserShoot, Explosion, PowerUp, HitHurt, Jump, BlipSelect }
+
+/// Everything that describes a synthesized sound effect, in the spirit of sfxr
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SynthParams{
+	pub waveform: Waveform,
+
+	pub attack:  f32, /* Seconds it takes to reach full volume                  */
+	pub sustain: f32, /* Seconds spent at full volume                           */
+	pub punch:   f32, /* Extra volume at the start of the sustain, fading out   */
+	pub decay:   f32, /* Seconds it takes to fade into silence                  */
+
+	pub frequency:     f32, /* Starting pitch, in Hz                              */
+	pub min_frequency: f32, /* Pitch under which the sound is cut off, in Hz      */
+	pub slide:         f32, /* Pitch change, in octaves per second                */
+	pub delta_slide:   f32, /* Change of the slide, in octaves per second squared */
+
+	pub vibrato_depth: f32, /* Pitch wobble, as a fraction of the frequency */
+	pub vibrato_speed: f32, /* Wobbles per second                           */
+
+	pub duty:       f32, /* Fraction of a square wave's period spent high */
+	pub duty_sweep: f32, /* Duty change per second                        */
+	pub noise:      f32, /* How much noise is mixed into the other waveforms, from 0.0 to 1.0 */
+
+	pub low_pass: f32, /* Cutoff of a final low-pass filter in Hz, or zero for none */
+	pub volume:   f32
+}
+impl Default for SynthParams{
+	fn default() -> SynthParams{
+		SynthParams{
+			waveform: Waveform::Square,
+
+			attack:  0.0,
+			sustain: 0.1,
+			punch:   0.0,
+			decay:   0.2,
+
+			frequency:     440.0,
+			min_frequency: 0.0,
+			slide:         0.0,
+			delta_slide:   0.0,
+
+			vibrato_depth: 0.0,
+			vibrato_speed: 0.0,
+
+			duty:       0.5,
+			duty_sweep: 0.0,
+			noise:      0.0,
+
+			low_pass: 0.0,
+			volume:   0.5
+		}
+	}
+}
+
+/* Xorshift generator, so presets come out the same for the same seed on every platform */
+struct Rng(u32);
+impl Rng{
+	fn new(seed: u32) -> Rng{ Rng(if seed == 0 { 0x9E3779B9 } else { seed }) }
+
+	fn next(&mut self) -> u32{
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 17;
+		self.0 ^= self.0 << 5;
+		self.0
+	}
+
+	/* Uniform float in [0.0, 1.0) */
+	fn float(&mut self) -> f32{ (self.next() >> 8) as f32 / (1 << 24) as f32 }
+	fn range(&mut self, min: f32, max: f32) -> f32{ min + (max - min) * self.float() }
+	fn chance(&mut self, probability: f32) -> bool{ self.float() < probability }
+}
+
+impl SynthParams{
+	/// Rolls a random sound of the given kind, always the same one for the same seed
+	pub fn preset(preset: Preset, seed: u32) -> SynthParams{
+		let mut rng = Rng::new(seed);
+		let mut params = SynthParams::default();
+
+		match preset{
+			Preset::PickupCoin => {
+				params.frequency = rng.range(600.0, 1400.0);
+				params.sustain   = rng.range(0.02, 0.1);
+				params.punch     = rng.range(0.3, 0.6);
+				params.decay     = rng.range(0.1, 0.3);
+			},
+			Preset::LaserShoot => {
+				params.waveform = match rng.next() % 3{ 0 => Waveform::Square, 1 => Waveform::Sawtooth, _ => Waveform::Sine };
+				params.frequency     = rng.range(500.0, 1500.0);
+				params.min_frequency = rng.range(40.0, 120.0);
+				params.slide         = -rng.range(3.0, 8.0);
+				params.duty          = rng.range(0.2, 0.5);
+				params.duty_sweep    = rng.range(-0.5, 0.5);
+				params.sustain       = rng.range(0.05, 0.15);
+				params.decay         = rng.range(0.05, 0.2);
+			},
+			Preset::Explosion => {
+				params.waveform  = Waveform::Noise;
+				params.frequency = rng.range(80.0, 400.0);
+				params.slide     = -rng.range(0.0, 1.5);
+				params.sustain   = rng.range(0.1, 0.3);
+				params.punch     = rng.range(0.2, 0.8);
+				params.decay     = rng.range(0.3, 0.8);
+				if rng.chance(0.5){
+					params.vibrato_depth = rng.range(0.1, 0.4);
+					params.vibrato_speed = rng.range(4.0, 12.0);
+				}
+				params.low_pass = rng.range(1500.0, 6000.0);
+			},
+			Preset::PowerUp => {
+				params.waveform = if rng.chance(0.5) { Waveform::Square } else { Waveform::Sawtooth };
+				params.frequency = rng.range(200.0, 600.0);
+				params.slide     = rng.range(1.0, 3.0);
+				if rng.chance(0.5){
+					params.vibrato_depth = rng.range(0.02, 0.1);
+					params.vibrato_speed = rng.range(5.0, 15.0);
+				}
+				params.sustain = rng.range(0.1, 0.3);
+				params.decay   = rng.range(0.2, 0.5);
+			},
+			Preset::HitHurt => {
+				params.waveform = match rng.next() % 3{ 0 => Waveform::Square, 1 => Waveform::Sawtooth, _ => Waveform::Noise };
+				params.frequency = rng.range(100.0, 500.0);
+				params.slide     = -rng.range(2.0, 5.0);
+				params.sustain   = rng.range(0.02, 0.06);
+				params.decay     = rng.range(0.1, 0.25);
+				if rng.chance(0.5) { params.low_pass = rng.range(1000.0, 4000.0) }
+			},
+			Preset::Jump => {
+				params.frequency = rng.range(250.0, 600.0);
+				params.slide     = rng.range(1.0, 3.0);
+				params.duty      = rng.range(0.1, 0.5);
+				params.sustain   = rng.range(0.05, 0.15);
+				params.decay     = rng.range(0.1, 0.2);
+			},
+			Preset::BlipSelect => {
+				params.waveform  = if rng.chance(0.5) { Waveform::Square } else { Waveform::Sine };
+				params.frequency = rng.range(400.0, 1200.0);
+				params.duty      = rng.range(0.2, 0.5);
+				params.sustain   = rng.range(0.02, 0.08);
+				params.decay     = rng.range(0.01, 0.05);
+			}
+		}
+
+		params
+	}
+
+	/// Length of the envelope, in seconds
+	pub fn duration(&self) -> f32{ self.attack + self.sustain + self.decay }
+
+	/**
+	 * Renders the sound effect as a mono sound at the given sample rate.
+	 *
+	 * The sound lasts for the whole envelope, unless the pitch slides below the
+	 * minimum frequency, in which case it's cut short right there.
+	 */
+	pub fn synthesize(&self, rate: usize) -> Sound{
+		let format = Format::new(1, rate);
+		let rate = rate as f32;
+		let length = (self.duration() * rate).round() as usize;
+
+		let mut rng = Rng::new(0x5EED);
+		let mut noise = [0.0f32; 32];
+		for n in noise.iter_mut(){ *n = rng.range(-1.0, 1.0) }
+
+		let mut data = Vec::with_capacity(length);
+		let (mut phase, mut octaves, mut slide, mut duty) = (0.0f32, 0.0f32, self.slide, self.duty);
+		for i in 0..length{
+			let time = i as f32 / rate;
+
+			let mut frequency = self.frequency * octaves.exp2();
+			if frequency < self.min_frequency { break }
+			frequency *= 1.0 + self.vibrato_depth * (2.0 * PI * self.vibrato_speed * time).sin();
+
+			let envelope = if time < self.attack { time / self.attack }
+				else if time < self.attack + self.sustain { 1.0 + self.punch * (1.0 - (time - self.attack) / self.sustain) }
+				else { 1.0 - (time - self.attack - self.sustain) / self.decay };
+
+			let noise_sample = noise[(phase * 32.0) as usize % 32];
+			let wave = match self.waveform{
+				Waveform::Square   => if phase < duty { 1.0 } else { -1.0 },
+				Waveform::Sawtooth => 1.0 - 2.0 * phase,
+				Waveform::Sine     => (2.0 * PI * phase).sin(),
+				Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+				Waveform::Noise    => noise_sample
+			};
+			let sample = if self.waveform == Waveform::Noise { wave } else { wave * (1.0 - self.noise) + noise_sample * self.noise };
+
+			let value = sample * envelope * self.volume * 32767.0;
+			data.push(if value >= 32767.0 { 32767 } else if value <= -32768.0 { -32768 } else { value.round() as i16 });
+
+			// Step the oscillator, rolling new noise every period
+			phase += frequency / rate;
+			if phase >= 1.0{
+				phase -= phase.floor();
+				for n in noise.iter_mut(){ *n = rng.range(-1.0, 1.0) }
+			}
+
+			octaves += slide / rate;
+			slide   += self.delta_slide / rate;
+			duty = duty + self.duty_sweep / rate;
+			duty = if duty < 0.0 { 0.0 } else if duty > 1.0 { 1.0 } else { duty };
+		}
+
+		if self.low_pass > 0.0{
+			data = dsp::apply(&data, format, &mut Biquad::low_pass(self.low_pass, 0.707));
+		}
+		Sound::from_samples(format, data)
+	}
+
+	pub fn to_json_value(&self) -> JsonValue{
+		let mut value = JsonValue::new_object();
+		value["waveform"] = self.waveform.name().into();
+
+		value["attack"]  = number(self.attack);
+		value["sustain"] = number(self.sustain);
+		value["punch"]   = number(self.punch);
+		value["decay"]   = number(self.decay);
+
+		value["frequency"]     = number(self.frequency);
+		value["min_frequency"] = number(self.min_frequency);
+		value["slide"]         = number(self.slide);
+		value["delta_slide"]   = number(self.delta_slide);
+
+		value["vibrato_depth"] = number(self.vibrato_depth);
+		value["vibrato_speed"] = number(self.vibrato_speed);
+
+		value["duty"]       = number(self.duty);
+		value["duty_sweep"] = number(self.duty_sweep);
+		value["noise"]      = number(self.noise);
+
+		value["low_pass"] = number(self.low_pass);
+		value["volume"]   = number(self.volume);
+		value
+	}
+
+	/// Serializes the parameters into a human friendly JSON document
+	pub fn to_json(&self) -> String{ self.to_json_value().pretty(4) }
+
+	pub fn from_json(json: &str) -> Option<SynthParams>{
+		match json::parse(json){
+			Ok(parsed) => SynthParams::from_json_value(&parsed),
+			Err(what) => { error!("Could not parse synthesizer JSON data: {:?}", what); None }
+		}
+	}
+
+	/// Reads parameters back from JSON, leaving the defaults in place of missing fields
+	pub fn from_json_value(value: &JsonValue) -> Option<SynthParams>{
+		if !value.is_object(){
+			error!("Synthesizer parameters must be a JSON object");
+			return None
+		}
+
+		let mut params = SynthParams::default();
+		if !value["waveform"].is_null(){
+			params.waveform = match value["waveform"].as_str().and_then(Waveform::from_name){
+				Some(waveform) => waveform,
+				None => {
+					error!("Unknown synthesizer waveform: {}", value["waveform"].dump());
+					return None
+				}
+			};
+		}
+
+		{
+			let mut fields: [(&str, &mut f32); 15] = [
+				("attack", &mut params.attack), ("sustain", &mut params.sustain),
+				("punch",  &mut params.punch),  ("decay",   &mut params.decay),
+
+				("frequency", &mut params.frequency), ("min_frequency", &mut params.min_frequency),
+				("slide",     &mut params.slide),     ("delta_slide",   &mut params.delta_slide),
+
+				("vibrato_depth", &mut params.vibrato_depth), ("vibrato_speed", &mut params.vibrato_speed),
+
+				("duty",  &mut params.duty), ("duty_sweep", &mut params.duty_sweep),
+				("noise", &mut params.noise),
+
+				("low_pass", &mut params.low_pass), ("volume", &mut params.volume)
+			];
+			for &mut (name, ref mut field) in fields.iter_mut(){
+				if value[name].is_null() { continue }
+				match value[name].as_f64(){
+					Some(number) => **field = number as f32,
+					None => {
+						error!(r#"Synthesizer parameter "{}" is not a number"#, name);
+						return None
+					}
+				}
+			}
+		}
+
+		Some(params)
+	}
+}
+
+/* Numbers are stored as f64, as f32 doesn't make it through the JSON crate unchanged */
+fn number(value: f32) -> JsonValue{ (value as f64).into() }
+
+#[cfg(test)]
+mod tests{
+	use super::{SynthParams, Waveform, Preset};
+
+	#[test]
+	fn square(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut params = SynthParams::default();
+		params.frequency = 125.0;
+		params.sustain   = 0.1;
+		params.decay     = 0.0;
+		params.volume    = 1.0;
+
+		let sound = params.synthesize(1000);
+		assert_eq!(sound.frames(), 100);
+		assert_eq!(&sound.data()[..8], &[32767, 32767, 32767, 32767, -32767, -32767, -32767, -32767]);
+
+		// Sliding down past the minimum frequency cuts the sound short
+		params.slide = -125.0;
+		params.min_frequency = 62.5;
+		assert_eq!(params.synthesize(1000).frames(), 9);
+	}
+
+	#[test]
+	fn envelope(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut params = SynthParams::default();
+		params.frequency = 0.0;
+		params.attack    = 0.004;
+		params.sustain   = 0.002;
+		params.decay     = 0.004;
+		params.volume    = 1.0;
+
+		let sound = params.synthesize(1000);
+		assert_eq!(sound.data(), &[0, 8192, 16384, 24575, 32767, 32767, 32767, 24575, 16384, 8192]);
+	}
+
+	#[test]
+	fn presets(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let explosion = SynthParams::preset(Preset::Explosion, 42);
+		assert_eq!(explosion, SynthParams::preset(Preset::Explosion, 42));
+		assert!(explosion != SynthParams::preset(Preset::Explosion, 43));
+		assert_eq!(explosion.waveform, Waveform::Noise);
+		assert_eq!(explosion.synthesize(22050).data(), SynthParams::preset(Preset::Explosion, 42).synthesize(22050).data());
+
+		for preset in &[Preset::PickupCoin, Preset::LaserShoot, Preset::PowerUp, Preset::HitHurt, Preset::Jump, Preset::BlipSelect]{
+			let sound = SynthParams::preset(*preset, 7).synthesize(22050);
+			assert!(sound.frames() > 0);
+			assert!(sound.data().iter().any(|s| *s != 0));
+		}
+	}
+
+	#[test]
+	fn json(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let params = SynthParams::preset(Preset::LaserShoot, 1234);
+		assert_eq!(SynthParams::from_json(&params.to_json()), Some(params));
+
+		let partial = SynthParams::from_json(r#"{ "waveform": "triangle", "frequency": 220 }"#).unwrap();
+		assert_eq!(partial.waveform, Waveform::Triangle);
+		assert_eq!(partial.frequency, 220.0);
+		assert_eq!(partial.decay, SynthParams::default().decay);
+
+		assert_eq!(SynthParams::from_json(r#"{ "waveform": "kazoo" }"#), None);
+		assert_eq!(SynthParams::from_json(r#"{ "slide": "fast" }"#), None);
+	}
+}
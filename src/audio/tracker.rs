@@ -0,0 +1,1259 @@
+use std::cmp::{min, max};
+
+use super::{Format, AudioError};
+
+/// Sample rate modules are rendered at when decoded or streamed through a `Codec`
+pub const TRACKER_RATE: usize = 44100;
+
+/* Keeps a handful of loud channels from clipping right away */
+const AMPLIFICATION: f32 = 0.5;
+
+/* Note value that releases the key instead of playing anything */
+const KEY_OFF: u8 = 97;
+
+/* Half a period of the sine used by vibrato and tremolo, as found in ProTracker */
+const SINE: [u8; 32] = [
+	  0,  24,  49,  74,  97, 120, 141, 161, 180, 197, 212, 224, 235, 244, 250, 253,
+	255, 253, 250, 244, 235, 224, 212, 197, 180, 161, 141, 120,  97,  74,  49,  24
+];
+
+/// Formats a module can be loaded from
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ModuleKind{
+	/// ProTracker and compatible modules, with 31 samples
+	Mod,
+	/// FastTracker II extended modules
+	Xm
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+struct Cell{
+	note:       u8, /* 1 to 96 from C-0, KEY_OFF, or 0 for none */
+	instrument: u8, /* 1 based, or 0 for none                   */
+	volume:     u8, /* XM volume column, or 0 for none          */
+	effect:     u8,
+	param:      u8
+}
+
+struct Pattern{
+	rows:  usize,
+	cells: Vec<Cell> /* Row after row, a cell for every channel */
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum LoopKind{ None, Forward, PingPong }
+
+struct Sample{
+	data: Vec<f32>,
+	loop_start: usize,
+	loop_end:   usize,
+	looping: LoopKind,
+
+	volume:        i32, /* From 0 to 64                      */
+	finetune:      i32, /* In 128ths of a semitone           */
+	panning:       i32, /* From 0 (left) to 255 (right)      */
+	relative_note: i32  /* Semitones added to every note     */
+}
+
+/* Volume or panning curve of an instrument, in ticks, disabled when it has no points */
+#[derive(Clone, Default, Debug)]
+struct Envelope{
+	points:  Vec<(u16, u16)>,
+	sustain: Option<usize>,
+	repeat:  Option<(usize, usize)>
+}
+impl Envelope{
+	fn enabled(&self) -> bool{ !self.points.is_empty() }
+
+	/* Value of the envelope at a tick, from 0.0 to 1.0 */
+	fn value(&self, tick: u16) -> f32{
+		let last = match self.points.last(){ Some(last) => *last, None => return 1.0 };
+		if tick >= last.0 { return last.1 as f32 / 64.0 }
+
+		for pair in self.points.windows(2){
+			let (a, b) = (pair[0], pair[1]);
+			if tick < b.0 && b.0 > a.0{
+				let fract = (tick.saturating_sub(a.0)) as f32 / (b.0 - a.0) as f32;
+				return (a.1 as f32 + (b.1 as f32 - a.1 as f32) * fract) / 64.0
+			}
+		}
+		self.points[0].1 as f32 / 64.0
+	}
+
+	/* Tick the envelope moves to next, holding at the sustain point while the key is down */
+	fn advance(&self, tick: u16, key_on: bool) -> u16{
+		if let (true, Some(sustain)) = (key_on, self.sustain){
+			if tick == self.points[sustain].0 { return tick }
+		}
+
+		let mut tick = tick.saturating_add(1);
+		if let Some((start, end)) = self.repeat{
+			if tick >= self.points[end].0 { tick = self.points[start].0 }
+		}
+		min(tick, self.points[self.points.len() - 1].0)
+	}
+}
+
+struct Instrument{
+	samples: Vec<Sample>,
+	keymap: Vec<u8>, /* Sample played by each of the 96 notes */
+
+	volume_envelope:  Envelope,
+	panning_envelope: Envelope,
+	fadeout: i32 /* Volume lost every tick after the key is released, out of 32768 */
+}
+
+/// A tracker module, parsed and ready to be played by a `ModulePlayer`
+pub struct Module{
+	title: String,
+	kind: ModuleKind,
+	channels: usize,
+	linear: bool, /* Whether pitches use linear periods instead of Amiga ones */
+
+	orders:      Vec<usize>,
+	patterns:    Vec<Pattern>,
+	instruments: Vec<Instrument>,
+
+	speed: usize, /* Initial ticks per row                       */
+	tempo: usize  /* Initial tempo, in beats per minute (of 24 ticks) */
+}
+
+/* Bounds checked reads out of a module */
+struct Bytes<'a>(&'a [u8]);
+impl<'a> Bytes<'a>{
+	fn slice(&self, at: usize, length: usize) -> Result<&'a [u8], AudioError>{
+		if at + length <= self.0.len() { Ok(&self.0[at..at + length]) }
+		else {
+			error!("Module is truncated, expected {} bytes at offset {} but it ends at {}", length, at, self.0.len());
+			Err(AudioError::CorruptStream)
+		}
+	}
+
+	fn u8(&self, at: usize) -> Result<u8, AudioError>{ self.slice(at, 1).map(|b| b[0]) }
+	fn u16_le(&self, at: usize) -> Result<u16, AudioError>{ self.slice(at, 2).map(|b| b[0] as u16 | (b[1] as u16) << 8) }
+	fn u16_be(&self, at: usize) -> Result<u16, AudioError>{ self.slice(at, 2).map(|b| (b[0] as u16) << 8 | b[1] as u16) }
+	fn u32_le(&self, at: usize) -> Result<u32, AudioError>{
+		self.slice(at, 4).map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+	}
+}
+
+/* Turns a space or null padded name into a string */
+fn name(bytes: &[u8]) -> String{
+	String::from_utf8_lossy(bytes).trim_right_matches(|c| c == '\0' || c == ' ').to_owned()
+}
+
+/* Number of channels of a ProTracker module, given the signature at offset 1080 */
+fn mod_channels(signature: &[u8]) -> Option<usize>{
+	match signature{
+		b"M.K." | b"M!K!" | b"FLT4" | b"4CHN" => Some(4),
+		b"6CHN" => Some(6),
+		b"8CHN" | b"FLT8" | b"OKTA" | b"CD81" => Some(8),
+		_ => {
+			let digit = |b: u8| if b >= b'0' && b <= b'9' { Some((b - b'0') as usize) } else { None };
+			match (&signature[2..], digit(signature[0]), digit(signature[1])){
+				(b"CH", Some(tens), Some(units)) if tens * 10 + units > 0 => Some(tens * 10 + units),
+				(b"CN", Some(tens), Some(units)) if tens * 10 + units > 0 => Some(tens * 10 + units),
+				_ => None
+			}
+		}
+	}
+}
+
+impl Module{
+	/// Whether the data looks like a module this player understands
+	pub fn detect(data: &[u8]) -> bool{
+		data.starts_with(b"Extended Module: ") || (data.len() >= 1084 && mod_channels(&data[1080..1084]).is_some())
+	}
+
+	pub fn parse(data: &[u8]) -> Result<Module, AudioError>{
+		if data.starts_with(b"Extended Module: ") { Module::parse_xm(data) }
+		else if data.len() >= 1084 && mod_channels(&data[1080..1084]).is_some() { Module::parse_mod(data) }
+		else {
+			error!("Data is neither a ProTracker nor a FastTracker II module");
+			Err(AudioError::UnsupportedFormat)
+		}
+	}
+
+	pub fn title(&self) -> &str{ &self.title }
+	pub fn kind(&self) -> ModuleKind{ self.kind }
+	pub fn channels(&self) -> usize{ self.channels }
+
+	/// Number of entries in the order list
+	pub fn length(&self) -> usize{ self.orders.len() }
+
+	fn parse_mod(data: &[u8]) -> Result<Module, AudioError>{
+		let bytes = Bytes(data);
+		let channels = mod_channels(&data[1080..1084]).unwrap_or(4);
+
+		let mut samples = Vec::new();
+		for i in 0..31{
+			let header = 20 + i * 30;
+			let finetune = (try!(bytes.u8(header + 24)) & 0x0F) as i32;
+			let loop_start  = try!(bytes.u16_be(header + 26)) as usize * 2;
+			let loop_length = try!(bytes.u16_be(header + 28)) as usize * 2;
+
+			samples.push((try!(bytes.u16_be(header + 22)) as usize * 2, Sample{
+				data: Vec::new(),
+				loop_start: loop_start,
+				loop_end:   loop_start + loop_length,
+				looping: if loop_length > 2 { LoopKind::Forward } else { LoopKind::None },
+
+				volume:        min(try!(bytes.u8(header + 25)) as i32, 64),
+				finetune:      (if finetune > 7 { finetune - 16 } else { finetune }) * 16,
+				panning:       128,
+				relative_note: 0
+			}));
+		}
+
+		let length = min(try!(bytes.u8(950)) as usize, 128);
+		let table = try!(bytes.slice(952, 128));
+		let orders = table[..length].iter().map(|order| *order as usize).collect::<Vec<usize>>();
+
+		// Every entry of the order table counts, even the ones past the song's length
+		let pattern_count = table.iter().map(|order| *order as usize + 1).max().unwrap_or(0);
+		let mut patterns = Vec::with_capacity(pattern_count);
+		for pattern in 0..pattern_count{
+			let raw = try!(bytes.slice(1084 + pattern * 64 * channels * 4, 64 * channels * 4));
+			patterns.push(Pattern{
+				rows: 64,
+				cells: raw.chunks(4).map(|cell| {
+					let period = ((cell[0] & 0x0F) as u16) << 8 | cell[1] as u16;
+					Cell{
+						note:       if period > 0 { period_to_note(period) } else { 0 },
+						instrument: (cell[0] & 0xF0) | cell[2] >> 4,
+						volume:     0,
+						effect:     cell[2] & 0x0F,
+						param:      cell[3]
+					}
+				}).collect()
+			});
+		}
+
+		// Sample data follows the patterns, and is often cut short in the wild
+		let mut offset = 1084 + pattern_count * 64 * channels * 4;
+		let mut instruments = Vec::with_capacity(31);
+		for (length, mut sample) in samples{
+			let end = min(offset + length, data.len());
+			if end > offset { sample.data = data[offset..end].iter().map(|b| *b as i8 as f32 / 128.0).collect() }
+			offset += length;
+
+			sample.loop_end = min(sample.loop_end, sample.data.len());
+			if sample.loop_start >= sample.loop_end { sample.looping = LoopKind::None }
+
+			instruments.push(Instrument{
+				samples: vec![sample],
+				keymap:  vec![0; 96],
+
+				volume_envelope:  Envelope::default(),
+				panning_envelope: Envelope::default(),
+				fadeout: 0
+			});
+		}
+
+		Ok(Module{
+			title: name(try!(bytes.slice(0, 20))),
+			kind: ModuleKind::Mod,
+			channels: channels,
+			linear: false,
+
+			orders:      orders,
+			patterns:    patterns,
+			instruments: instruments,
+
+			speed: 6,
+			tempo: 125
+		})
+	}
+
+	fn parse_xm(data: &[u8]) -> Result<Module, AudioError>{
+		let bytes = Bytes(data);
+		let version = try!(bytes.u16_le(58));
+		if version < 0x0104 { warn!("XM version {:x} is older than 1.04, it may not load correctly", version) }
+
+		let header_size   = try!(bytes.u32_le(60)) as usize;
+		let length        = try!(bytes.u16_le(64)) as usize;
+		let channels      = try!(bytes.u16_le(68)) as usize;
+		let pattern_count = try!(bytes.u16_le(70)) as usize;
+		let instrument_count = try!(bytes.u16_le(72)) as usize;
+		if channels == 0 || channels > 64 || length > 256{
+			error!("XM module has an invalid layout: {} channels and {} orders", channels, length);
+			return Err(AudioError::UnsupportedFormat)
+		}
+		let orders = try!(bytes.slice(80, length)).iter().map(|order| *order as usize).collect();
+
+		let mut offset = 60 + header_size;
+		let mut patterns = Vec::with_capacity(pattern_count);
+		for _ in 0..pattern_count{
+			let header = try!(bytes.u32_le(offset)) as usize;
+			let rows   = min(max(try!(bytes.u16_le(offset + 5)) as usize, 1), 256);
+			let packed = try!(bytes.slice(offset + header, try!(bytes.u16_le(offset + 7)) as usize));
+			offset += header + packed.len();
+
+			let mut cells = vec![Cell::default(); rows * channels];
+			let mut i = 0;
+			for cell in cells.iter_mut(){
+				if i >= packed.len() { break }
+
+				// A set high bit tells which fields follow, otherwise all five are there
+				let flags = if packed[i] & 0x80 != 0 { i += 1; packed[i - 1] } else { 0x1F };
+				let mut field = |bit: u8| if flags & bit != 0 && i < packed.len() { i += 1; packed[i - 1] } else { 0 };
+				cell.note       = field(0x01);
+				cell.instrument = field(0x02);
+				cell.volume     = field(0x04);
+				cell.effect     = field(0x08);
+				cell.param      = field(0x10);
+			}
+			patterns.push(Pattern{ rows: rows, cells: cells });
+		}
+
+		let mut instruments = Vec::with_capacity(instrument_count);
+		for _ in 0..instrument_count{
+			let size = try!(bytes.u32_le(offset)) as usize;
+			let sample_count = try!(bytes.u16_le(offset + 27)) as usize;
+			if sample_count == 0{
+				offset += size;
+				instruments.push(Instrument{
+					samples: Vec::new(),
+					keymap:  vec![0; 96],
+
+					volume_envelope:  Envelope::default(),
+					panning_envelope: Envelope::default(),
+					fadeout: 0
+				});
+				continue
+			}
+
+			let sample_header = try!(bytes.u32_le(offset + 29)) as usize;
+			let keymap = try!(bytes.slice(offset + 33, 96)).to_vec();
+			let (volume_envelope, panning_envelope) = {
+				let envelope = |points: usize, count: usize, settings: usize, kind: usize| -> Result<Envelope, AudioError>{
+					let kind  = try!(bytes.u8(offset + kind));
+					let count = min(try!(bytes.u8(offset + count)) as usize, 12);
+					if kind & 1 == 0 || count == 0 { return Ok(Envelope::default()) }
+
+					let mut envelope = Envelope::default();
+					for point in 0..count{
+						let at = offset + points + point * 4;
+						envelope.points.push((try!(bytes.u16_le(at)), min(try!(bytes.u16_le(at + 2)), 64)));
+					}
+
+					let sustain = try!(bytes.u8(offset + settings)) as usize;
+					let (start, end) = (try!(bytes.u8(offset + settings + 1)) as usize, try!(bytes.u8(offset + settings + 2)) as usize);
+					if kind & 2 != 0 && sustain < count { envelope.sustain = Some(sustain) }
+					if kind & 4 != 0 && start <= end && end < count { envelope.repeat = Some((start, end)) }
+					Ok(envelope)
+				};
+				(try!(envelope(129, 225, 227, 233)), try!(envelope(177, 226, 230, 234)))
+			};
+			let fadeout = try!(bytes.u16_le(offset + 239)) as i32;
+			offset += size;
+
+			// Every sample header comes first, then the data of every sample
+			let headers = offset;
+			offset += sample_count * sample_header;
+
+			let mut samples = Vec::with_capacity(sample_count);
+			for s in 0..sample_count{
+				let header = headers + s * sample_header;
+				let length = try!(bytes.u32_le(header)) as usize;
+				let kind   = try!(bytes.u8(header + 14));
+				let raw = try!(bytes.slice(offset, length));
+				offset += length;
+
+				// Samples are stored as deltas between consecutive values
+				let sixteen = kind & 0x10 != 0;
+				let data = if sixteen{
+					let mut value = 0i16;
+					raw.chunks(2).filter(|pair| pair.len() == 2).map(|pair| {
+						value = value.wrapping_add((pair[0] as u16 | (pair[1] as u16) << 8) as i16);
+						value as f32 / 32768.0
+					}).collect::<Vec<f32>>()
+				}else{
+					let mut value = 0i8;
+					raw.iter().map(|byte| {
+						value = value.wrapping_add(*byte as i8);
+						value as f32 / 128.0
+					}).collect::<Vec<f32>>()
+				};
+
+				let unit = if sixteen { 2 } else { 1 };
+				let loop_start = try!(bytes.u32_le(header + 4)) as usize / unit;
+				let loop_end   = min(loop_start + try!(bytes.u32_le(header + 8)) as usize / unit, data.len());
+				samples.push(Sample{
+					looping: match kind & 3{
+						_ if loop_start >= loop_end => LoopKind::None,
+						1 => LoopKind::Forward,
+						2 => LoopKind::PingPong,
+						_ => LoopKind::None
+					},
+					data: data,
+					loop_start: loop_start,
+					loop_end:   loop_end,
+
+					volume:        min(try!(bytes.u8(header + 12)) as i32, 64),
+					finetune:      try!(bytes.u8(header + 13)) as i8 as i32,
+					panning:       try!(bytes.u8(header + 15)) as i32,
+					relative_note: try!(bytes.u8(header + 16)) as i8 as i32
+				});
+			}
+
+			instruments.push(Instrument{
+				samples: samples,
+				keymap:  keymap,
+
+				volume_envelope:  volume_envelope,
+				panning_envelope: panning_envelope,
+				fadeout: fadeout
+			});
+		}
+
+		Ok(Module{
+			title: name(try!(bytes.slice(17, 20))),
+			kind: ModuleKind::Xm,
+			channels: channels,
+			linear: try!(bytes.u16_le(74)) & 1 != 0,
+
+			orders:      orders,
+			patterns:    patterns,
+			instruments: instruments,
+
+			speed: max(try!(bytes.u16_le(76)) as usize, 1),
+			tempo: max(try!(bytes.u16_le(78)) as usize, 32)
+		})
+	}
+
+	/* Period of a note, counting semitones from C-0, in the units the module uses */
+	fn period(&self, note: i32, finetune: i32) -> f32{
+		if self.linear { 7680.0 - note as f32 * 64.0 - finetune as f32 / 2.0 }
+		else { 1712.0 * ((48 - note) as f32 / 12.0 - finetune as f32 / 1536.0).exp2() }
+	}
+
+	/* Playback frequency of a sample at a period, shifted by some semitones */
+	fn frequency(&self, period: f32, semitones: i32) -> f32{
+		let base = if self.linear { 8363.0 * ((4608.0 - period) / 768.0).exp2() } else { 8363.0 * 1712.0 / period };
+		base * (semitones as f32 / 12.0).exp2()
+	}
+}
+
+/* Finds the note closest to a ProTracker period, where C-2 (428) maps to C-4 */
+fn period_to_note(period: u16) -> u8{
+	let semitone = 48.0 - 12.0 * (period as f32 * 4.0 / 1712.0).log2();
+	let semitone = semitone.round();
+	if semitone < 0.0 { 1 } else if semitone > 95.0 { 96 } else { semitone as u8 + 1 }
+}
+
+/* Vibrato and tremolo waveforms, from -255 to 255 over 64 steps */
+fn oscillator(waveform: u8, position: u8) -> i32{
+	let position = position & 63;
+	match waveform & 3{
+		1 => 255 - position as i32 * 8,
+		2 => if position < 32 { 255 } else { -255 },
+		_ => if position < 32 { SINE[position as usize] as i32 } else { -(SINE[position as usize - 32] as i32) }
+	}
+}
+
+fn clamp(value: i32, low: i32, high: i32) -> i32{ if value < low { low } else if value > high { high } else { value } }
+
+#[derive(Clone, Default)]
+struct Channel{
+	instrument: Option<usize>,
+	sample:     Option<usize>,
+	active: bool,
+
+	position:  f64,
+	backwards: bool, /* Direction of ping-pong loops */
+
+	note:     i32, /* Semitones from C-0, relative note included */
+	finetune: i32,
+	period:   f32,
+	target:   f32, /* Period a tone portamento slides to */
+
+	volume:  i32,
+	panning: i32,
+	key_on:  bool,
+	fadeout: i32,
+	volume_tick:  u16,
+	panning_tick: u16,
+
+	// Modulations that only last for the current tick
+	arpeggio:  i32,
+	vibrato:   f32,
+	tremolo:   i32,
+
+	// Parameters remembered by effects that reuse the last non-zero one
+	porta_up:   u8,
+	porta_down: u8,
+	tone_speed: u8,
+	vibrato_speed: u8,
+	vibrato_depth: u8,
+	vibrato_position: u8,
+	vibrato_waveform: u8,
+	tremolo_speed: u8,
+	tremolo_depth: u8,
+	tremolo_position: u8,
+	tremolo_waveform: u8,
+	volume_slide:  u8,
+	panning_slide: u8,
+	global_slide:  u8,
+	fine_porta_up:   u8,
+	fine_porta_down: u8,
+	fine_volume_up:   u8,
+	fine_volume_down: u8,
+	extra_fine_up:   u8,
+	extra_fine_down: u8,
+	offset:  u8,
+	retrigger: u8,
+	retrigger_ticks: u8,
+
+	cell: Cell
+}
+
+/* Remembers an effect parameter, giving back the last non-zero one when it's zero */
+fn remember(memory: &mut u8, param: u8) -> u8{
+	if param != 0 { *memory = param }
+	*memory
+}
+
+impl Channel{
+	fn sample<'a>(&self, module: &'a Module) -> Option<&'a Sample>{
+		match (self.instrument, self.sample){
+			(Some(instrument), Some(sample)) => module.instruments.get(instrument).and_then(|i| i.samples.get(sample)),
+			_ => None
+		}
+	}
+
+	fn trigger(&mut self, module: &Module, cell: Cell){
+		let tone_porta = cell.effect == 0x3 || cell.effect == 0x5 || cell.volume >= 0xF0;
+
+		if cell.instrument > 0{
+			self.instrument = if (cell.instrument as usize) <= module.instruments.len() { Some(cell.instrument as usize - 1) } else { None };
+		}
+
+		if cell.note == KEY_OFF{
+			self.key_off(module);
+		}else if cell.note > 0 && cell.note < KEY_OFF{
+			let note = cell.note as usize - 1;
+			let sample = self.instrument
+				.and_then(|instrument| module.instruments.get(instrument))
+				.and_then(|instrument| match instrument.keymap.get(note){
+					Some(sample) if (*sample as usize) < instrument.samples.len() => Some(*sample as usize),
+					_ => None
+				});
+
+			match (sample, tone_porta && self.active){
+				(Some(sample), true) => {
+					let relative = module.instruments[self.instrument.unwrap()].samples[sample].relative_note;
+					self.target = module.period(note as i32 + relative, self.finetune);
+				},
+				(Some(sample), false) => {
+					self.sample = Some(sample);
+					let (relative, finetune) = {
+						let sample = &module.instruments[self.instrument.unwrap()].samples[sample];
+						(sample.relative_note, sample.finetune)
+					};
+					self.note = note as i32 + relative;
+					self.finetune = finetune;
+					if cell.effect == 0xE && cell.param >> 4 == 0x5 { self.finetune = ((cell.param & 0x0F) as i32 - 8) * 16 }
+					self.period = module.period(self.note, self.finetune);
+					self.target = self.period;
+
+					self.position  = 0.0;
+					self.backwards = false;
+					self.active    = true;
+					if self.vibrato_waveform & 4 == 0 { self.vibrato_position = 0 }
+					if self.tremolo_waveform & 4 == 0 { self.tremolo_position = 0 }
+
+					if cell.effect == 0x9{
+						self.position = remember(&mut self.offset, cell.param) as f64 * 256.0;
+						let length = self.sample(module).map(|s| s.data.len()).unwrap_or(0);
+						if self.position >= length as f64 { self.active = false }
+					}
+				},
+				(None, _) => self.active = false
+			}
+		}
+
+		// Picking an instrument resets the volume, panning and envelopes
+		if cell.instrument > 0 && cell.note != KEY_OFF{
+			if let Some((volume, panning)) = self.sample(module).map(|s| (s.volume, s.panning)){
+				self.volume  = volume;
+				if module.kind == ModuleKind::Xm { self.panning = panning }
+			}
+			self.key_on  = true;
+			self.fadeout = 32768;
+			self.volume_tick  = 0;
+			self.panning_tick = 0;
+		}
+	}
+
+	fn key_off(&mut self, module: &Module){
+		self.key_on = false;
+
+		// Without an envelope to fade out with, the note is cut right away
+		let enveloped = self.instrument.and_then(|i| module.instruments.get(i)).map(|i| i.volume_envelope.enabled()).unwrap_or(false);
+		if !enveloped { self.volume = 0 }
+	}
+
+	fn slide_volume(&mut self, param: u8){
+		let (up, down) = ((param >> 4) as i32, (param & 0x0F) as i32);
+		self.volume = clamp(self.volume + if up > 0 { up } else { -down }, 0, 64);
+	}
+
+	fn slide_panning(&mut self, param: u8){
+		let (right, left) = ((param >> 4) as i32, (param & 0x0F) as i32);
+		self.panning = clamp(self.panning + if right > 0 { right } else { -left }, 0, 255);
+	}
+
+	fn porta(&mut self, amount: f32){
+		self.period = if self.period + amount < 1.0 { 1.0 } else if self.period + amount > 32000.0 { 32000.0 } else { self.period + amount };
+	}
+
+	fn tone_porta(&mut self){
+		let speed = self.tone_speed as f32 * 4.0;
+		if self.period < self.target { self.period = if self.period + speed > self.target { self.target } else { self.period + speed } }
+		else if self.period > self.target { self.period = if self.period - speed < self.target { self.target } else { self.period - speed } }
+	}
+
+	fn vibrate(&mut self){
+		self.vibrato = (oscillator(self.vibrato_waveform, self.vibrato_position) * self.vibrato_depth as i32) as f32 / 32.0;
+		self.vibrato_position = self.vibrato_position.wrapping_add(self.vibrato_speed) & 63;
+	}
+
+	/* Effects that happen once, at the start of a row */
+	fn row_effects(&mut self, cell: Cell){
+		// Modulations only carry on if the effect that started them does
+		self.arpeggio = 0;
+		if cell.effect != 0x4 && cell.effect != 0x6 && cell.volume >> 4 != 0xB { self.vibrato = 0.0 }
+		if cell.effect != 0x7 { self.tremolo = 0 }
+
+		match cell.volume >> 4{
+			0x1...0x5 => self.volume = min(cell.volume as i32 - 0x10, 64),
+			0x8 => self.volume = clamp(self.volume - (cell.volume & 0x0F) as i32, 0, 64),
+			0x9 => self.volume = clamp(self.volume + (cell.volume & 0x0F) as i32, 0, 64),
+			0xA => self.vibrato_speed = (cell.volume & 0x0F) * 4,
+			0xB => if cell.volume & 0x0F != 0 { self.vibrato_depth = cell.volume & 0x0F },
+			0xC => self.panning = (cell.volume & 0x0F) as i32 * 17,
+			0xF => if cell.volume & 0x0F != 0 { self.tone_speed = (cell.volume & 0x0F) << 4 },
+			_ => {}
+		}
+
+		let (x, y) = (cell.param >> 4, cell.param & 0x0F);
+		match cell.effect{
+			0x1 => { remember(&mut self.porta_up, cell.param); },
+			0x2 => { remember(&mut self.porta_down, cell.param); },
+			0x3 => { remember(&mut self.tone_speed, cell.param); },
+			0x4 => {
+				if x != 0 { self.vibrato_speed = x }
+				if y != 0 { self.vibrato_depth = y }
+			},
+			0x5 | 0x6 | 0xA => { remember(&mut self.volume_slide, cell.param); },
+			0x7 => {
+				if x != 0 { self.tremolo_speed = x }
+				if y != 0 { self.tremolo_depth = y }
+			},
+			0x8 => self.panning = cell.param as i32,
+			0xC => self.volume = min(cell.param as i32, 64),
+			0xE => match x{
+				0x1 => { let amount = remember(&mut self.fine_porta_up, y); self.porta(-4.0 * amount as f32) },
+				0x2 => { let amount = remember(&mut self.fine_porta_down, y); self.porta(4.0 * amount as f32) },
+				0x4 => self.vibrato_waveform = y,
+				0x7 => self.tremolo_waveform = y,
+				0x8 => self.panning = y as i32 * 17,
+				0xA => { let amount = remember(&mut self.fine_volume_up, y); self.volume = min(self.volume + amount as i32, 64) },
+				0xB => { let amount = remember(&mut self.fine_volume_down, y); self.volume = max(self.volume - amount as i32, 0) },
+				0xC => if y == 0 { self.volume = 0 },
+				_ => {}
+			},
+			0x11 => { remember(&mut self.global_slide, cell.param); },
+			0x15 => { self.volume_tick = cell.param as u16; self.panning_tick = cell.param as u16 },
+			0x19 => { remember(&mut self.panning_slide, cell.param); },
+			0x1B => {
+				if y != 0 { self.retrigger = (self.retrigger & 0xF0) | y }
+				if x != 0 { self.retrigger = (self.retrigger & 0x0F) | x << 4 }
+				self.retrigger_ticks = 0;
+			},
+			0x21 => match x{
+				0x1 => { let amount = remember(&mut self.extra_fine_up, y); self.porta(-(amount as f32)) },
+				0x2 => { let amount = remember(&mut self.extra_fine_down, y); self.porta(amount as f32) },
+				_ => {}
+			},
+			_ => {}
+		}
+	}
+
+	/* Effects that keep going on every tick but the first of a row */
+	fn tick_effects(&mut self, module: &Module, tick: usize){
+		let cell = self.cell;
+		self.arpeggio = 0;
+
+		match cell.volume >> 4{
+			0x6 => self.volume = max(self.volume - (cell.volume & 0x0F) as i32, 0),
+			0x7 => self.volume = min(self.volume + (cell.volume & 0x0F) as i32, 64),
+			0xB => self.vibrate(),
+			0xD => self.panning = max(self.panning - (cell.volume & 0x0F) as i32, 0),
+			0xE => self.panning = min(self.panning + (cell.volume & 0x0F) as i32, 255),
+			0xF => self.tone_porta(),
+			_ => {}
+		}
+
+		let (x, y) = (cell.param >> 4, cell.param & 0x0F);
+		match cell.effect{
+			0x0 if cell.param != 0 => self.arpeggio = [0, x as i32, y as i32][tick % 3],
+			0x1 => { let amount = self.porta_up as f32;   self.porta(-4.0 * amount) },
+			0x2 => { let amount = self.porta_down as f32; self.porta(4.0 * amount) },
+			0x3 => self.tone_porta(),
+			0x4 => self.vibrate(),
+			0x5 => { self.tone_porta(); let slide = self.volume_slide; self.slide_volume(slide) },
+			0x6 => { self.vibrate();    let slide = self.volume_slide; self.slide_volume(slide) },
+			0x7 => {
+				self.tremolo = oscillator(self.tremolo_waveform, self.tremolo_position) * self.tremolo_depth as i32 / 64;
+				self.tremolo_position = self.tremolo_position.wrapping_add(self.tremolo_speed) & 63;
+			},
+			0xA => { let slide = self.volume_slide; self.slide_volume(slide) },
+			0xE => match x{
+				0x9 => if y != 0 && tick % y as usize == 0 { self.position = 0.0; self.backwards = false; self.active = self.sample(module).is_some() },
+				0xC => if tick == y as usize { self.volume = 0 },
+				0xD => if tick == y as usize { self.trigger(module, cell); self.row_effects(Cell{ note: 0, instrument: 0, ..cell }) },
+				_ => {}
+			},
+			0x14 => if tick == cell.param as usize { self.key_off(module) },
+			0x19 => { let slide = self.panning_slide; self.slide_panning(slide) },
+			0x1B => {
+				self.retrigger_ticks += 1;
+				if self.retrigger & 0x0F != 0 && self.retrigger_ticks >= self.retrigger & 0x0F{
+					self.retrigger_ticks = 0;
+					self.position = 0.0;
+					self.backwards = false;
+					self.active = self.sample(module).is_some();
+					self.volume = clamp(match self.retrigger >> 4{
+						0x1...0x5 => self.volume - (1 << (self.retrigger >> 4) - 1),
+						0x6 => self.volume * 2 / 3,
+						0x7 => self.volume / 2,
+						0x9...0xD => self.volume + (1 << (self.retrigger >> 4) - 9),
+						0xE => self.volume * 3 / 2,
+						0xF => self.volume * 2,
+						_ => self.volume
+					}, 0, 64);
+				}
+			},
+			_ => {}
+		}
+	}
+
+	/* Moves the envelopes and the fadeout along by one tick */
+	fn update_envelopes(&mut self, module: &Module){
+		let instrument = match self.instrument.and_then(|i| module.instruments.get(i)){ Some(i) => i, None => return };
+
+		if instrument.volume_envelope.enabled(){
+			self.volume_tick = instrument.volume_envelope.advance(self.volume_tick, self.key_on);
+			if !self.key_on { self.fadeout = max(self.fadeout - instrument.fadeout, 0) }
+		}
+		if instrument.panning_envelope.enabled(){
+			self.panning_tick = instrument.panning_envelope.advance(self.panning_tick, self.key_on);
+		}
+	}
+
+	/* Gains for the left and right channels, with everything but the global volume applied */
+	fn gains(&self, module: &Module) -> (f32, f32){
+		let instrument = self.instrument.and_then(|i| module.instruments.get(i));
+
+		let mut volume = clamp(self.volume + self.tremolo, 0, 64) as f32 / 64.0;
+		let mut panning = self.panning as f32;
+		if let Some(instrument) = instrument{
+			if instrument.volume_envelope.enabled(){
+				volume *= instrument.volume_envelope.value(self.volume_tick) * self.fadeout as f32 / 32768.0;
+			}
+			if instrument.panning_envelope.enabled(){
+				let swing = 128.0 - (panning - 128.0).abs();
+				panning += (instrument.panning_envelope.value(self.panning_tick) - 0.5) * 2.0 * swing;
+			}
+		}
+
+		let panning = if panning < 0.0 { 0.0 } else if panning > 255.0 { 255.0 } else { panning };
+		(volume * (256.0 - panning) / 256.0, volume * panning / 256.0)
+	}
+}
+
+/**
+ * Plays a `Module`, rendering it into interleaved stereo samples.
+ *
+ * The song ends once the order list runs out or a jump goes back to a row that
+ * already played, so looping is left to whoever plays it, just like any other stream.
+ */
+pub struct ModulePlayer{
+	module: Module,
+	rate: usize,
+
+	speed: usize,
+	tempo: usize,
+	global_volume: i32,
+
+	order: usize,
+	row:   usize,
+	tick:  usize,
+	remaining: f64, /* Frames left to render before the next tick */
+
+	pattern_delay: usize,
+	jump:       Option<usize>, /* Order a position jump goes to           */
+	break_row:  Option<usize>, /* Row a pattern break goes to             */
+	loop_start: usize,         /* Row a pattern loop goes back to         */
+	loop_count: usize,         /* Repetitions left in the pattern loop    */
+	loop_back:  bool,          /* Whether the next row comes from a loop  */
+	visited: Vec<bool>,        /* Rows reached so far, by order           */
+	finished: bool,
+
+	channels: Vec<Channel>,
+	mix: Vec<f32>
+}
+impl ModulePlayer{
+	pub fn new(module: Module, rate: usize) -> ModulePlayer{
+		let mut player = ModulePlayer{
+			rate: if rate > 0 { rate } else { TRACKER_RATE },
+
+			speed: module.speed,
+			tempo: module.tempo,
+			global_volume: 64,
+
+			order: 0,
+			row:   0,
+			tick:  0,
+			remaining: 0.0,
+
+			pattern_delay: 0,
+			jump:       None,
+			break_row:  None,
+			loop_start: 0,
+			loop_count: 0,
+			loop_back:  false,
+			visited: Vec::new(),
+			finished: false,
+
+			channels: Vec::new(),
+			mix: Vec::new(),
+
+			module: module
+		};
+		player.restart();
+		player
+	}
+
+	pub fn module(&self) -> &Module{ &self.module }
+	pub fn format(&self) -> Format{ Format::new(2, self.rate) }
+	pub fn is_finished(&self) -> bool{ self.finished }
+
+	/// Goes back to the start of the song
+	pub fn restart(&mut self){
+		self.speed = self.module.speed;
+		self.tempo = self.module.tempo;
+		self.global_volume = 64;
+
+		self.order = 0;
+		self.row   = 0;
+		self.tick  = 0;
+		self.remaining = 0.0;
+
+		self.pattern_delay = 0;
+		self.jump      = None;
+		self.break_row = None;
+		self.loop_start = 0;
+		self.loop_count = 0;
+		self.loop_back  = false;
+		self.visited = vec![false; self.module.orders.len() * 256];
+		if !self.visited.is_empty() { self.visited[0] = true }
+		self.finished = self.module.orders.is_empty();
+
+		// ProTracker channels are panned left, right, right, left, and so on
+		let kind = self.module.kind;
+		self.channels = (0..self.module.channels).map(|c| Channel{
+			panning: match kind{
+				ModuleKind::Mod => if c % 4 == 0 || c % 4 == 3 { 0x40 } else { 0xC0 },
+				ModuleKind::Xm  => 0x80
+			},
+			..Channel::default()
+		}).collect();
+	}
+
+	/**
+	 * Renders interleaved stereo samples into `output`, returning how many were written.
+	 * Fewer samples than asked for are only written once the song has ended.
+	 */
+	pub fn render(&mut self, output: &mut [i16]) -> usize{
+		let frames = output.len() / 2;
+		let mut frame = 0;
+		while frame < frames{
+			if self.remaining < 1.0{
+				if self.finished { break }
+				self.process_tick();
+				self.remaining += self.rate as f64 * 2.5 / self.tempo as f64;
+			}
+
+			let count = min(frames - frame, self.remaining as usize);
+			self.render_frames(&mut output[frame * 2..(frame + count) * 2]);
+			frame += count;
+			self.remaining -= count as f64;
+		}
+		frame * 2
+	}
+
+	fn render_frames(&mut self, output: &mut [i16]){
+		self.mix.clear();
+		self.mix.resize(output.len(), 0.0);
+
+		let global = self.global_volume as f32 / 64.0;
+		for channel in self.channels.iter_mut().filter(|channel| channel.active){
+			let (left, right) = channel.gains(&self.module);
+			let step = (self.module.frequency(channel.period + channel.vibrato, channel.arpeggio) / self.rate as f32) as f64;
+			let sample = match channel.sample(&self.module){ Some(sample) => sample, None => { channel.active = false; continue } };
+			let length = sample.data.len();
+
+			for frame in self.mix.chunks_mut(2){
+				if channel.position >= length as f64 || channel.position < 0.0 { channel.active = false; break }
+
+				// Interpolate towards the next sample, wrapping around forward loops
+				let index = channel.position.floor() as usize;
+				let next = if sample.looping == LoopKind::Forward && index + 1 >= sample.loop_end { sample.loop_start } else { min(index + 1, length - 1) };
+				let fract = (channel.position - index as f64) as f32;
+				let value = sample.data[index] + (sample.data[next] - sample.data[index]) * fract;
+
+				frame[0] += value * left * global;
+				frame[1] += value * right * global;
+
+				if channel.backwards { channel.position -= step } else { channel.position += step }
+				match sample.looping{
+					LoopKind::Forward => if channel.position >= sample.loop_end as f64{
+						let length = (sample.loop_end - sample.loop_start) as f64;
+						channel.position = sample.loop_start as f64 + (channel.position - sample.loop_end as f64) % length;
+					},
+					LoopKind::PingPong => {
+						let (start, end) = (sample.loop_start as f64, sample.loop_end as f64 - 1.0);
+						if !channel.backwards && channel.position > end { channel.position = end - (channel.position - end); channel.backwards = true }
+						if channel.backwards && channel.position < start { channel.position = start + (start - channel.position); channel.backwards = false }
+						if channel.position < start || channel.position > end { channel.position = start }
+					},
+					LoopKind::None => {}
+				}
+			}
+		}
+
+		for (o, s) in output.iter_mut().zip(&self.mix){
+			let value = *s * AMPLIFICATION * 32767.0;
+			*o = if value >= 32767.0 { 32767 } else if value <= -32768.0 { -32768 } else { value.round() as i16 };
+		}
+	}
+
+	fn process_tick(&mut self){
+		let speed = max(self.speed, 1);
+		if self.tick == 0{
+			self.process_row();
+		}else if self.tick % speed != 0{
+			let tick = self.tick % speed;
+			for channel in self.channels.iter_mut(){ channel.tick_effects(&self.module, tick) }
+
+			for channel in &self.channels{
+				if channel.cell.effect == 0x11{
+					let (up, down) = ((channel.global_slide >> 4) as i32, (channel.global_slide & 0x0F) as i32);
+					self.global_volume = clamp(self.global_volume + if up > 0 { up } else { -down }, 0, 64);
+				}
+			}
+		}
+
+		for channel in self.channels.iter_mut(){ channel.update_envelopes(&self.module) }
+
+		self.tick += 1;
+		if self.tick >= max(self.speed, 1) * (1 + self.pattern_delay) { self.advance() }
+	}
+
+	fn process_row(&mut self){
+		let index = self.module.orders[self.order];
+		for c in 0..self.channels.len(){
+			let cell = self.module.patterns.get(index).and_then(|pattern| pattern.cells.get(self.row * self.module.channels + c)).map(|cell| *cell).unwrap_or(Cell::default());
+			{
+				let channel = &mut self.channels[c];
+				channel.cell = cell;
+
+				// Delayed notes are triggered later on, by the delay effect itself
+				let delayed = cell.effect == 0xE && cell.param >> 4 == 0xD && cell.param & 0x0F != 0;
+				if !delayed { channel.trigger(&self.module, cell) }
+				channel.row_effects(cell);
+			}
+
+			let (x, y) = (cell.param >> 4, cell.param & 0x0F);
+			match cell.effect{
+				0xB => { self.jump = Some(cell.param as usize); self.break_row = self.break_row.or(Some(0)) },
+				0xD => self.break_row = Some(min(x as usize * 10 + y as usize, 255)),
+				0xE if x == 0x6 => {
+					if y == 0 { self.loop_start = self.row }
+					else if self.loop_count == 0 { self.loop_count = y as usize; self.loop_back = true }
+					else { self.loop_count -= 1; if self.loop_count > 0 { self.loop_back = true } }
+				},
+				0xE if x == 0xE => self.pattern_delay = y as usize,
+				0xF if cell.param > 0 => if cell.param < 0x20 { self.speed = cell.param as usize } else { self.tempo = cell.param as usize },
+				0x10 => self.global_volume = min(cell.param as i32, 64),
+				_ => {}
+			}
+		}
+	}
+
+	/* Number of rows in the pattern an order plays, patterns that don't exist count as empty */
+	fn rows(&self, order: usize) -> usize{
+		self.module.orders.get(order).and_then(|index| self.module.patterns.get(*index)).map(|pattern| pattern.rows).unwrap_or(64)
+	}
+
+	fn advance(&mut self){
+		self.tick = 0;
+		self.pattern_delay = 0;
+
+		if self.loop_back{
+			// Rows a pattern loop plays again don't mean the song has looped
+			self.loop_back = false;
+			for row in self.loop_start..self.row + 1 { self.visited[self.order * 256 + row] = false }
+			self.row = self.loop_start;
+		}else if self.jump.is_some() || self.break_row.is_some(){
+			let order = self.jump.take().unwrap_or(self.order + 1);
+			self.row = self.break_row.take().unwrap_or(0);
+			if order != self.order { self.loop_start = 0; self.loop_count = 0 }
+			self.order = order;
+		}else{
+			self.row += 1;
+			if self.row >= self.rows(self.order){
+				self.row = 0;
+				self.order += 1;
+				self.loop_start = 0;
+				self.loop_count = 0;
+			}
+		}
+
+		if self.order >= self.module.orders.len() { self.finished = true; return }
+		if self.row >= self.rows(self.order) { self.row = 0 }
+
+		// Going back to a row that already played means the song has looped
+		let visited = self.order * 256 + self.row;
+		if self.visited[visited] { self.finished = true }
+		else { self.visited[visited] = true }
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::{Module, ModulePlayer, ModuleKind, Envelope, TRACKER_RATE};
+	use super::super::{Sound, Music, Codec};
+
+	/* A ProTracker cell, playing the first sample at a period */
+	fn cell(period: u16, effect: u8, param: u8) -> [u8; 4]{
+		[(period >> 8) as u8, period as u8, if period > 0 { 0x10 } else { 0 } | effect, param]
+	}
+
+	/* A four channel module with a single pattern and a single sample */
+	fn protracker(sample: &[i8], cells: &[(usize, usize, [u8; 4])]) -> Vec<u8>{
+		let mut data = vec![0u8; 1084];
+		data[..4].copy_from_slice(b"test");
+
+		let words = (sample.len() / 2) as u16;
+		data[42] = (words >> 8) as u8;
+		data[43] = words as u8;
+		data[45] = 64;
+		data[48] = data[42];
+		data[49] = data[43];
+
+		data[950] = 1;
+		data[951] = 127;
+		data[1080..1084].copy_from_slice(b"M.K.");
+
+		let mut pattern = vec![0u8; 64 * 4 * 4];
+		for &(row, channel, ref cell) in cells{
+			let at = (row * 4 + channel) * 4;
+			pattern[at..at + 4].copy_from_slice(cell);
+		}
+		data.extend_from_slice(&pattern);
+		data.extend(sample.iter().map(|s| *s as u8));
+		data
+	}
+
+	fn render_all(player: &mut ModulePlayer) -> Vec<i16>{
+		let mut output = Vec::new();
+		let mut buffer = [0i16; 1000];
+		loop{
+			let count = player.render(&mut buffer);
+			output.extend_from_slice(&buffer[..count]);
+			if count < buffer.len() { return output }
+		}
+	}
+
+	#[test]
+	fn protracker_playback(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Middle C plays at 8363Hz, so rendering at that rate steps one sample per frame
+		let data = protracker(&[64, 64, -64, -64], &[(0, 0, cell(428, 0, 0)), (6, 0, cell(0, 0xC, 32))]);
+		let module = Module::parse(&data).unwrap();
+		assert_eq!(module.kind(), ModuleKind::Mod);
+		assert_eq!(module.title(), "test");
+		assert_eq!(module.channels(), 4);
+
+		let mut player = ModulePlayer::new(module, 8363);
+		let output = render_all(&mut player);
+		assert_eq!(&output[..8], &[6144, 2048, 6144, 2048, -6144, -2048, -6144, -2048]);
+
+		// The volume command on the seventh row halves the volume from then on
+		let row = (8363.0 * 2.5 / 125.0 * 6.0 * 6.0) as usize;
+		assert!(output[(row + 10) * 2..(row + 14) * 2].chunks(2).all(|frame| frame[0].abs() == 3072 && frame[1].abs() == 1024));
+
+		// A single pattern of 64 rows at speed 6
+		assert!(player.is_finished());
+		let expected = 8363.0 * 2.5 / 125.0 * 6.0 * 64.0;
+		assert!((output.len() as f64 / 2.0 - expected).abs() < 2.0);
+	}
+
+	#[test]
+	fn position_jump_ends_song(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let data = protracker(&[64, 64, -64, -64], &[(0, 0, cell(428, 0, 0)), (1, 0, cell(0, 0xB, 0))]);
+		let mut player = ModulePlayer::new(Module::parse(&data).unwrap(), 8363);
+		let output = render_all(&mut player);
+
+		let expected = 8363.0 * 2.5 / 125.0 * 6.0 * 2.0;
+		assert!((output.len() as f64 / 2.0 - expected).abs() < 2.0);
+
+		// Restarting plays the exact same thing again
+		player.restart();
+		assert_eq!(render_all(&mut player), output);
+	}
+
+	#[test]
+	fn pattern_loop(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// The first two rows play three times over, then the rest of the pattern follows
+		let data = protracker(&[64, 64, -64, -64], &[(0, 0, cell(428, 0xE, 0x60)), (1, 0, cell(0, 0xE, 0x62))]);
+		let mut player = ModulePlayer::new(Module::parse(&data).unwrap(), 8363);
+		let output = render_all(&mut player);
+
+		assert!(player.is_finished());
+		let expected = 8363.0 * 2.5 / 125.0 * 6.0 * 68.0;
+		assert!((output.len() as f64 / 2.0 - expected).abs() < 2.0);
+	}
+
+	/* An extended module with two channels, a two row pattern and a looping 16-bit sample */
+	fn extended() -> Vec<u8>{
+		fn u16(data: &mut Vec<u8>, value: u16){ data.push(value as u8); data.push((value >> 8) as u8) }
+		fn u32(data: &mut Vec<u8>, value: u32){ u16(data, value as u16); u16(data, (value >> 16) as u16) }
+
+		let mut data = b"Extended Module: extended test       \x1A".to_vec();
+		data.extend_from_slice(&[b' '; 20]);
+		u16(&mut data, 0x0104);
+		u32(&mut data, 276);
+		for value in &[1, 0, 2, 1, 1, 1, 1, 125]{ u16(&mut data, *value) }
+		data.extend_from_slice(&[0; 256]);
+
+		// Play middle C at half volume on the first row, release it on the second
+		let packed = [49, 1, 0x30, 0, 0, 0x80, 0x81, 97, 0x80];
+		u32(&mut data, 9);
+		data.push(0);
+		u16(&mut data, 2);
+		u16(&mut data, packed.len() as u16);
+		data.extend_from_slice(&packed);
+
+		u32(&mut data, 263);
+		data.extend_from_slice(&[0; 22]);
+		data.push(0);
+		u16(&mut data, 1);
+		u32(&mut data, 40);
+		data.extend_from_slice(&[0; 263 - 33]);
+
+		u32(&mut data, 8);
+		u32(&mut data, 0);
+		u32(&mut data, 8);
+		data.extend_from_slice(&[64, 0, 0x11, 128, 0, 0]);
+		data.extend_from_slice(&[0; 22]);
+		for delta in &[16384i16, 0, -32768, 0]{ u16(&mut data, *delta as u16) }
+
+		data
+	}
+
+	#[test]
+	fn extended_playback(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let module = Module::parse(&extended()).unwrap();
+		assert_eq!(module.kind(), ModuleKind::Xm);
+		assert_eq!(module.title(), "extended test");
+		assert_eq!(module.length(), 1);
+
+		let mut player = ModulePlayer::new(module, 8363);
+		let output = render_all(&mut player);
+		assert_eq!(&output[..8], &[2048, 2048, 2048, 2048, -2048, -2048, -2048, -2048]);
+
+		// Releasing a note without a volume envelope cuts it
+		let row = (8363.0 * 2.5 / 125.0) as usize;
+		assert!(output[(row + 2) * 2..].iter().all(|s| *s == 0));
+		assert!((output.len() as f64 / 2.0 - 2.0 * 8363.0 * 2.5 / 125.0).abs() < 2.0);
+	}
+
+	/* Plays a module from the test directory, checking it against a reference render at 11025Hz made by test/modules/reference.py */
+	fn reference(name: &str){
+		use std::fs::File;
+		use std::io::Read;
+		let mut data = Vec::new();
+		File::open(format!("test/modules/{}", name)).unwrap().read_to_end(&mut data).unwrap();
+		let reference = Sound::from_reader(Codec::Wave, File::open(format!("test/modules/{}.wav", name)).unwrap()).unwrap();
+		assert_eq!(reference.format().samplerate(), 11025);
+
+		let output = render_all(&mut ModulePlayer::new(Module::parse(&data).unwrap(), 11025));
+		assert_eq!(output.len(), reference.data().len());
+
+		// Allow for rounding, and for the playback position drifting by a tiny fraction of a sample
+		let error = output.iter().zip(reference.data()).map(|(a, b)| (*a as i32 - *b as i32).abs()).max().unwrap_or(0);
+		assert!(error <= 32, "{} strays from its reference by up to {}", name, error);
+	}
+
+	#[test]
+	fn reference_renders(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		reference("reference.mod");
+		reference("reference.xm");
+	}
+
+	#[test]
+	fn envelope_end(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Point ticks take the whole 16 bits, so the last one can't be stepped past
+		let envelope = Envelope{ points: vec![(0, 64), (65535, 0)], sustain: None, repeat: None };
+		assert_eq!(envelope.advance(65534, true), 65535);
+		assert_eq!(envelope.advance(65535, true), 65535);
+		assert_eq!(envelope.value(65535), 0.0);
+	}
+
+	#[test]
+	fn streaming(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use std::io::Cursor;
+		let data = protracker(&[64, 64, -64, -64], &[(0, 0, cell(428, 0, 0)), (3, 1, cell(214, 0x4, 0x48)), (4, 0, cell(0, 0xD, 0))]);
+		assert_eq!(Codec::detect(&data), Some(Codec::Tracker));
+		assert_eq!(Codec::detect(&extended()), Some(Codec::Tracker));
+
+		let sound = Sound::from_reader(Codec::Tracker, Cursor::new(data.clone())).unwrap();
+		assert_eq!(sound.format().samplerate(), TRACKER_RATE);
+		assert_eq!(sound.format().channels(), 2);
+
+		let mut music = Music::from_reader(Codec::Tracker, Cursor::new(data)).unwrap();
+		let mut streamed = vec![0i16; sound.data().len() + 100];
+		assert_eq!(music.fill(&mut streamed), sound.data().len());
+		assert_eq!(&streamed[..sound.data().len()], sound.data());
+
+		music.seek(1000).unwrap();
+		let mut buffer = [0i16; 64];
+		music.fill(&mut buffer);
+		assert_eq!(&buffer[..], &sound.data()[2000..2064]);
+	}
+}
@@ -11,7 +11,11 @@ pub struct VideoProfile{
 }
 
 use glium::Surface;
+use super::audio::Track;
 pub trait Scene{
+	/// Track the music player should switch to once the scene starts, or None to keep the current one
+	fn soundtrack(&self) -> Option<Track> { None }
+
 	fn pause(&mut self,     _: &mut Game) { }
 	fn unpause(&mut self,   _: &mut Game) { }
 	fn update(&mut self,    _: &mut Game, _: f64) { }
@@ -67,7 +71,9 @@ use std::any::Any;
 
 use glium::Frame;
 use glium::backend::glutin_backend::GlutinFacade;
-use super::graphics::Renderer2d;
+use glium::framebuffer::SimpleFrameBuffer;
+use super::graphics::{Renderer2d, RenderTarget, PostProcessor};
+use super::audio::{self, Output, Mixer, AudioBackend, NullBackend, Spatializer, MusicPlayer, AudioError};
 pub struct Game{
 	name:  String,
 	video: VideoProfile,  /* Video profile the game will be targetting */
@@ -77,6 +83,13 @@ pub struct Game{
 	flags: HashMap<String, Box<Any>>, /* Game-wide flags */
 	scene_queue: Vec<Box<Scene>>,     /* Buffer of scenes to succeed the current one once it finishes */
 	framebuffer: Option<Frame>,       /* Access to the default framebuffer */
+	canvas: RenderTarget,             /* Off-screen frame scenes can draw into */
+	canvas_drawn: bool,               /* Whether the canvas was drawn into this frame */
+	post: PostProcessor,              /* Passes the canvas goes through on its way to the window */
+	time: f64,                        /* Seconds the game has been running for */
+	audio: Output,                    /* Mixer and the backend it plays into */
+	spatializer: Spatializer,         /* Positions voices around the listener */
+	music: MusicPlayer,               /* Crossfades between soundtracks */
 
 	target: Target /* Target execution state */
 }
@@ -108,6 +121,10 @@ impl Game{
 			);
 		}
 
+		// Setup the off-screen canvas and its post processing
+		let canvas = RenderTarget::new(&context, video.width as u32, video.height as u32).expect("Could not create the game's canvas!");
+		let post   = PostProcessor::new(&context).expect("Could not create the game's post processor!");
+
 		Game{
 			name: name,
 			video: video,
@@ -117,6 +134,13 @@ impl Game{
 			flags: HashMap::new(),
 			scene_queue: Vec::new(),
 			framebuffer: None,
+			canvas: canvas,
+			canvas_drawn: false,
+			post: post,
+			time: 0.0,
+			audio: Output::new(Box::new(NullBackend::new(audio::Format::new(2, 44100)))),
+			spatializer: Spatializer::new(),
+			music: MusicPlayer::new(),
 			target: Target::None
 		}
 	}
@@ -135,6 +159,114 @@ impl Game{
 		)
 	}
 
+	/**
+	 * Gets the off-screen canvas, sized after the video profile, which gets run through
+	 * the post processor and stretched onto the window once render() returns.
+	 *
+	 * NOTE: Scenes drawing into the canvas should not draw into the framebuffer as well,
+	 * since the canvas gets drawn over the whole of it
+	 */
+	pub fn canvas(&mut self) -> SimpleFrameBuffer{
+		self.canvas_drawn = true;
+		self.canvas.surface()
+	}
+
+	/** The render target behind the canvas, for scenes that want to sample from it */
+	pub fn render_target(&self) -> &RenderTarget{ &self.canvas }
+
+	/** The chain of passes the canvas goes through, which can be configured here */
+	pub fn post_processor(&mut self) -> &mut PostProcessor{ &mut self.post }
+
+	/** Seconds the game has been running for, as seen by post processing passes */
+	pub fn time(&self) -> f64{ self.time }
+
+	/* Draws the canvas onto the framebuffer, if the scene drew anything into it */
+	fn present(&mut self){
+		if !self.canvas_drawn { return }
+		self.canvas_drawn = false;
+
+		if let Some(frame) = self.framebuffer.as_mut(){
+			if let Err(what) = self.post.apply(&self.window, self.canvas.texture(), frame, self.time as f32){
+				warn!("Could not post process frame: {:?}", what);
+			}
+		}
+	}
+
+	/** The mixer every sound in the game should be played through */
+	pub fn mixer(&mut self) -> &mut Mixer{ self.audio.mixer() }
+
+	/** Sets a game-wide flag, replacing whatever value it had before */
+	pub fn set_flag<T: Any>(&mut self, name: &str, value: T){
+		self.flags.insert(name.to_owned(), Box::new(value));
+	}
+
+	/** Gets a game-wide flag, if it exists and holds a value of the requested type */
+	pub fn flag<T: Any>(&self, name: &str) -> Option<&T>{
+		self.flags.get(name).and_then(|value| value.downcast_ref::<T>())
+	}
+
+	/**
+	 * Stores the volume and mute state of every mixer bus into the game flags,
+	 * as "audio.bus.<name>.volume" (f32) and "audio.bus.<name>.muted" (bool)
+	 */
+	pub fn store_bus_settings(&mut self){
+		let settings = self.audio.mixer().buses().iter()
+			.map(|bus| (bus.name().to_owned(), bus.volume(), bus.is_muted()))
+			.collect::<Vec<(String, f32, bool)>>();
+
+		for (name, volume, muted) in settings{
+			self.set_flag(&format!("audio.bus.{}.volume", name), volume);
+			self.set_flag(&format!("audio.bus.{}.muted",  name), muted);
+		}
+	}
+
+	/** Restores bus settings previously stored into the game flags */
+	pub fn restore_bus_settings(&mut self){
+		let names = self.audio.mixer().buses().iter()
+			.map(|bus| bus.name().to_owned())
+			.collect::<Vec<String>>();
+
+		for name in names{
+			let volume = self.flag::<f32>(&format!("audio.bus.{}.volume", name)).cloned();
+			let muted  = self.flag::<bool>(&format!("audio.bus.{}.muted",  name)).cloned();
+
+			if let Some(bus) = self.audio.mixer().bus_mut(&name){
+				if let Some(volume) = volume { bus.set_volume(volume) }
+				if let Some(muted)  = muted  { bus.set_muted(muted)   }
+			}
+		}
+	}
+
+	/** The music player, which can be configured here */
+	pub fn music_player(&mut self) -> &mut MusicPlayer{ &mut self.music }
+
+	/** Crossfades into a new track, unless it's already playing */
+	pub fn play_track(&mut self, track: Track) -> Result<(), AudioError>{
+		self.music.play(self.audio.mixer(), track)
+	}
+
+	/** Fades the current track out over the player's crossfade duration */
+	pub fn stop_track(&mut self){
+		let seconds = self.music.crossfade();
+		self.music.stop(self.audio.mixer(), seconds);
+	}
+
+	/** Positional audio, updated every frame right before the mixer plays */
+	pub fn spatializer(&mut self) -> &mut Spatializer{ &mut self.spatializer }
+
+	/**
+	 * Replaces the backend audio is played into.
+	 *
+	 * NOTE: This creates a new mixer in the backend's format,
+	 * so every voice playing at the moment gets dropped
+	 */
+	pub fn set_audio_backend(&mut self, backend: Box<AudioBackend>){
+		if let Err(what) = self.audio.finish(){
+			warn!("Could not finish the previous audio backend: {:?}", what);
+		}
+		self.audio = Output::new(backend);
+	}
+
 	pub fn queue_scene(&mut self, scene: Box<Scene>){
 		self.scene_queue.push(scene);
 	}
@@ -182,9 +314,17 @@ impl Runner{
 				let delta = self.timer.delta();
 				scene.update(&mut self.game, delta);
 
+				// Mix as much audio as the frame took
+				self.game.spatializer.update(self.game.audio.mixer());
+				if let Err(what) = self.game.audio.advance(delta){
+					warn!("Could not play audio for this frame: {:?}", what);
+				}
+
 				// Create a new frame, draw and dicard frame
+				self.game.time += delta;
 				self.game.framebuffer = Some(self.game.window.draw());
 				scene.render(&mut self.game);
+				self.game.present();
 
 				let _ = self.game.framebuffer.unwrap().finish();
 				self.game.framebuffer = None;
@@ -222,6 +362,13 @@ impl Runner{
 						info!("No more queued scenes, loading fallback scene");
 						Box::new(ErrorScene::new(&self.game)) as Box<Scene>
 					};
+
+					// Carry the music over, unless the scene asks for a track of its own
+					if let Some(track) = scene.soundtrack(){
+						if let Err(what) = self.game.play_track(track){
+							warn!("Could not play the scene's soundtrack: {:?}", what);
+						}
+					}
 					State::Running(scene)
 				},
 
@@ -230,6 +377,10 @@ impl Runner{
 			}
 		}
 
+		if let Err(what) = self.game.audio.finish(){
+			warn!("Could not finish the audio backend: {:?}", what);
+		}
+
 		/* Return game once finished running */
 		self.game
 	}
@@ -299,4 +450,36 @@ mod tests{
 		// Run and dispose of the game
 		let _ = Runner::new(game).run();
 	}
+
+	#[test]
+	fn bus_settings(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use super::super::audio::mixer::{MUSIC_BUS, SFX_BUS};
+		let profile = VideoProfile{
+			width: 800,
+			height: 600,
+			framerate: 60,
+			fullscreen: false,
+			vsync: true
+		};
+		let mut game = Game::new("Automated test: bus_settings()".to_owned(), profile);
+
+		game.mixer().bus_mut(MUSIC_BUS).unwrap().set_volume(0.25);
+		game.mixer().bus_mut(SFX_BUS).unwrap().set_muted(true);
+		game.store_bus_settings();
+		assert_eq!(game.flag::<f32>("audio.bus.music.volume"), Some(&0.25));
+		assert_eq!(game.flag::<bool>("audio.bus.sfx.muted"), Some(&true));
+
+		// Whatever happened to the buses since, they go back to how they were stored
+		game.mixer().bus_mut(MUSIC_BUS).unwrap().set_volume(1.0);
+		game.mixer().bus_mut(SFX_BUS).unwrap().set_muted(false);
+		game.mixer().bus_mut(SFX_BUS).unwrap().set_volume(0.5);
+		game.restore_bus_settings();
+		assert_eq!(game.mixer().bus(MUSIC_BUS).unwrap().volume(), 0.25);
+		assert!(!game.mixer().bus(MUSIC_BUS).unwrap().is_muted());
+		assert_eq!(game.mixer().bus(SFX_BUS).unwrap().volume(), 1.0);
+		assert!(game.mixer().bus(SFX_BUS).unwrap().is_muted());
+	}
 }
@@ -0,0 +1,452 @@
+use std::collections::{HashMap, HashSet};
+use glium::Texture2d;
+
+use super::TextureProvider;
+use super::atlas::Sprite;
+
+/// How a clip moves through its frames
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PlaybackMode{
+	/// From the first frame to the last, over and over
+	Loop,
+	/// From the first frame to the last, stopping there
+	Once,
+	/// Back and forth between the first and the last frame
+	PingPong,
+	/// From the last frame to the first, over and over
+	Reverse
+}
+impl PlaybackMode{
+	/// Parses the direction names used by Aseprite tags
+	pub fn from_direction(direction: &str) -> Option<PlaybackMode>{
+		match direction{
+			"forward"  => Some(PlaybackMode::Loop),
+			"reverse"  => Some(PlaybackMode::Reverse),
+			"pingpong" => Some(PlaybackMode::PingPong),
+			_ => None
+		}
+	}
+}
+
+/// A single frame of a clip, showing a sprite unless the clip is made of something else
+#[derive(Clone)]
+pub struct ClipFrame<F = Sprite>{
+	pub sprite:   F,
+	pub duration: f32,        /* Seconds the frame stays on screen          */
+	pub events:   Vec<String> /* Events fired as soon as the frame is shown */
+}
+
+/// A named sequence of frames
+#[derive(Clone)]
+pub struct Clip<F = Sprite>{
+	pub name:   String,
+	pub frames: Vec<ClipFrame<F>>,
+	pub mode:   PlaybackMode,
+	pub loop_start: usize /* Frame a looping clip jumps back to, so the ones before it work as an intro */
+}
+impl<F> Clip<F>{
+	pub fn new(name: &str, mode: PlaybackMode) -> Clip<F>{
+		Clip{
+			name:   name.to_owned(),
+			frames: Vec::new(),
+			mode:   mode,
+			loop_start: 0
+		}
+	}
+
+	/// Appends a frame, returning the clip so frames can be chained
+	pub fn frame(mut self, sprite: F, duration: f32) -> Clip<F>{
+		self.frames.push(ClipFrame{ sprite: sprite, duration: duration, events: Vec::new() });
+		self
+	}
+
+	/// Attaches an event to the last frame appended
+	pub fn event(mut self, event: &str) -> Clip<F>{
+		match self.frames.last_mut(){
+			Some(frame) => frame.events.push(event.to_owned()),
+			None => warn!(r#"Event "{}" added to clip "{}" before any of its frames"#, event, self.name)
+		}
+		self
+	}
+
+	/// Seconds it takes to go through every frame once
+	pub fn duration(&self) -> f32{ self.frames.iter().fold(0.0, |acc, frame| acc + frame.duration) }
+}
+
+/**
+ * Plays a clip, moving through its frames as time is fed to it.
+ *
+ * Time only moves when `update()` is called, with however many seconds have
+ * passed, so the same updates always end up on the same frame.
+ */
+pub struct Animation<F = Sprite>{
+	clip:  Clip<F>,
+	speed: f32,
+
+	frame:     usize,
+	elapsed:   f32,  /* Seconds spent on the current frame       */
+	backwards: bool, /* Direction a ping-pong clip is going in    */
+	entered:   bool, /* Whether the current frame's events fired  */
+	finished:  bool,
+
+	listeners: Vec<(String, Box<FnMut(&str)>)> /* Event name and the callback it triggers */
+}
+impl<F> Animation<F>{
+	/// Starts playing a clip, unless it has no frames to play
+	pub fn new(clip: Clip<F>) -> Option<Animation<F>>{
+		if clip.frames.is_empty(){
+			error!(r#"Clip "{}" has no frames to play"#, clip.name);
+			return None
+		}
+
+		let mut animation = Animation{
+			clip:  clip,
+			speed: 1.0,
+
+			frame:     0,
+			elapsed:   0.0,
+			backwards: false,
+			entered:   false,
+			finished:  false,
+
+			listeners: Vec::new()
+		};
+		animation.restart();
+		Some(animation)
+	}
+
+	/// Switches to another clip, starting it from the beginning, or returns false and keeps the current one if it has no frames
+	pub fn play(&mut self, clip: Clip<F>) -> bool{
+		if clip.frames.is_empty(){
+			error!(r#"Clip "{}" has no frames to play"#, clip.name);
+			return false
+		}
+		self.clip = clip;
+		self.restart();
+		true
+	}
+
+	pub fn restart(&mut self){
+		self.frame     = if self.clip.mode == PlaybackMode::Reverse { self.clip.frames.len() - 1 } else { 0 };
+		self.elapsed   = 0.0;
+		self.backwards = false;
+		self.entered   = false;
+		self.finished  = false;
+	}
+
+	pub fn clip(&self) -> &Clip<F>{ &self.clip }
+
+	/// Playback speed multiplier, where 2.0 plays twice as fast
+	pub fn speed(&self) -> f32{ self.speed }
+	pub fn set_speed(&mut self, speed: f32){ self.speed = if speed < 0.0 { 0.0 } else { speed } }
+
+	/// Index of the frame being shown
+	pub fn current_frame(&self) -> usize{ self.frame }
+	pub fn sprite(&self) -> &F{ &self.clip.frames[self.frame].sprite }
+
+	/// Whether a clip that plays once has reached its last frame and stayed there for its whole duration
+	pub fn is_finished(&self) -> bool{ self.finished }
+
+	/// Calls back every time a frame with the event is shown, passing along the name of the clip
+	pub fn on_event(&mut self, event: &str, callback: Box<FnMut(&str)>){
+		self.listeners.push((event.to_owned(), callback));
+	}
+
+	/// Moves the animation forward by some seconds
+	pub fn update(&mut self, delta: f32){
+		self.enter();
+		if self.finished { return }
+		self.elapsed += delta * self.speed;
+
+		// A clip whose frames take no time would never be done skipping through them
+		if self.clip.duration() <= 0.0 { return }
+		while !self.finished && self.elapsed >= self.clip.frames[self.frame].duration{
+			self.elapsed -= self.clip.frames[self.frame].duration;
+			self.advance();
+			self.enter();
+		}
+	}
+
+	/* Fires the events of the current frame, if they haven't been yet */
+	fn enter(&mut self){
+		if self.entered { return }
+		self.entered = true;
+
+		let frame = &self.clip.frames[self.frame];
+		for event in &frame.events{
+			for &mut (ref name, ref mut callback) in self.listeners.iter_mut(){
+				if name == event { callback(&self.clip.name) }
+			}
+		}
+	}
+
+	fn advance(&mut self){
+		let last = self.clip.frames.len() - 1;
+		let start = if self.clip.loop_start > last { 0 } else { self.clip.loop_start };
+
+		let next = match self.clip.mode{
+			PlaybackMode::Loop    => if self.frame < last { self.frame + 1 } else { start },
+			PlaybackMode::Reverse => if self.frame > start { self.frame - 1 } else { last },
+			PlaybackMode::Once    => if self.frame < last { self.frame + 1 } else {
+				self.finished = true;
+				return
+			},
+			PlaybackMode::PingPong => {
+				if last == start { start }
+				else{
+					if self.frame >= last { self.backwards = true }
+					else if self.frame <= start { self.backwards = false }
+					if self.backwards { self.frame - 1 } else { self.frame + 1 }
+				}
+			}
+		};
+		self.frame = next;
+		self.entered = false;
+	}
+}
+impl<F: TextureProvider> TextureProvider for Animation<F>{
+	fn get_texture(&self) -> &Texture2d{ self.sprite().get_texture() }
+	fn get_uv(&self) -> (f32, f32, f32, f32){ self.sprite().get_uv() }
+	fn is_rotated(&self) -> bool{ self.sprite().is_rotated() }
+}
+
+/// Something that has to be true for a transition between states to happen
+#[derive(Clone, PartialEq, Debug)]
+pub enum Condition{
+	/// A trigger was set, which is then used up by the transition
+	Trigger(String),
+	/// A boolean parameter has some value
+	Bool(String, bool),
+	/// A number parameter is greater than some value
+	Above(String, f32),
+	/// A number parameter is less than some value
+	Below(String, f32),
+	/// The current clip finished playing
+	Finished
+}
+
+/* A way out of a state, or out of any state if it has no origin */
+struct Transition{
+	from: Option<String>,
+	to:   String,
+	conditions: Vec<Condition>
+}
+
+/**
+ * Moves between clips, its states, as its parameters change.
+ *
+ * Transitions are checked in the order they were added after every update, and
+ * the first one whose conditions all hold starts its clip from the beginning.
+ */
+pub struct Animator<F = Sprite>{
+	states:    HashMap<String, Clip<F>>,
+	state:     String,
+	animation: Animation<F>,
+	transitions: Vec<Transition>,
+
+	bools:    HashMap<String, bool>,
+	numbers:  HashMap<String, f32>,
+	triggers: HashSet<String>
+}
+impl<F: Clone> Animator<F>{
+	/// Creates a state machine starting out in the state of the clip, unless it has no frames to play
+	pub fn new(initial: Clip<F>) -> Option<Animator<F>>{
+		let state = initial.name.clone();
+		let mut states = HashMap::new();
+		states.insert(state.clone(), initial.clone());
+
+		Animation::new(initial).map(|animation| Animator{
+			states:    states,
+			state:     state,
+			animation: animation,
+			transitions: Vec::new(),
+
+			bools:    HashMap::new(),
+			numbers:  HashMap::new(),
+			triggers: HashSet::new()
+		})
+	}
+
+	/// Adds a state, named after its clip, replacing any state with the same name
+	pub fn add_state(&mut self, clip: Clip<F>){ self.states.insert(clip.name.clone(), clip); }
+
+	/// Adds a transition between two states
+	pub fn add_transition(&mut self, from: &str, to: &str, conditions: Vec<Condition>){
+		self.transitions.push(Transition{ from: Some(from.to_owned()), to: to.to_owned(), conditions: conditions });
+	}
+
+	/// Adds a transition from every other state
+	pub fn add_any_transition(&mut self, to: &str, conditions: Vec<Condition>){
+		self.transitions.push(Transition{ from: None, to: to.to_owned(), conditions: conditions });
+	}
+
+	pub fn set_bool(&mut self, name: &str, value: bool){ self.bools.insert(name.to_owned(), value); }
+	pub fn set_number(&mut self, name: &str, value: f32){ self.numbers.insert(name.to_owned(), value); }
+
+	/// Sets a trigger, which stays set until a transition uses it up
+	pub fn trigger(&mut self, name: &str){ self.triggers.insert(name.to_owned()); }
+	pub fn reset_trigger(&mut self, name: &str){ self.triggers.remove(name); }
+
+	/// Name of the current state
+	pub fn state(&self) -> &str{ &self.state }
+
+	pub fn animation(&self) -> &Animation<F>{ &self.animation }
+	pub fn animation_mut(&mut self) -> &mut Animation<F>{ &mut self.animation }
+
+	/// Calls back every time a frame with the event is shown, whatever state it's in
+	pub fn on_event(&mut self, event: &str, callback: Box<FnMut(&str)>){ self.animation.on_event(event, callback) }
+
+	/// Jumps straight into a state, returning false if there's no state with that name or its clip has no frames
+	pub fn jump(&mut self, state: &str) -> bool{
+		let played = match self.states.get(state){
+			Some(clip) => self.animation.play(clip.clone()),
+			None => {
+				warn!(r#"Tried to jump into inexistent animation state "{}""#, state);
+				return false
+			}
+		};
+		if played { self.state = state.to_owned() }
+		played
+	}
+
+	/// Moves the current clip forward by some seconds, then follows the first transition that holds
+	pub fn update(&mut self, delta: f32){
+		self.animation.update(delta);
+
+		let next = self.transitions.iter()
+			.filter(|transition| match transition.from{
+				Some(ref from) => *from == self.state,
+				None => transition.to != self.state
+			})
+			.find(|transition| transition.conditions.iter().all(|condition| self.holds(condition)))
+			.map(|transition| (transition.to.clone(), transition.conditions.clone()));
+
+		if let Some((to, conditions)) = next{
+			for condition in conditions{
+				if let Condition::Trigger(name) = condition { self.triggers.remove(&name); }
+			}
+			self.jump(&to);
+		}
+	}
+
+	fn holds(&self, condition: &Condition) -> bool{
+		match *condition{
+			Condition::Trigger(ref name)      => self.triggers.contains(name),
+			Condition::Bool(ref name, value)  => self.bools.get(name).map(|b| *b == value).unwrap_or(!value),
+			Condition::Above(ref name, value) => self.numbers.get(name).map(|n| *n > value).unwrap_or(false),
+			Condition::Below(ref name, value) => self.numbers.get(name).map(|n| *n < value).unwrap_or(false),
+			Condition::Finished => self.animation.is_finished()
+		}
+	}
+}
+impl<F: TextureProvider> TextureProvider for Animator<F>{
+	fn get_texture(&self) -> &Texture2d{ self.animation.get_texture() }
+	fn get_uv(&self) -> (f32, f32, f32, f32){ self.animation.get_uv() }
+	fn is_rotated(&self) -> bool{ self.animation.is_rotated() }
+}
+
+#[cfg(test)]
+mod tests{
+	use std::rc::Rc;
+	use std::cell::RefCell;
+	use super::{Clip, PlaybackMode, Animation, Animator, Condition};
+
+	/* A clip of four frames, of 0.1, 0.2, 0.1 and 0.1 seconds, standing in for sprites with letters */
+	fn clip(name: &str, mode: PlaybackMode) -> Clip<char>{
+		Clip::new(name, mode)
+			.frame('a', 0.1)
+			.frame('b', 0.2).event("footstep")
+			.frame('c', 0.1)
+			.frame('d', 0.1).event("footstep")
+	}
+
+	#[test]
+	fn playback(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let frames = |mode: PlaybackMode, speed: f32| {
+			let mut animation = Animation::new(clip("walk", mode)).unwrap();
+			animation.set_speed(speed);
+			(0..10).map(|_| { animation.update(0.1); animation.current_frame() }).collect::<Vec<usize>>()
+		};
+		assert_eq!(frames(PlaybackMode::Loop,     1.0), vec![1, 1, 2, 3, 0, 1, 1, 2, 3, 0]);
+		assert_eq!(frames(PlaybackMode::Once,     1.0), vec![1, 1, 2, 3, 3, 3, 3, 3, 3, 3]);
+		assert_eq!(frames(PlaybackMode::Reverse,  1.0), vec![2, 1, 1, 0, 3, 2, 1, 1, 0, 3]);
+		assert_eq!(frames(PlaybackMode::PingPong, 1.0), vec![1, 1, 2, 3, 2, 1, 1, 0, 1, 1]);
+		assert_eq!(frames(PlaybackMode::Loop,     2.0), vec![1, 3, 1, 2, 0, 1, 3, 1, 2, 0]);
+
+		let mut animation = Animation::new(clip("walk", PlaybackMode::Once)).unwrap();
+		animation.update(0.45);
+		assert!(!animation.is_finished());
+		animation.update(0.1);
+		assert!(animation.is_finished());
+
+		// Frames after the loop start repeat, the ones before it play only once
+		let mut intro = clip("walk", PlaybackMode::Loop);
+		intro.loop_start = 2;
+		let mut animation = Animation::new(intro).unwrap();
+		let played = (0..8).map(|_| { animation.update(0.1); *animation.sprite() }).collect::<String>();
+		assert_eq!(played, "bbcdcdcd");
+
+		// Clips without frames are turned down, leaving whatever was playing alone
+		assert!(Animation::new(Clip::<char>::new("empty", PlaybackMode::Loop)).is_none());
+		assert!(!animation.play(Clip::new("empty", PlaybackMode::Loop)));
+		assert_eq!(*animation.sprite(), 'd');
+	}
+
+	#[test]
+	fn events_and_states(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let idle = Clip::new("idle", PlaybackMode::Loop).frame('i', 1.0);
+		let attack = Clip::new("attack", PlaybackMode::Once)
+			.frame('x', 0.1)
+			.frame('y', 0.1).event("spawn_flame");
+
+		let mut animator = Animator::new(idle).unwrap();
+		animator.add_state(clip("walk", PlaybackMode::Loop));
+		animator.add_state(attack);
+		animator.add_transition("idle", "walk", vec![Condition::Bool("moving".to_owned(), true)]);
+		animator.add_transition("walk", "idle", vec![Condition::Bool("moving".to_owned(), false)]);
+		animator.add_transition("attack", "idle", vec![Condition::Finished]);
+		animator.add_any_transition("attack", vec![Condition::Trigger("attack".to_owned())]);
+
+		let fired = Rc::new(RefCell::new(Vec::new()));
+		for event in &["footstep", "spawn_flame"]{
+			let fired = fired.clone();
+			let event = event.to_string();
+			animator.on_event(&event.clone(), Box::new(move |clip| fired.borrow_mut().push(format!("{}:{}", clip, event))));
+		}
+
+		animator.update(0.1);
+		assert_eq!(animator.state(), "idle");
+		animator.set_bool("moving", true);
+		animator.update(0.1);
+		assert_eq!(animator.state(), "walk");
+
+		// Half a second of walking goes through both footsteps
+		animator.update(0.5);
+		assert_eq!(*fired.borrow(), vec!["walk:footstep", "walk:footstep"]);
+
+		// Triggers are used up by the transition, and the attack goes back to idle once over
+		animator.trigger("attack");
+		animator.update(0.0);
+		assert_eq!(animator.state(), "attack");
+		animator.update(0.1);
+		assert_eq!(fired.borrow().last().unwrap(), "attack:spawn_flame");
+		animator.update(0.1);
+		assert_eq!(animator.state(), "idle");
+
+		// Still moving, so it goes right back to walking
+		animator.update(0.1);
+		assert_eq!(animator.state(), "walk");
+
+		// States without frames can't be jumped into
+		animator.add_state(Clip::new("empty", PlaybackMode::Loop));
+		assert!(!animator.jump("empty"));
+		assert_eq!(animator.state(), "walk");
+		assert!(Animator::new(Clip::<char>::new("empty", PlaybackMode::Loop)).is_none());
+	}
+}
@@ -0,0 +1,258 @@
+use std::rc::Rc;
+use std::borrow::Cow;
+
+use glium::Texture2d;
+use glium::Rect;
+use glium::texture::{RawImage2d, ClientFormat, MipmapsOption};
+use glium::backend::Facade;
+use image::{self, RgbaImage, ImageBuffer};
+
+use super::{TextureProvider, TextureError};
+use super::sheet::SheetLayout;
+use super::skyline::Skyline;
+use super::super::grid::Grid;
+
+/**
+ * A rectangle of a texture page, drawn like any other texture.
+ *
+ * Sprites keep their page alive, so they can be passed around on their own, and
+ * sprites sharing a page can be drawn without switching textures in between.
+ */
+#[derive(Clone)]
+pub struct Sprite{
+	page: Rc<Texture2d>,
+	uv: (f32, f32, f32, f32), /* Left, top, width and height of the sprite, in texture coordinates */
+	size: (u32, u32),         /* Width and height, in pixels */
+	rotated: bool             /* Whether the sprite is stored turned 90 degrees clockwise */
+}
+impl Sprite{
+	/// Wraps a whole texture in a sprite
+	pub fn new(texture: Texture2d) -> Sprite{
+		let size = (texture.width(), texture.height());
+		Sprite{
+			page: Rc::new(texture),
+			uv: (0.0, 0.0, 1.0, 1.0),
+			size: size,
+			rotated: false
+		}
+	}
+
+	/// A region of a texture page, in pixels, measured from its top left corner
+	pub fn region(page: Rc<Texture2d>, x: u32, y: u32, width: u32, height: u32) -> Sprite{
+		let (page_width, page_height) = (page.width() as f32, page.height() as f32);
+		Sprite{
+			page: page,
+			uv: (x as f32 / page_width, y as f32 / page_height, width as f32 / page_width, height as f32 / page_height),
+			size: (width, height),
+			rotated: false
+		}
+	}
+
+	/**
+	 * A region of a texture page holding a sprite turned 90 degrees clockwise, the way
+	 * packers store some sprites to save space. The width and height are the sprite's,
+	 * so the region itself is `height` pixels wide and `width` pixels tall.
+	 */
+	pub fn rotated_region(page: Rc<Texture2d>, x: u32, y: u32, width: u32, height: u32) -> Sprite{
+		let mut sprite = Sprite::region(page, x, y, height, width);
+		sprite.size = (width, height);
+		sprite.rotated = true;
+		sprite
+	}
+
+	pub fn width(&self)  -> u32{ self.size.0 }
+	pub fn height(&self) -> u32{ self.size.1 }
+
+	/// Whether both sprites live in the same texture page
+	pub fn shares_page(&self, other: &Sprite) -> bool{ &*self.page as *const Texture2d == &*other.page as *const Texture2d }
+}
+impl TextureProvider for Sprite{
+	fn get_texture(&self) -> &Texture2d{ &self.page }
+	fn get_uv(&self) -> (f32, f32, f32, f32){ self.uv }
+	fn is_rotated(&self) -> bool{ self.rotated }
+}
+
+/* Cuts a region out of an image, repeating its edge pixels outwards by some amount */
+fn extrude(image: &RgbaImage, x: u32, y: u32, width: u32, height: u32, amount: u32) -> RgbaImage{
+	ImageBuffer::from_fn(width + amount * 2, height + amount * 2, |a, b| {
+		let a = if a < amount { 0 } else if a - amount >= width  { width  - 1 } else { a - amount };
+		let b = if b < amount { 0 } else if b - amount >= height { height - 1 } else { b - amount };
+		*image.get_pixel(x + a, y + b)
+	})
+}
+
+struct Page{
+	texture: Rc<Texture2d>,
+	skyline: Skyline
+}
+
+/**
+ * Packs many images into a few large textures, so they can be drawn without
+ * switching textures all the time.
+ *
+ * Images are placed on the first page with room for them, and a new page is
+ * created when none has. Every image is kept apart from the others by some
+ * padding, and its edges are extruded into it, so filtering at the border of
+ * a sprite doesn't bleed in pixels from its neighbours.
+ */
+pub struct Atlas{
+	width:  u32,
+	height: u32,
+	padding:   u32, /* Empty pixels kept between images                  */
+	extrusion: u32, /* Pixels the edges of every image are repeated for */
+
+	pages: Vec<Page>
+}
+impl Atlas{
+	/// An empty atlas whose pages have the given dimensions
+	pub fn new(width: u32, height: u32) -> Atlas{
+		Atlas{
+			width:  width,
+			height: height,
+			padding:   2,
+			extrusion: 1,
+
+			pages: Vec::new()
+		}
+	}
+
+	/// Only affects images added afterwards
+	pub fn set_padding(&mut self, padding: u32){ self.padding = padding }
+	pub fn set_extrusion(&mut self, extrusion: u32){ self.extrusion = extrusion }
+
+	/// Number of texture pages created so far
+	pub fn pages(&self) -> usize{ self.pages.len() }
+	pub fn page(&self, index: usize) -> Option<&Texture2d>{ self.pages.get(index).map(|page| &*page.texture) }
+
+	/// Packs a whole image into the atlas
+	pub fn add<F: Facade>(&mut self, facade: &F, image: &RgbaImage) -> Result<Sprite, TextureError>{
+		self.add_region(facade, image, 0, 0, image.width(), image.height())
+	}
+
+	/// Opens an image file and packs it into the atlas
+	pub fn add_file<F: Facade>(&mut self, facade: &F, path: &str) -> Result<Sprite, TextureError>{
+		match image::open(path){
+			Ok(image) => self.add(facade, &image.to_rgba()),
+			Err(what) => {
+				error!(r#"Could not open image at "{}": {:?}"#, path, what);
+				Err(TextureError::Image(what))
+			}
+		}
+	}
+
+	/**
+	 * Packs many images at once, returning their sprites in the same order.
+	 * Packing the tallest images first wastes a lot less space than adding them one by one.
+	 */
+	pub fn add_all<F: Facade>(&mut self, facade: &F, images: &[RgbaImage]) -> Result<Vec<Sprite>, TextureError>{
+		let mut order = (0..images.len()).collect::<Vec<usize>>();
+		order.sort_by(|a, b| (images[*b].height(), images[*b].width()).cmp(&(images[*a].height(), images[*a].width())));
+
+		let mut sprites = vec![None; images.len()];
+		for index in order{ sprites[index] = Some(try!(self.add(facade, &images[index]))) }
+		Ok(sprites.into_iter().map(|sprite| sprite.unwrap()).collect())
+	}
+
+	/// Cuts a sheet of equally sized sprites into cells, packing every one of them
+	pub fn add_sheet<F: Facade>(&mut self, facade: &F, sheet: &RgbaImage, layout: &SheetLayout) -> Result<Grid<Sprite>, TextureError>{
+		let (columns, rows) = layout.dimensions(sheet.width(), sheet.height());
+
+		let mut grid = Grid::new(layout.sprite_width as usize, layout.sprite_height as usize, 1, columns as usize, rows as usize, 1);
+		for row in 0..rows{
+			for column in 0..columns{
+				let (x, y) = layout.position(column, row);
+				let sprite = try!(self.add_region(facade, sheet, x, y, layout.sprite_width, layout.sprite_height));
+				grid.push(row as usize, 0, sprite);
+			}
+		}
+		Ok(grid)
+	}
+
+	/* Packs a region of an image, measured from its top left corner */
+	fn add_region<F: Facade>(&mut self, facade: &F, image: &RgbaImage, x: u32, y: u32, width: u32, height: u32) -> Result<Sprite, TextureError>{
+		let margin = self.extrusion * 2 + self.padding;
+		if width + margin > self.width || height + margin > self.height || width == 0 || height == 0{
+			error!("Image of {}x{} pixels does not fit in atlas pages of {}x{}", width, height, self.width, self.height);
+			return Err(TextureError::DoesNotFit)
+		}
+
+		let mut placement = None;
+		for (index, page) in self.pages.iter_mut().enumerate(){
+			if let Some(position) = page.skyline.insert(width + margin, height + margin){
+				placement = Some((index, position));
+				break
+			}
+		}
+		let (index, (left, top)) = match placement{
+			Some(placement) => placement,
+			None => {
+				let page = try!(self.new_page(facade));
+				self.pages.push(page);
+
+				let index = self.pages.len() - 1;
+				(index, self.pages[index].skyline.insert(width + margin, height + margin).unwrap())
+			}
+		};
+
+		// Rows go in top to bottom, the same way whole textures are uploaded
+		let extruded = extrude(image, x, y, width, height, self.extrusion);
+		let texture = self.pages[index].texture.clone();
+		texture.write(Rect{
+			left:   left,
+			bottom: top,
+			width:  extruded.width(),
+			height: extruded.height()
+		}, RawImage2d{
+			width:  extruded.width(),
+			height: extruded.height(),
+			format: ClientFormat::U8U8U8U8,
+			data:   Cow::Owned(extruded.into_raw())
+		});
+
+		Ok(Sprite::region(texture, left + self.extrusion, top + self.extrusion, width, height))
+	}
+
+	fn new_page<F: Facade>(&self, facade: &F) -> Result<Page, TextureError>{
+		debug!("Creating a new {}x{} atlas page", self.width, self.height);
+
+		// Pages start out transparent, and without mipmaps, since they're written to after being created
+		let blank = RawImage2d{
+			width:  self.width,
+			height: self.height,
+			format: ClientFormat::U8U8U8U8,
+			data:   Cow::Owned(vec![0u8; (self.width * self.height * 4) as usize])
+		};
+		match Texture2d::with_mipmaps(facade, blank, MipmapsOption::NoMipmap){
+			Ok(texture) => Ok(Page{
+				texture: Rc::new(texture),
+				skyline: Skyline::new(self.width, self.height)
+			}),
+			Err(what) => {
+				error!("Could not create atlas page: {:?}", what);
+				Err(TextureError::TextureCreation(what))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::extrude;
+	use image::{ImageBuffer, Rgba};
+
+	#[test]
+	fn extrusion(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let image = ImageBuffer::from_fn(4, 4, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+		let cell = extrude(&image, 2, 2, 2, 2, 1);
+		assert_eq!((cell.width(), cell.height()), (4, 4));
+
+		// Corners repeat the corner pixels, edges repeat the edge pixels
+		assert_eq!(cell.get_pixel(0, 0).data, [2, 2, 0, 255]);
+		assert_eq!(cell.get_pixel(3, 3).data, [3, 3, 0, 255]);
+		assert_eq!(cell.get_pixel(2, 0).data, [3, 2, 0, 255]);
+		assert_eq!(cell.get_pixel(1, 2).data, [2, 3, 0, 255]);
+	}
+}
@@ -0,0 +1,178 @@
+use glium::{Surface, Texture2d, DrawError};
+use glium::index::{NoIndices, PrimitiveType};
+
+use super::{Renderer2d, TextureProvider};
+
+/// Number of quads a batch holds before it has to be drawn
+pub const BATCH_QUADS: usize = 4096;
+
+#[derive(Copy, Clone)]
+#[allow(non_snake_case)]
+pub struct BatchVertex{
+	bbm_Position: [f32; 2],
+	bbm_TexCoord: [f32; 2],
+	bbm_Color:    [f32; 4]
+}
+implement_vertex!(BatchVertex, bbm_Position, bbm_TexCoord, bbm_Color);
+
+/* Texture the quads waiting in a batch are drawn with */
+#[derive(Copy, Clone)]
+enum Page<'a>{
+	Texture(&'a Texture2d),
+	Blank /* The renderer's white texture, for plain rectangles */
+}
+impl<'a> Page<'a>{
+	fn is(&self, other: &Page) -> bool{
+		match (*self, *other){
+			(Page::Texture(a), Page::Texture(b)) => a as *const Texture2d == b as *const Texture2d,
+			(Page::Blank, Page::Blank) => true,
+			_ => false
+		}
+	}
+}
+
+/**
+ * Collects quads into a single vertex buffer, drawing them all at once when the
+ * texture changes, when the buffer fills up or when the batch ends.
+ *
+ * Drawing everything that shares a texture in a row, like sprites from the same
+ * atlas page, keeps the number of draw calls down to a handful per frame.
+ */
+pub struct SpriteBatch<'a, S: Surface + 'a>{
+	renderer: &'a mut Renderer2d,
+	target:   &'a mut S,
+
+	page:     Page<'a>,
+	vertices: Vec<BatchVertex>
+}
+impl<'a, S: Surface + 'a> SpriteBatch<'a, S>{
+	pub fn new(renderer: &'a mut Renderer2d, target: &'a mut S) -> SpriteBatch<'a, S>{
+		SpriteBatch{
+			renderer: renderer,
+			target:   target,
+
+			page:     Page::Blank,
+			vertices: Vec::with_capacity(BATCH_QUADS * 6)
+		}
+	}
+
+	pub fn rectangle(&mut self, x: f32, y: f32, width: f32, height: f32, color: (f32, f32, f32, f32)) -> Result<(), DrawError>{
+		self.quad(Page::Blank, x, y, width, height, (0.0, 0.0, 1.0, 1.0), false, color)
+	}
+
+	pub fn shaded_sprite<T: TextureProvider>(&mut self, x: f32, y: f32, width: f32, height: f32, texture: &'a T, color: (f32, f32, f32, f32)) -> Result<(), DrawError>{
+		self.quad(Page::Texture(texture.get_texture()), x, y, width, height, texture.get_uv(), texture.is_rotated(), color)
+	}
+
+	pub fn sprite<T: TextureProvider>(&mut self, x: f32, y: f32, width: f32, height: f32, texture: &'a T) -> Result<(), DrawError>{
+		self.shaded_sprite(x, y, width, height, texture, (1.0, 1.0, 1.0, 1.0))
+	}
+
+	fn quad(&mut self, page: Page<'a>, x: f32, y: f32, width: f32, height: f32, uv: (f32, f32, f32, f32), rotated: bool, color: (f32, f32, f32, f32)) -> Result<(), DrawError>{
+		if !self.page.is(&page) || self.vertices.len() >= BATCH_QUADS * 6{
+			try!(self.flush());
+			self.page = page;
+		}
+
+		let color = [color.0, color.1, color.2, color.3];
+		let corner = |a: f32, b: f32| {
+			let (u, v) = if rotated { (1.0 - b, a) } else { (a, b) };
+			BatchVertex{
+				bbm_Position: [x + width * a, y + height * b],
+				bbm_TexCoord: [uv.0 + uv.2 * u, uv.1 + uv.3 * v],
+				bbm_Color:    color
+			}
+		};
+
+		// Two triangles, sharing the top left to bottom right diagonal
+		self.vertices.push(corner(0.0, 0.0));
+		self.vertices.push(corner(1.0, 0.0));
+		self.vertices.push(corner(1.0, 1.0));
+		self.vertices.push(corner(0.0, 0.0));
+		self.vertices.push(corner(1.0, 1.0));
+		self.vertices.push(corner(0.0, 1.0));
+		Ok(())
+	}
+
+	/// Draws every quad collected so far
+	pub fn flush(&mut self) -> Result<(), DrawError>{
+		if self.vertices.is_empty() { return Ok(()) }
+		let count = self.vertices.len();
+
+		self.renderer.camera.push();
+		self.renderer.camera.scale(1.0, 1.0, 1.0);
+		let matrix: [[f32; 4]; 4] = self.renderer.camera.get_matrix().into();
+		self.renderer.camera.pop();
+
+		let result = {
+			let texture = match self.page{
+				Page::Texture(texture) => texture,
+				Page::Blank => &self.renderer.blank
+			};
+			let uniform = uniform!{
+				bbm_Matrix:  matrix,
+				bbm_Texture: texture
+			};
+
+			let slice = self.renderer.batch.slice(0..count).unwrap();
+			slice.write(&self.vertices);
+			self.target.draw(slice, NoIndices(PrimitiveType::TrianglesList), &self.renderer.batch_shader, &uniform, &Default::default())
+		};
+		self.renderer.draw_calls += 1;
+		self.vertices.clear();
+		result
+	}
+
+	/// Draws whatever is left in the batch
+	pub fn end(mut self) -> Result<(), DrawError>{ self.flush() }
+}
+impl<'a, S: Surface + 'a> Drop for SpriteBatch<'a, S>{
+	fn drop(&mut self){
+		if let Err(what) = self.flush(){ warn!("Could not draw the rest of a sprite batch: {}", what) }
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::BATCH_QUADS;
+	use super::super::{Renderer2d, Texture};
+
+	#[test]
+	fn draw_calls(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Setup context
+		use glium::DisplayBuild;
+		use glium::glutin::WindowBuilder;
+		let display = WindowBuilder::new()
+			.with_dimensions(1280, 720)
+			.with_title("Automated test: batch::draw_calls()")
+			.build_glium().unwrap();
+
+		let sheet = Texture::open(&display, "test/sheet.png").unwrap().sprite_sheet(16, 16).flatten();
+		let mut renderer = Renderer2d::new(&display, 1024.0, 1024.0).unwrap();
+
+		use glium::Texture2d;
+		let canvas = Texture2d::empty(&display, 1024, 1024).unwrap();
+		let mut surface = canvas.as_surface();
+
+		// A whole tile map fills a batch, the actors on top of it start another one
+		{
+			let mut batch = renderer.batch(&mut surface);
+			for i in 0..BATCH_QUADS{
+				batch.sprite((i % 64) as f32 * 16.0, (i / 64) as f32 * 16.0, 16.0, 16.0, &sheet[i % sheet.len()]).unwrap();
+			}
+			for i in 0..200{ batch.sprite(i as f32, i as f32, 16.0, 16.0, &sheet[0]).unwrap() }
+
+			// Plain rectangles don't share the sheet's texture
+			batch.rectangle(0.0, 0.0, 64.0, 8.0, (1.0, 0.0, 0.0, 1.0)).unwrap();
+			batch.end().unwrap();
+		}
+		assert_eq!(renderer.draw_calls(), 3);
+
+		renderer.reset_draw_calls();
+		renderer.sprite(&mut surface, 0.0, 0.0, 16.0, 16.0, &sheet[0]).unwrap();
+		assert_eq!(renderer.draw_calls(), 1);
+	}
+}
@@ -0,0 +1,474 @@
+use std::rc::Rc;
+use std::path::Path;
+use std::collections::HashMap;
+
+use glium::backend::Facade;
+
+use super::{Texture, TextureError};
+use super::atlas::Sprite;
+
+/* Most pages a font can have, as glyphs keep their page in a byte in the binary format */
+const MAX_PAGES: usize = 256;
+
+use std::io::Error as IoError;
+#[derive(Debug)]
+pub enum FontError{
+	UnableToReadFile(IoError),
+
+	/// The descriptor isn't a valid AngelCode BMFont, in either its text or binary form
+	Malformed,
+	Texture(TextureError)
+}
+
+/// Where a character is in its page, and how it sits on the line
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Glyph{
+	pub id: u32, /* Unicode code point */
+	pub x: u32,
+	pub y: u32,
+	pub width:  u32,
+	pub height: u32,
+
+	pub x_offset:  i32, /* Offset from the pen position to the top left corner of the glyph */
+	pub y_offset:  i32,
+	pub x_advance: i32, /* How far the pen moves once the glyph is drawn */
+	pub page: usize
+}
+
+/// Metrics of a bitmap font, without the pages themselves
+#[derive(Clone, PartialEq, Debug)]
+pub struct FontData{
+	pub face: String,
+	pub size: i32,
+	pub line_height: u32,
+	pub base: u32,          /* Distance from the top of a line to its baseline */
+	pub pages: Vec<String>, /* Paths of the page images, relative to the descriptor */
+
+	pub glyphs:  HashMap<u32, Glyph>,
+	pub kerning: HashMap<(u32, u32), i32> /* Extra advance between two characters */
+}
+impl FontData{
+	/// Reads a descriptor in either the text or the binary format
+	pub fn parse(source: &[u8]) -> Result<FontData, FontError>{
+		if source.starts_with(b"BMF") { return FontData::from_binary(source) }
+		match ::std::str::from_utf8(source){
+			Ok(text) => FontData::from_text(text),
+			Err(_) => {
+				error!("Font descriptor is neither binary nor text");
+				Err(FontError::Malformed)
+			}
+		}
+	}
+
+	fn empty() -> FontData{
+		FontData{
+			face: String::new(),
+			size: 0,
+			line_height: 0,
+			base: 0,
+			pages: Vec::new(),
+
+			glyphs:  HashMap::new(),
+			kerning: HashMap::new()
+		}
+	}
+
+	/// Reads a descriptor in the text format, made up of lines of `key=value` pairs
+	pub fn from_text(source: &str) -> Result<FontData, FontError>{
+		let mut font = FontData::empty();
+		let mut page_count = MAX_PAGES;
+		for line in source.lines(){
+			let mut tags = tokens(line).into_iter();
+			let kind = match tags.next(){ Some(kind) => kind, None => continue };
+			let pairs = tags.filter_map(|token| {
+				let mut split = token.splitn(2, '=');
+				match (split.next(), split.next()){
+					(Some(key), Some(value)) => Some((key.to_owned(), value.trim_matches('"').to_owned())),
+					_ => None
+				}
+			}).collect::<HashMap<String, String>>();
+
+			let number = |key: &str| -> Result<i32, FontError> {
+				match pairs.get(key).and_then(|value| value.parse::<i32>().ok()){
+					Some(number) => Ok(number),
+					None => {
+						error!(r#"Font descriptor line "{}" is missing a number for "{}""#, line, key);
+						Err(FontError::Malformed)
+					}
+				}
+			};
+			let unsigned = |key: &str| -> Result<u32, FontError> {
+				let number = try!(number(key));
+				if number < 0{
+					error!(r#"Font descriptor line "{}" has a negative "{}""#, line, key);
+					return Err(FontError::Malformed)
+				}
+				Ok(number as u32)
+			};
+
+			match kind.as_str(){
+				"info" => {
+					font.face = pairs.get("face").cloned().unwrap_or(String::new());
+					font.size = try!(number("size")).abs();
+				},
+				"common" => {
+					font.line_height = try!(unsigned("lineHeight"));
+					font.base        = try!(unsigned("base"));
+					if pairs.contains_key("pages"){
+						page_count = ::std::cmp::min(try!(unsigned("pages")) as usize, MAX_PAGES);
+					}
+				},
+				"page" => {
+					let id = try!(unsigned("id")) as usize;
+					if id >= page_count{
+						error!("Font page {} is past the {} pages the font can have", id, page_count);
+						return Err(FontError::Malformed)
+					}
+					let file = match pairs.get("file"){
+						Some(file) => file.clone(),
+						None => {
+							error!(r#"Font page {} has no file"#, id);
+							return Err(FontError::Malformed)
+						}
+					};
+					if font.pages.len() <= id { font.pages.resize(id + 1, String::new()) }
+					font.pages[id] = file;
+				},
+				"char" => {
+					let glyph = Glyph{
+						id: try!(unsigned("id")),
+						x:  try!(unsigned("x")),
+						y:  try!(unsigned("y")),
+						width:  try!(unsigned("width")),
+						height: try!(unsigned("height")),
+
+						x_offset:  try!(number("xoffset")),
+						y_offset:  try!(number("yoffset")),
+						x_advance: try!(number("xadvance")),
+						page: try!(unsigned("page")) as usize
+					};
+					font.glyphs.insert(glyph.id, glyph);
+				},
+				"kerning" => {
+					let pair = (try!(unsigned("first")), try!(unsigned("second")));
+					font.kerning.insert(pair, try!(number("amount")));
+				},
+				_ => {}
+			}
+		}
+
+		font.validate()
+	}
+
+	/// Reads a descriptor in the binary format, version 3
+	pub fn from_binary(source: &[u8]) -> Result<FontData, FontError>{
+		if source.len() < 4 || &source[..3] != b"BMF" || source[3] != 3{
+			error!("Binary font descriptor doesn't start with the BMF version 3 signature");
+			return Err(FontError::Malformed)
+		}
+
+		let bytes = Bytes(source);
+		let mut font = FontData::empty();
+		let mut at = 4;
+		while at < source.len(){
+			let kind   = try!(bytes.u8(at));
+			let length = try!(bytes.u32(at + 1)) as usize;
+			let block  = Bytes(try!(bytes.slice(at + 5, length)));
+			at += 5 + length;
+
+			match kind{
+				1 => {
+					font.size = (try!(block.u16(0)) as i16 as i32).abs();
+					font.face = strings(try!(block.slice(14, length.saturating_sub(14)))).into_iter().next().unwrap_or(String::new());
+				},
+				2 => {
+					font.line_height = try!(block.u16(0)) as u32;
+					font.base        = try!(block.u16(2)) as u32;
+				},
+				3 => font.pages = strings(block.0),
+				4 => for index in 0..length / 20{
+					let at = index * 20;
+					let glyph = Glyph{
+						id: try!(block.u32(at)),
+						x:  try!(block.u16(at + 4)) as u32,
+						y:  try!(block.u16(at + 6)) as u32,
+						width:  try!(block.u16(at + 8))  as u32,
+						height: try!(block.u16(at + 10)) as u32,
+
+						x_offset:  try!(block.u16(at + 12)) as i16 as i32,
+						y_offset:  try!(block.u16(at + 14)) as i16 as i32,
+						x_advance: try!(block.u16(at + 16)) as i16 as i32,
+						page: try!(block.u8(at + 18)) as usize
+					};
+					font.glyphs.insert(glyph.id, glyph);
+				},
+				5 => for index in 0..length / 10{
+					let at = index * 10;
+					let pair = (try!(block.u32(at)), try!(block.u32(at + 4)));
+					font.kerning.insert(pair, try!(block.u16(at + 8)) as i16 as i32);
+				},
+				_ => warn!("Skipping unknown block {} of binary font descriptor", kind)
+			}
+		}
+
+		font.validate()
+	}
+
+	/* Makes sure every glyph lives in a page that exists */
+	fn validate(self) -> Result<FontData, FontError>{
+		if self.pages.is_empty(){
+			error!("Font descriptor has no pages");
+			return Err(FontError::Malformed)
+		}
+		for glyph in self.glyphs.values(){
+			if glyph.page >= self.pages.len(){
+				error!("Glyph {} is in page {}, but the font only has {} pages", glyph.id, glyph.page, self.pages.len());
+				return Err(FontError::Malformed)
+			}
+		}
+		Ok(self)
+	}
+
+	/**
+	 * Walks through the glyphs of a text, calling back with each glyph and the position
+	 * of its top left corner, relative to the top left corner of the text. Characters
+	 * the font doesn't have are drawn as a question mark, if it has one.
+	 *
+	 * Returns the width of the widest line and the height of all lines together.
+	 */
+	pub fn layout<F: FnMut(&Glyph, f32, f32)>(&self, text: &str, mut place: F) -> (f32, f32){
+		let (mut x, mut y) = (0i32, 0i32);
+		let mut width = 0;
+		let mut previous: Option<u32> = None;
+
+		for character in text.chars(){
+			match character{
+				'\n' => {
+					x = 0;
+					y += self.line_height as i32;
+					previous = None;
+					continue
+				},
+				'\r' => continue,
+				_ => {}
+			}
+
+			let glyph = match self.glyphs.get(&(character as u32)).or_else(|| self.glyphs.get(&('?' as u32))){
+				Some(glyph) => glyph,
+				None => continue
+			};
+			if let Some(previous) = previous{
+				x += self.kerning.get(&(previous, glyph.id)).cloned().unwrap_or(0);
+			}
+
+			place(glyph, (x + glyph.x_offset) as f32, (y + glyph.y_offset) as f32);
+			x += glyph.x_advance;
+			if x > width { width = x }
+			previous = Some(glyph.id);
+		}
+		(width as f32, (y + self.line_height as i32) as f32)
+	}
+
+	/// Width of the widest line and height of all lines of a text
+	pub fn measure(&self, text: &str) -> (f32, f32){ self.layout(text, |_, _, _| {}) }
+}
+
+/* Splits a line of the text format on spaces, except for those inside quotes */
+fn tokens(line: &str) -> Vec<String>{
+	let mut tokens = Vec::new();
+	let mut current = String::new();
+	let mut quoted = false;
+	for character in line.chars(){
+		match character{
+			'"' => { quoted = !quoted; current.push(character) },
+			' ' | '\t' if !quoted => if !current.is_empty() { tokens.push(current.clone()); current.clear() },
+			_ => current.push(character)
+		}
+	}
+	if !current.is_empty() { tokens.push(current) }
+	tokens
+}
+
+/* Splits a block of null terminated strings */
+fn strings(bytes: &[u8]) -> Vec<String>{
+	bytes.split(|b| *b == 0)
+		.filter(|string| !string.is_empty())
+		.map(|string| String::from_utf8_lossy(string).into_owned())
+		.collect()
+}
+
+struct Bytes<'a>(&'a [u8]);
+impl<'a> Bytes<'a>{
+	fn slice(&self, at: usize, length: usize) -> Result<&'a [u8], FontError>{
+		if at + length <= self.0.len() { Ok(&self.0[at..at + length]) }
+		else {
+			error!("Binary font descriptor is truncated, expected {} bytes at offset {} but it ends at {}", length, at, self.0.len());
+			Err(FontError::Malformed)
+		}
+	}
+
+	fn u8(&self, at: usize) -> Result<u8, FontError>{ self.slice(at, 1).map(|b| b[0]) }
+	fn u16(&self, at: usize) -> Result<u16, FontError>{ self.slice(at, 2).map(|b| b[0] as u16 | (b[1] as u16) << 8) }
+	fn u32(&self, at: usize) -> Result<u32, FontError>{
+		self.slice(at, 4).map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+	}
+}
+
+/**
+ * A font whose glyphs were drawn beforehand into one or more pages, as exported
+ * by AngelCode's BMFont and the many tools that share its format.
+ */
+pub struct BitmapFont{
+	data: FontData,
+	sprites: HashMap<u32, Sprite>
+}
+impl BitmapFont{
+	/// Builds a font out of its metrics and its pages, in the same order the metrics list them
+	pub fn new(data: FontData, pages: Vec<Texture>) -> BitmapFont{
+		let pages = pages.into_iter().map(|page| Rc::new(page.0)).collect::<Vec<_>>();
+		let sprites = data.glyphs.values()
+			.filter(|glyph| glyph.width > 0 && glyph.height > 0 && glyph.page < pages.len())
+			.map(|glyph| (glyph.id, Sprite::region(pages[glyph.page].clone(), glyph.x, glyph.y, glyph.width, glyph.height)))
+			.collect();
+
+		BitmapFont{
+			data: data,
+			sprites: sprites
+		}
+	}
+
+	/// Opens a descriptor, in either format, along with the pages it refers to
+	pub fn open<F: Facade>(facade: &F, path: &str) -> Result<BitmapFont, FontError>{
+		use std::fs::File;
+		use std::io::Read;
+		let mut source = Vec::new();
+		if let Err(what) = File::open(path).and_then(|mut file| file.read_to_end(&mut source)){
+			error!(r#"Could not read font descriptor at "{}": {:?}"#, path, what);
+			return Err(FontError::UnableToReadFile(what))
+		}
+		BitmapFont::from_memory(facade, &source, Path::new(path))
+	}
+
+	/// Reads a descriptor from memory, loading its pages relative to the path of the descriptor
+	pub fn from_memory<F: Facade>(facade: &F, source: &[u8], path: &Path) -> Result<BitmapFont, FontError>{
+		let data = try!(FontData::parse(source));
+
+		let mut pages = Vec::with_capacity(data.pages.len());
+		for page in &data.pages{
+			match Texture::open(facade, &path.with_file_name(page).to_string_lossy()){
+				Ok(texture) => pages.push(texture),
+				Err(what) => return Err(FontError::Texture(what))
+			}
+		}
+		Ok(BitmapFont::new(data, pages))
+	}
+
+	pub fn data(&self) -> &FontData{ &self.data }
+	pub fn line_height(&self) -> f32{ self.data.line_height as f32 }
+
+	/// Walks through the sprites of a text, calling back with each one and the position of its top left corner
+	pub fn layout<'a, F: FnMut(&'a Sprite, f32, f32)>(&'a self, text: &str, mut place: F) -> (f32, f32){
+		let sprites = &self.sprites;
+		self.data.layout(text, |glyph, x, y| if let Some(sprite) = sprites.get(&glyph.id){ place(sprite, x, y) })
+	}
+
+	/// Width of the widest line and height of all lines of a text
+	pub fn measure(&self, text: &str) -> (f32, f32){ self.data.measure(text) }
+}
+
+#[cfg(test)]
+mod tests{
+	use super::FontData;
+
+	static TEXT: &'static str = r#"info face="Bomber Sans" size=-16 bold=0 italic=0 charset="" unicode=1 stretchH=100 smooth=1 aa=1 padding=0,0,0,0 spacing=1,1
+common lineHeight=18 base=14 scaleW=128 scaleH=128 pages=1 packed=0
+page id=0 file="bomber sans_0.png"
+chars count=4
+char id=32   x=0     y=0     width=0     height=0     xoffset=0     yoffset=14    xadvance=4     page=0  chnl=15
+char id=63   x=0     y=0     width=6     height=10    xoffset=0     yoffset=4     xadvance=7     page=0  chnl=15
+char id=65   x=7     y=0     width=9     height=10    xoffset=-1    yoffset=4     xadvance=8     page=0  chnl=15
+char id=86   x=17    y=0     width=9     height=10    xoffset=0     yoffset=4     xadvance=8     page=0  chnl=15
+kernings count=1
+kerning first=65  second=86  amount=-2
+"#;
+
+	#[test]
+	fn text_format(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let font = FontData::parse(TEXT.as_bytes()).unwrap();
+		assert_eq!(font.face, "Bomber Sans");
+		assert_eq!(font.size, 16);
+		assert_eq!((font.line_height, font.base), (18, 14));
+		assert_eq!(font.pages, vec!["bomber sans_0.png".to_owned()]);
+		assert_eq!(font.glyphs.len(), 4);
+		assert_eq!(font.glyphs[&65].x_offset, -1);
+		assert_eq!(font.kerning[&(65, 86)], -2);
+
+		assert!(FontData::parse(b"info face=\"x\" size=8\nchar id=65 x=0").is_err());
+
+		// Pages out of range and negative sizes are turned down rather than trusted
+		let with = |from: &str, to: &str| FontData::parse(TEXT.replace(from, to).as_bytes());
+		assert!(with("page id=0", "page id=-1").is_err());
+		assert!(with("page id=0", "page id=100000000").is_err());
+		assert!(with("page id=0", "page id=1").is_err());
+		assert!(with("pages=1", "pages=2").is_ok());
+		assert!(with("lineHeight=18", "lineHeight=-18").is_err());
+		assert!(with("x=7 ", "x=-7 ").is_err());
+		assert!(with("width=9", "width=-9").is_err());
+	}
+
+	#[test]
+	fn binary_format(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut source = b"BMF\x03".to_vec();
+		let mut block = |kind: u8, data: &[u8]| {
+			source.push(kind);
+			let length = data.len() as u32;
+			source.extend_from_slice(&[length as u8, (length >> 8) as u8, (length >> 16) as u8, (length >> 24) as u8]);
+			source.extend_from_slice(data);
+		};
+		block(1, b"\xF0\xFF\x00\x00\x64\x00\x01\x00\x00\x00\x00\x01\x01\x00Bomber\x00");
+		block(2, b"\x12\x00\x0E\x00\x80\x00\x80\x00\x01\x00\x00\x00\x00\x00\x00");
+		block(3, b"bomber_0.png\x00");
+		block(4, &[
+			0x41, 0, 0, 0,  7, 0,  0, 0,  9, 0,  10, 0,  0xFF, 0xFF,  4, 0,  8, 0,  0,  15,
+			0x56, 0, 0, 0,  17, 0, 0, 0,  9, 0,  10, 0,  0, 0,        4, 0,  8, 0,  0,  15
+		]);
+		block(5, &[0x41, 0, 0, 0,  0x56, 0, 0, 0,  0xFE, 0xFF]);
+
+		let font = FontData::parse(&source).unwrap();
+		assert_eq!(font.face, "Bomber");
+		assert_eq!(font.size, 16);
+		assert_eq!((font.line_height, font.base), (18, 14));
+		assert_eq!(font.pages, vec!["bomber_0.png".to_owned()]);
+		assert_eq!(font.glyphs[&65].x_offset, -1);
+		assert_eq!(font.glyphs[&86].x, 17);
+		assert_eq!(font.kerning[&(65, 86)], -2);
+
+		assert!(FontData::parse(&source[..40]).is_err());
+	}
+
+	#[test]
+	fn measure(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let font = FontData::parse(TEXT.as_bytes()).unwrap();
+
+		// Kerning pulls the V closer to the A
+		assert_eq!(font.measure("AV"), (14.0, 18.0));
+		assert_eq!(font.measure("VA"), (16.0, 18.0));
+		assert_eq!(font.measure("A V\nA"), (20.0, 36.0));
+
+		// Missing characters turn into question marks
+		assert_eq!(font.measure("Z"), (7.0, 18.0));
+
+		let mut placed = Vec::new();
+		font.layout("AV\nV", |glyph, x, y| placed.push((glyph.id, x, y)));
+		assert_eq!(placed, vec![(65, -1.0, 4.0), (86, 6.0, 4.0), (86, 0.0, 22.0)]);
+	}
+}
@@ -0,0 +1,156 @@
+use std::rc::Rc;
+use glium::{Texture2d, Program};
+use glium::uniforms::UniformValue;
+use glium::backend::Facade;
+
+use super::{ShaderError, RENDERER2D_TEXTURE_VERTEX_SHADER, compile};
+
+/// Value of a uniform a shader declares on top of the ones the engine gives it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Parameter{
+	Float(f32),
+	Vec2([f32; 2]),
+	Vec3([f32; 3]),
+	Vec4([f32; 4]),
+	Int(i32),
+	Bool(bool),
+	Mat4([[f32; 4]; 4])
+}
+impl Parameter{
+	pub fn as_uniform<'a>(&self) -> UniformValue<'a>{
+		match *self{
+			Parameter::Float(value) => UniformValue::Float(value),
+			Parameter::Vec2(value)  => UniformValue::Vec2(value),
+			Parameter::Vec3(value)  => UniformValue::Vec3(value),
+			Parameter::Vec4(value)  => UniformValue::Vec4(value),
+			Parameter::Int(value)   => UniformValue::SignedInt(value),
+			Parameter::Bool(value)  => UniformValue::Bool(value),
+			Parameter::Mat4(value)  => UniformValue::Mat4(value)
+		}
+	}
+}
+
+/**
+ * A shader program of its own to draw sprites and rectangles with, along with values
+ * for the uniforms it declares.
+ *
+ * The vertex shader gets the quad's `bbm_Position`, `bbm_TexCoord` and `bbm_Normal`,
+ * along with `bbm_Matrix`, `bbm_TexRect` and `bbm_TexRotated`. When none is given, the
+ * renderer's own is used, which hands `texcoord` over to the fragment shader. Both get
+ * `bbm_Texture`, the sprite being drawn, and `bbm_Color`, its tint.
+ *
+ * Clones share the program, so one shader can be drawn with different values.
+ */
+#[derive(Clone)]
+pub struct Material{
+	program: Rc<Program>,
+	parameters: Vec<(String, Parameter)>,
+	textures:   Vec<(String, Rc<Texture2d>)>
+}
+impl Material{
+	/// A material whose fragment shader reads `texcoord`, like the renderer's own one does
+	pub fn new<F: Facade>(facade: &F, fragment: &str) -> Result<Material, ShaderError>{
+		Material::with_vertex(facade, RENDERER2D_TEXTURE_VERTEX_SHADER, fragment)
+	}
+
+	pub fn with_vertex<F: Facade>(facade: &F, vertex: &str, fragment: &str) -> Result<Material, ShaderError>{
+		Ok(Material{
+			program: Rc::new(try!(compile(facade, vertex, fragment, "material shader program"))),
+			parameters: Vec::new(),
+			textures:   Vec::new()
+		})
+	}
+
+	pub fn program(&self) -> &Program{ &self.program }
+
+	pub fn parameters(&self) -> &[(String, Parameter)]{ &self.parameters }
+	pub fn parameter(&self, name: &str) -> Option<Parameter>{
+		self.parameters.iter().find(|&&(ref parameter, _)| parameter == name).map(|&(_, value)| value)
+	}
+
+	pub fn set(&mut self, name: &str, value: Parameter){
+		match self.parameters.iter().position(|&(ref parameter, _)| parameter == name){
+			Some(index) => self.parameters[index].1 = value,
+			None => self.parameters.push((name.to_owned(), value))
+		}
+	}
+
+	/// Textures bound to samplers the shader declares besides bbm_Texture
+	pub fn textures(&self) -> &[(String, Rc<Texture2d>)]{ &self.textures }
+	pub fn texture(&self, name: &str) -> Option<&Rc<Texture2d>>{
+		self.textures.iter().find(|&&(ref sampler, _)| sampler == name).map(|&(_, ref texture)| texture)
+	}
+
+	pub fn set_texture(&mut self, name: &str, texture: Rc<Texture2d>){
+		match self.textures.iter().position(|&(ref sampler, _)| sampler == name){
+			Some(index) => self.textures[index].1 = texture,
+			None => self.textures.push((name.to_owned(), texture))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use std::rc::Rc;
+	use super::{Material, Parameter};
+	use super::super::{Renderer2d, RenderTarget, ShaderError};
+
+	#[test]
+	fn material(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Setup context
+		use glium::DisplayBuild;
+		use glium::glutin::WindowBuilder;
+		let display = WindowBuilder::new()
+			.with_dimensions(1280, 720)
+			.with_title("Automated test: material::material()")
+			.build_glium().unwrap();
+
+		match Material::new(&display, "this is not glsl"){
+			Err(ShaderError::Compilation(_)) => {},
+			_ => panic!("Expected a compilation error")
+		}
+
+		// Mixes the sprite with a second texture, by an amount given as a parameter
+		let mut material = Material::new(&display, "
+			#version 140
+
+			in vec2 texcoord;
+			out vec4 color;
+
+			uniform sampler2D bbm_Texture;
+			uniform sampler2D mix_Other;
+			uniform vec4 bbm_Color;
+			uniform float mix_Amount;
+			void main(){ color = mix(texture(bbm_Texture, texcoord), texture(mix_Other, texcoord), mix_Amount) * bbm_Color; }
+		").unwrap();
+		use glium::Texture2d;
+		let red  = Texture2d::new(&display, vec![vec![(255u8, 0u8, 0u8, 255u8)]]).unwrap();
+		let blue = Rc::new(Texture2d::new(&display, vec![vec![(0u8, 0u8, 255u8, 255u8)]]).unwrap());
+		material.set_texture("mix_Other", blue);
+		material.set("mix_Amount", Parameter::Float(0.0));
+		assert_eq!(material.parameter("mix_Amount"), Some(Parameter::Float(0.0)));
+		assert!(material.texture("mix_Other").is_some());
+
+		let mut renderer = Renderer2d::new(&display, 1.0, 1.0).unwrap();
+		let output = RenderTarget::new(&display, 16, 16).unwrap();
+		let center = |output: &RenderTarget| { let pixels: Vec<Vec<(u8, u8, u8, u8)>> = output.texture().read(); pixels[8][8] };
+
+		renderer.sprite_with(&mut output.surface(), 0.0, 0.0, 1.0, 1.0, &red, (1.0, 1.0, 1.0, 1.0), &material).unwrap();
+		assert_eq!(center(&output), (255, 0, 0, 255));
+
+		// Clones share the program, but not the values
+		let mut other = material.clone();
+		other.set("mix_Amount", Parameter::Float(1.0));
+		renderer.sprite_with(&mut output.surface(), 0.0, 0.0, 1.0, 1.0, &red, (1.0, 1.0, 1.0, 1.0), &other).unwrap();
+		assert_eq!(center(&output), (0, 0, 255, 255));
+		assert_eq!(material.parameter("mix_Amount"), Some(Parameter::Float(0.0)));
+
+		// Rectangles sample a white pixel instead of a sprite
+		renderer.rectangle_with(&mut output.surface(), 0.0, 0.0, 1.0, 1.0, (0.0, 1.0, 0.0, 1.0), &material).unwrap();
+		assert_eq!(center(&output), (0, 255, 0, 255));
+		assert_eq!(renderer.draw_calls(), 3);
+	}
+}
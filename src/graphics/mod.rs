@@ -0,0 +1,562 @@
+/* Import font rasterization library */
+extern crate rusttype;
+
+#[derive(Copy, Clone)]
+#[allow(non_snake_case)]
+struct Vertex{
+	bbm_Position: [f32; 3],
+	bbm_TexCoord: [f32; 2],
+	bbm_Normal:   [f32; 3]
+}
+implement_vertex!(Vertex, bbm_Position, bbm_TexCoord, bbm_Normal);
+
+/* Rectangle packing shared by the atlas and the glyph cache */
+mod skyline;
+
+pub mod atlas;
+pub use self::atlas::{Atlas, Sprite};
+
+pub mod batch;
+pub use self::batch::SpriteBatch;
+
+pub mod animation;
+pub use self::animation::{Clip, ClipFrame, PlaybackMode, Animation, Animator, Condition};
+
+pub mod sheet;
+pub use self::sheet::{SheetLayout, SheetData, SheetError, SpriteSheet, Frame};
+
+pub mod font;
+pub use self::font::{BitmapFont, FontData, FontError, Glyph};
+
+pub mod outline;
+pub use self::outline::{OutlineFont, ScalableFont, FontSize};
+
+pub mod text;
+pub use self::text::{TextFont, RichText, MarkupError, Style, Align, TextLayout};
+
+pub mod target;
+pub use self::target::RenderTarget;
+
+pub mod post;
+pub use self::post::{PostProcessor, Pass};
+
+pub mod material;
+pub use self::material::{Material, Parameter};
+
+use glium::Texture2d;
+pub trait TextureProvider{
+	fn get_texture(&self) -> &Texture2d;
+
+	/// Left, top, width and height of the region of the texture that gets drawn, in texture coordinates
+	fn get_uv(&self) -> (f32, f32, f32, f32){ (0.0, 0.0, 1.0, 1.0) }
+
+	/// Whether the region is stored turned 90 degrees clockwise
+	fn is_rotated(&self) -> bool{ false }
+}
+impl TextureProvider for Texture2d{
+	fn get_texture(&self) -> &Texture2d { self }
+}
+
+use image::ImageError;
+use glium::texture::TextureCreationError;
+#[derive(Debug)]
+pub enum TextureError{
+	Image(ImageError),
+	TextureCreation(TextureCreationError),
+
+	/// The image is larger than the pages of the atlas it was added to
+	DoesNotFit
+}
+
+use glium::program::ProgramCreationError;
+#[derive(Debug)]
+pub enum ShaderError{
+	/// A shader did not compile, with the log of the driver
+	Compilation(String),
+	/// The shaders compiled but could not be linked together, with the log of the driver
+	Linking(String),
+	/// The context cannot run the program at all
+	Unsupported(ProgramCreationError)
+}
+
+use glium::vertex::BufferCreationError;
+#[derive(Debug)]
+pub enum RendererError{
+	Shader(ShaderError),
+	VertexBuffer(BufferCreationError),
+	TextureCreation(TextureCreationError)
+}
+
+use glium::texture::{RawImage2d, ClientFormat};
+use glium::backend::Facade;
+use super::grid::Grid;
+pub struct Texture(Texture2d);
+impl Texture{
+	pub fn open<F: Facade>(facade: &F, path: &str) -> Result<Texture, TextureError>{
+		use image;
+		use std::borrow::Cow;
+		match image::open(path){
+			Ok(img) => {
+				let rgba = img.to_rgba();
+				let data = RawImage2d{
+					width:  rgba.width(),
+					height: rgba.height(),
+					format: ClientFormat::U8U8U8U8,
+					data:   Cow::Owned(rgba.into_raw())
+				};
+
+				match Texture2d::new(facade, data){
+					Ok(texture) => Ok(Texture(texture)),
+					Err(what) => {
+						error!("Could not upload texture to OpenGL: {:?}", what);
+						Err(TextureError::TextureCreation(what))
+					}
+				}
+			}
+			Err(what) => {
+				error!(r#"Could not open image at "{}": {:?}"#, path, what);
+				Err(TextureError::Image(what))
+			}
+		}
+	}
+
+	pub fn open_from_memory<F: Facade>(facade: &F, source: &[u8]) -> Result<Texture, TextureError>{
+		use image;
+		use std::borrow::Cow;
+		match image::load_from_memory(source){
+			Ok(img) => {
+				let rgba = img.to_rgba();
+				let data = RawImage2d{
+					width:  rgba.width(),
+					height: rgba.height(),
+					format: ClientFormat::U8U8U8U8,
+					data:   Cow::Owned(rgba.into_raw())
+				};
+
+				match Texture2d::new(facade, data){
+					Ok(texture) => Ok(Texture(texture)),
+					Err(what) => {
+						error!("Could not upload texture to OpenGL: {:?}", what);
+						Err(TextureError::TextureCreation(what))
+					}
+				}
+			}
+			Err(what) => {
+				error!("Could not open image from memory: {:?}", what);
+				Err(TextureError::Image(what))
+			}
+		}
+	}
+
+	/**
+	 * Splits a sheet of equally sized sprites into a grid of sprites. The texture itself
+	 * is shared by all of them, so nothing is read back from or uploaded to the GPU.
+	 */
+	pub fn sprite_sheet(self, sprite_width: usize, sprite_height: usize) -> Grid<Sprite>{
+		self.sprite_sheet_with(&SheetLayout::new(sprite_width as u32, sprite_height as u32))
+	}
+
+	/// Splits a sheet whose sprites are kept apart from each other and from its border
+	pub fn sprite_sheet_with(self, layout: &SheetLayout) -> Grid<Sprite>{
+		// Get the grid's dimensions in sprites
+		let (columns, rows) = layout.dimensions(self.0.width(), self.0.height());
+
+		use std::rc::Rc;
+		let page = Rc::new(self.0);
+		let mut grid = Grid::new(layout.sprite_width as usize, layout.sprite_height as usize, 1, columns as usize, rows as usize, 1);
+		for row in 0..rows{
+			for column in 0..columns{
+				let (x, y) = layout.position(column, row);
+				grid.push(row as usize, 0, Sprite::region(page.clone(), x, y, layout.sprite_width, layout.sprite_height));
+			}
+		}
+		grid
+	}
+
+	/// Turns the whole texture into a sprite
+	pub fn into_sprite(self) -> Sprite{ Sprite::new(self.0) }
+}
+impl TextureProvider for Texture{
+	fn get_texture(&self) -> &Texture2d{ &self.0 }
+}
+
+static RENDERER2D_TEXTURE_VERTEX_SHADER: &'static str = "
+	#version 140
+
+	in vec3 bbm_Position;
+	in vec2 bbm_TexCoord;
+	in vec3 bbm_Normal;
+
+	out vec2 texcoord;
+
+	uniform mat4 bbm_Matrix;
+	uniform vec4 bbm_TexRect;
+	uniform bool bbm_TexRotated;
+	void main(){
+		vec2 coord = bbm_TexRotated ? vec2(1.0 - bbm_TexCoord.y, bbm_TexCoord.x) : bbm_TexCoord;
+		texcoord = bbm_TexRect.xy + coord * bbm_TexRect.zw;
+		gl_Position = bbm_Matrix * vec4(bbm_Position, 1);
+	}
+";
+static RENDERER2D_COLOR_VERTEX_SHADER: &'static str = "
+	#version 140
+
+	in vec3 bbm_Position;
+	in vec2 bbm_TexCoord;
+	in vec3 bbm_Normal;
+
+	out vec2 texcoord;
+
+	uniform mat4 bbm_Matrix;
+	void main(){ gl_Position = bbm_Matrix * vec4(bbm_Position, 1); }
+";
+static RENDERER2D_TEXTURE_FRAGMENT_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform vec4 bbm_Color;
+	uniform sampler2D bbm_Texture;
+	void main(){ color = texture(bbm_Texture, texcoord) * bbm_Color; }
+";
+static RENDERER2D_COLOR_FRAGMENT_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform vec4 bbm_Color;
+	void main(){ color = bbm_Color; }
+";
+static RENDERER2D_BATCH_VERTEX_SHADER: &'static str = "
+	#version 140
+
+	in vec2 bbm_Position;
+	in vec2 bbm_TexCoord;
+	in vec4 bbm_Color;
+
+	out vec2 texcoord;
+	out vec4 tint;
+
+	uniform mat4 bbm_Matrix;
+	void main(){ texcoord = bbm_TexCoord; tint = bbm_Color; gl_Position = bbm_Matrix * vec4(bbm_Position, 0, 1); }
+";
+static RENDERER2D_BATCH_FRAGMENT_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	in vec4 tint;
+	out vec4 color;
+
+	uniform sampler2D bbm_Texture;
+	void main(){ color = texture(bbm_Texture, texcoord) * tint; }
+";
+
+use glium::{Surface, VertexBuffer, Program, DrawError};
+
+/* Compiles and links a program, telling what failed and in which of the engine's programs */
+fn compile<F: Facade>(facade: &F, vertex: &str, fragment: &str, name: &str) -> Result<Program, ShaderError>{
+	match Program::from_source(facade, vertex, fragment, None){
+		Ok(program) => Ok(program),
+		Err(ProgramCreationError::CompilationError(log)) => {
+			error!("Could not compile {}:\n{}", name, log);
+			Err(ShaderError::Compilation(log))
+		},
+		Err(ProgramCreationError::LinkingError(log)) => {
+			error!("Could not link {}:\n{}", name, log);
+			Err(ShaderError::Linking(log))
+		},
+		Err(what) => {
+			error!("Could not create {}: {:?}", name, what);
+			Err(ShaderError::Unsupported(what))
+		}
+	}
+}
+
+/* Everything a shader of a material gets */
+use glium::uniforms::{Uniforms, UniformValue};
+struct MaterialUniforms<'b>{
+	matrix:  [[f32; 4]; 4],
+	uv:      (f32, f32, f32, f32),
+	rotated: bool,
+	texture: &'b Texture2d,
+	color:   (f32, f32, f32, f32),
+	material: &'b Material
+}
+impl<'b> Uniforms for MaterialUniforms<'b>{
+	fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F){
+		visit("bbm_Matrix",     UniformValue::Mat4(self.matrix));
+		visit("bbm_TexRect",    UniformValue::Vec4([self.uv.0, self.uv.1, self.uv.2, self.uv.3]));
+		visit("bbm_TexRotated", UniformValue::Bool(self.rotated));
+		visit("bbm_Texture",    UniformValue::Texture2d(self.texture, None));
+		visit("bbm_Color",      UniformValue::Vec4([self.color.0, self.color.1, self.color.2, self.color.3]));
+		for &(ref name, ref value)   in self.material.parameters(){ visit(name, value.as_uniform()) }
+		for &(ref name, ref texture) in self.material.textures(){ visit(name, UniformValue::Texture2d(texture, None)) }
+	}
+}
+
+use super::Camera;
+use self::batch::{BatchVertex, BATCH_QUADS};
+pub struct Renderer2d{
+	camera: Camera,
+	rectangle: VertexBuffer<Vertex>,
+	texture_shader: Program,
+	color_shader:   Program,
+
+	batch: VertexBuffer<BatchVertex>, /* Quads of a sprite batch, rewritten every time it's drawn */
+	batch_shader: Program,
+	blank: Texture2d, /* White pixel plain rectangles are batched with */
+
+	draw_calls: usize
+}
+impl Renderer2d{
+	pub fn new<F: Facade>(facade: &F, width: f32, height: f32) -> Result<Renderer2d, RendererError>{
+		use cgmath;
+		Ok(Renderer2d{
+			camera: Camera::new(cgmath::ortho(0.0, width, height, 0.0, 1.0, -1.0)),
+			rectangle: match VertexBuffer::new(facade, &[
+				Vertex{ bbm_Position: [0.0, 0.0, 0.0], bbm_TexCoord: [0.0, 0.0], bbm_Normal: [0.0, 0.0, 0.0] },
+				Vertex{ bbm_Position: [1.0, 0.0, 0.0], bbm_TexCoord: [1.0, 0.0], bbm_Normal: [0.0, 0.0, 0.0] },
+				Vertex{ bbm_Position: [1.0, 1.0, 0.0], bbm_TexCoord: [1.0, 1.0], bbm_Normal: [0.0, 0.0, 0.0] },
+				Vertex{ bbm_Position: [0.0, 1.0, 0.0], bbm_TexCoord: [0.0, 1.0], bbm_Normal: [0.0, 0.0, 0.0] },
+				Vertex{ bbm_Position: [0.0, 0.0, 0.0], bbm_TexCoord: [0.0, 0.0], bbm_Normal: [0.0, 0.0, 0.0] }
+			]){
+				Ok(vb) => vb,
+				Err(what) => {
+					error!("Could not register the required Rectangle Vertex Buffer: {:?}", what);
+					return Err(RendererError::VertexBuffer(what))
+				}
+			},
+			texture_shader: match compile(facade, RENDERER2D_TEXTURE_VERTEX_SHADER, RENDERER2D_TEXTURE_FRAGMENT_SHADER, "texture shader program"){
+				Ok(program) => program,
+				Err(what) => return Err(RendererError::Shader(what))
+			},
+			color_shader: match compile(facade, RENDERER2D_COLOR_VERTEX_SHADER, RENDERER2D_COLOR_FRAGMENT_SHADER, "color shader program"){
+				Ok(program) => program,
+				Err(what) => return Err(RendererError::Shader(what))
+			},
+
+			batch: match VertexBuffer::empty_dynamic(facade, BATCH_QUADS * 6){
+				Ok(vb) => vb,
+				Err(what) => {
+					error!("Could not register the sprite batch Vertex Buffer: {:?}", what);
+					return Err(RendererError::VertexBuffer(what))
+				}
+			},
+			batch_shader: match compile(facade, RENDERER2D_BATCH_VERTEX_SHADER, RENDERER2D_BATCH_FRAGMENT_SHADER, "batch shader program"){
+				Ok(program) => program,
+				Err(what) => return Err(RendererError::Shader(what))
+			},
+			blank: match Texture2d::new(facade, vec![vec![(255u8, 255u8, 255u8, 255u8)]]){
+				Ok(texture) => texture,
+				Err(what) => {
+					error!("Could not create the blank texture: {:?}", what);
+					return Err(RendererError::TextureCreation(what))
+				}
+			},
+
+			draw_calls: 0
+		})
+	}
+
+	/// Starts collecting sprites and rectangles, to draw them with as few draw calls as possible
+	pub fn batch<'a, S: Surface>(&'a mut self, target: &'a mut S) -> SpriteBatch<'a, S>{ SpriteBatch::new(self, target) }
+
+	/// Number of draw calls issued since the renderer was created, or since the counter was reset
+	pub fn draw_calls(&self) -> usize{ self.draw_calls }
+	pub fn reset_draw_calls(&mut self){ self.draw_calls = 0 }
+
+	pub fn resize(&mut self, width: f32, height: f32){
+		use cgmath;
+		self.camera.replace_projection(cgmath::ortho(0.0, width, height, 0.0, 1.0, -1.0));
+	}
+
+	pub fn rectangle<S: Surface>(&mut self, target: &mut S, x: f32, y: f32, width: f32, height: f32, color: (f32, f32, f32, f32)) -> Result<(), DrawError>{
+		use glium::index::{NoIndices, PrimitiveType};
+		self.camera.push();
+
+		self.camera.translate(x, y, 0.0);
+		self.camera.scale(width, height, 1.0);
+		let matrix: [[f32; 4]; 4] = self.camera.get_matrix().into();
+
+		self.camera.pop();
+
+		let uniform = uniform!{
+			bbm_Matrix: matrix,
+			bbm_Color:  [color.0, color.1, color.2, color.3]
+		};
+
+		// Draw the quad
+		self.draw_calls += 1;
+		target.draw(&self.rectangle, NoIndices(PrimitiveType::TriangleStrip), &self.color_shader, &uniform, &Default::default())
+	}
+
+	pub fn shaded_sprite<S: Surface, T: TextureProvider>(&mut self, target: &mut S, x: f32, y: f32, width: f32, height: f32, texture: &T, color: (f32, f32, f32, f32)) -> Result<(), DrawError>{
+		use glium::index::{NoIndices, PrimitiveType};
+		self.camera.push();
+
+		self.camera.translate(x, y, 0.0);
+		self.camera.scale(width, height, 1.0);
+		let matrix: [[f32; 4]; 4] = self.camera.get_matrix().into();
+
+		self.camera.pop();
+
+		let uv = texture.get_uv();
+		let uniform = uniform!{
+			bbm_Matrix:  matrix,
+			bbm_TexRect: [uv.0, uv.1, uv.2, uv.3],
+			bbm_TexRotated: texture.is_rotated(),
+			bbm_Texture: texture.get_texture(),
+			bbm_Color:   [color.0, color.1, color.2, color.3]
+		};
+		// Draw the quad
+		self.draw_calls += 1;
+		target.draw(&self.rectangle, NoIndices(PrimitiveType::TriangleStrip), &self.texture_shader, &uniform, &Default::default())
+
+	}
+
+	pub fn sprite<S: Surface, T: TextureProvider>(&mut self, target: &mut S, x: f32, y: f32, width: f32, height: f32, texture: &T) -> Result<(), DrawError>{
+		self.shaded_sprite(target, x, y, width, height, texture, (1.0, 1.0, 1.0, 1.0))
+	}
+
+	/// Draws a sprite with a material instead of the default shader, tinted by the given color
+	pub fn sprite_with<S: Surface, T: TextureProvider>(&mut self, target: &mut S, x: f32, y: f32, width: f32, height: f32, texture: &T, color: (f32, f32, f32, f32), material: &Material) -> Result<(), DrawError>{
+		use glium::index::{NoIndices, PrimitiveType};
+		self.camera.push();
+
+		self.camera.translate(x, y, 0.0);
+		self.camera.scale(width, height, 1.0);
+		let matrix: [[f32; 4]; 4] = self.camera.get_matrix().into();
+
+		self.camera.pop();
+
+		let uniform = MaterialUniforms{
+			matrix:  matrix,
+			uv:      texture.get_uv(),
+			rotated: texture.is_rotated(),
+			texture: texture.get_texture(),
+			color:   color,
+			material: material
+		};
+
+		// Draw the quad
+		self.draw_calls += 1;
+		target.draw(&self.rectangle, NoIndices(PrimitiveType::TriangleStrip), material.program(), &uniform, &Default::default())
+	}
+
+	/// Draws a rectangle with a material, which gets a blank white pixel as its bbm_Texture
+	pub fn rectangle_with<S: Surface>(&mut self, target: &mut S, x: f32, y: f32, width: f32, height: f32, color: (f32, f32, f32, f32), material: &Material) -> Result<(), DrawError>{
+		use glium::index::{NoIndices, PrimitiveType};
+		self.camera.push();
+
+		self.camera.translate(x, y, 0.0);
+		self.camera.scale(width, height, 1.0);
+		let matrix: [[f32; 4]; 4] = self.camera.get_matrix().into();
+
+		self.camera.pop();
+
+		let uniform = MaterialUniforms{
+			matrix:  matrix,
+			uv:      (0.0, 0.0, 1.0, 1.0),
+			rotated: false,
+			texture: &self.blank,
+			color:   color,
+			material: material
+		};
+
+		// Draw the quad
+		self.draw_calls += 1;
+		target.draw(&self.rectangle, NoIndices(PrimitiveType::TriangleStrip), material.program(), &uniform, &Default::default())
+	}
+
+	/// Draws a text with its top left corner at the given position, tinted by the given color
+	pub fn text<S: Surface>(&mut self, target: &mut S, font: &BitmapFont, text: &str, x: f32, y: f32, color: (f32, f32, f32, f32)) -> Result<(), DrawError>{
+		let mut batch = self.batch(target);
+		let mut result = Ok(());
+		font.layout(text, |sprite, left, top| if result.is_ok(){
+			result = batch.shaded_sprite(x + left, y + top, sprite.width() as f32, sprite.height() as f32, sprite, color);
+		});
+		try!(result);
+		batch.end()
+	}
+
+	/// Draws a text at a size in pixels, rasterizing the glyphs it needs that the font hasn't cached yet
+	pub fn scalable_text<S: Surface>(&mut self, target: &mut S, font: &mut ScalableFont, text: &str, size: f32, x: f32, y: f32, color: (f32, f32, f32, f32)) -> Result<(), DrawError>{
+		if let Err(what) = font.prepare(text, size){ warn!("Could not rasterize every glyph of a text, some will be missing: {:?}", what) }
+
+		let font = &*font;
+		let mut batch = self.batch(target);
+		let mut result = Ok(());
+		font.layout(text, size, |sprite, left, top| if result.is_ok(){
+			result = batch.shaded_sprite(x + left, y + top, sprite.width() as f32, sprite.height() as f32, sprite, color);
+		});
+		try!(result);
+		batch.end()
+	}
+
+	/**
+	 * Draws rich text laid out with the given font, tinted by the given color. The
+	 * time, in seconds, drives effects like waving and shaking. Scalable fonts have
+	 * to be prepared for the plain text beforehand, or its glyphs will be missing.
+	 */
+	pub fn rich_text<S: Surface, T: TextFont>(&mut self, target: &mut S, font: &T, layout: &TextLayout, x: f32, y: f32, color: (f32, f32, f32, f32), time: f32) -> Result<(), DrawError>{
+		use self::text::Item;
+		let line_height = layout.line_height();
+
+		let mut batch = self.batch(target);
+		let mut result = Ok(());
+		for placed in layout.items(){
+			let style = placed.style;
+			let tint = (style.color.0 * color.0, style.color.1 * color.1, style.color.2 * color.2, style.color.3 * color.3);
+			let (left, top) = (x + placed.x, y + placed.y);
+
+			match placed.item{
+				Item::Text(ref text) => {
+					let mut glyph = placed.glyph;
+					font.layout(text, |sprite, gx, gy| {
+						let (dx, dy) = style.offset(glyph, time, line_height);
+						if result.is_ok(){
+							result = batch.shaded_sprite(left + gx + dx, top + gy + dy, sprite.width() as f32, sprite.height() as f32, sprite, tint);
+						}
+						glyph += 1;
+					});
+				},
+				Item::Icon(ref sprite) => {
+					let (dx, dy) = style.offset(placed.glyph, time, line_height);
+					result = batch.shaded_sprite(left + dx, top + dy, placed.width, placed.height, sprite, tint);
+				}
+			}
+			try!(result);
+		}
+		batch.end()
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::Texture;
+
+	#[test]
+	fn spritesheet(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Setup headless context
+		use glium::DisplayBuild;
+		use glium::glutin::WindowBuilder;
+		let display = WindowBuilder::new()
+			.with_dimensions(1280, 720)
+			.with_title("Automated test: grid::spritesheet()")
+			.build_glium().unwrap();
+
+		// Load and create a new image and turn it into a spritesheet
+		let spritesheet = Texture::open(&display, "test/sheet.png").unwrap().sprite_sheet(16, 16);
+
+		assert_eq!(spritesheet.elements.len(),       1);
+		assert_eq!(spritesheet.elements[0].len(),    2);
+		assert_eq!(spritesheet.elements[0][0].len(), 2);
+		assert!(spritesheet.in_range(1, 1, 0));
+		assert_eq!(spritesheet.flatten().len(), 4);
+	}
+}
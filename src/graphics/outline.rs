@@ -0,0 +1,447 @@
+use std::rc::Rc;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use glium::{Texture2d, Rect};
+use glium::texture::{RawImage2d, ClientFormat, MipmapsOption};
+use glium::backend::{Context, Facade};
+use super::rusttype::{self, Scale, GlyphId, point};
+
+use super::TextureError;
+use super::font::FontError;
+use super::atlas::Sprite;
+use super::skyline::Skyline;
+
+/// A TrueType or OpenType font, whose glyphs are drawn from their outlines at any size
+pub struct OutlineFont{
+	font: rusttype::Font<'static>
+}
+impl OutlineFont{
+	pub fn from_bytes(data: Vec<u8>) -> Result<OutlineFont, FontError>{
+		match rusttype::Font::try_from_vec(data){
+			Some(font) => Ok(OutlineFont{ font: font }),
+			None => {
+				error!("Data is not a TrueType or OpenType font");
+				Err(FontError::Malformed)
+			}
+		}
+	}
+
+	pub fn open(path: &str) -> Result<OutlineFont, FontError>{
+		use std::fs::File;
+		use std::io::Read;
+		let mut data = Vec::new();
+		if let Err(what) = File::open(path).and_then(|mut file| file.read_to_end(&mut data)){
+			error!(r#"Could not read font at "{}": {:?}"#, path, what);
+			return Err(FontError::UnableToReadFile(what))
+		}
+		OutlineFont::from_bytes(data)
+	}
+
+	/// Whether the font has a glyph of its own for a character
+	pub fn has_glyph(&self, character: char) -> bool{ self.font.glyph(character).id() != GlyphId(0) }
+}
+
+/* Size in 1/64ths of a pixel, so that glyphs of almost the same size share their rasterization */
+fn quantize(size: f32) -> u32{ (size * 64.0).round() as u32 }
+
+/* Glyphs are cached by font, glyph and size */
+type Key = (usize, u16, u32);
+
+/* A rasterized glyph, with the offset from the pen position on the baseline to its top left corner */
+struct Cached{
+	sprite: Option<Sprite>, /* Glyphs like spaces have nothing to draw */
+	offset: (i32, i32),
+	area: (u32, u32, u32, u32)
+}
+
+/**
+ * A single texture page holding every glyph rasterized so far, which gets
+ * bigger as glyphs are added, up to a maximum size.
+ */
+struct GlyphCache{
+	context: Rc<Context>,
+
+	width:  u32,
+	height: u32,
+	max_size: u32,
+
+	pixels:  Vec<u8>, /* Copy of the page, to carry glyphs over when it grows */
+	page:    Rc<Texture2d>,
+	skyline: Skyline,
+	glyphs:  HashMap<Key, Cached>
+}
+impl GlyphCache{
+	fn new(context: Rc<Context>, size: u32, max_size: u32) -> Result<GlyphCache, TextureError>{
+		let pixels = vec![0u8; (size * size * 4) as usize];
+		let page = try!(GlyphCache::upload(&context, &pixels, size, size));
+		Ok(GlyphCache{
+			context: context,
+
+			width:  size,
+			height: size,
+			max_size: max_size,
+
+			pixels:  pixels,
+			page:    page,
+			skyline: Skyline::new(size, size),
+			glyphs:  HashMap::new()
+		})
+	}
+
+	fn upload(context: &Rc<Context>, pixels: &[u8], width: u32, height: u32) -> Result<Rc<Texture2d>, TextureError>{
+		let image = RawImage2d{
+			width:  width,
+			height: height,
+			format: ClientFormat::U8U8U8U8,
+			data:   Cow::Borrowed(pixels)
+		};
+		match Texture2d::with_mipmaps(context, image, MipmapsOption::NoMipmap){
+			Ok(texture) => Ok(Rc::new(texture)),
+			Err(what) => {
+				error!("Could not create glyph cache page: {:?}", what);
+				Err(TextureError::TextureCreation(what))
+			}
+		}
+	}
+
+	/* Doubles the shorter side of the page, returning false if it already is as big as it gets */
+	fn grow(&mut self) -> Result<bool, TextureError>{
+		let (width, height) = if self.width > self.height { (self.width, self.height * 2) } else { (self.width * 2, self.height) };
+		if width > self.max_size || height > self.max_size { return Ok(false) }
+		debug!("Growing glyph cache page from {}x{} to {}x{}", self.width, self.height, width, height);
+
+		let mut pixels = vec![0u8; (width * height * 4) as usize];
+		for row in 0..self.height as usize{
+			let (old, new) = (self.width as usize * 4, width as usize * 4);
+			pixels[row * new..row * new + old].copy_from_slice(&self.pixels[row * old..(row + 1) * old]);
+		}
+		self.page = try!(GlyphCache::upload(&self.context, &pixels, width, height));
+		self.pixels = pixels;
+		self.width  = width;
+		self.height = height;
+		self.skyline.grow(width, height);
+
+		// Glyphs stay where they were, but their coordinates in the page have changed
+		let page = self.page.clone();
+		for cached in self.glyphs.values_mut(){
+			if cached.sprite.is_some(){
+				let (x, y, w, h) = cached.area;
+				cached.sprite = Some(Sprite::region(page.clone(), x, y, w, h));
+			}
+		}
+		Ok(true)
+	}
+
+	/* Stores a glyph of the given coverage, returning false if there's no room left for it */
+	fn add(&mut self, key: Key, offset: (i32, i32), width: u32, height: u32, coverage: &[u8]) -> Result<bool, TextureError>{
+		if width == 0 || height == 0{
+			self.glyphs.insert(key, Cached{ sprite: None, offset: offset, area: (0, 0, 0, 0) });
+			return Ok(true)
+		}
+
+		// A pixel of padding keeps filtering from bleeding in neighbouring glyphs
+		let (x, y) = loop{
+			if let Some(position) = self.skyline.insert(width + 1, height + 1) { break position }
+			if !try!(self.grow()) { return Ok(false) }
+		};
+
+		// Glyphs are white, so that the color they're drawn with tints them
+		let mut rgba = Vec::with_capacity(coverage.len() * 4);
+		for row in 0..height as usize{
+			for column in 0..width as usize{
+				rgba.extend_from_slice(&[255, 255, 255, coverage[row * width as usize + column]]);
+			}
+			let (start, line) = (((y as usize + row) * self.width as usize + x as usize) * 4, width as usize * 4);
+			self.pixels[start..start + line].copy_from_slice(&rgba[row * line..(row + 1) * line]);
+		}
+		self.page.write(Rect{
+			left:   x,
+			bottom: y,
+			width:  width,
+			height: height
+		}, RawImage2d{
+			width:  width,
+			height: height,
+			format: ClientFormat::U8U8U8U8,
+			data:   Cow::Owned(rgba)
+		});
+
+		self.glyphs.insert(key, Cached{
+			sprite: Some(Sprite::region(self.page.clone(), x, y, width, height)),
+			offset: offset,
+			area:   (x, y, width, height)
+		});
+		Ok(true)
+	}
+
+	fn clear(&mut self){
+		debug!("Glyph cache page is full, dropping all {} glyphs", self.glyphs.len());
+		for pixel in self.pixels.iter_mut(){ *pixel = 0 }
+
+		// Blank the page too, or old coverage would bleed into the padding of new glyphs
+		self.page.write(Rect{
+			left:   0,
+			bottom: 0,
+			width:  self.width,
+			height: self.height
+		}, RawImage2d{
+			width:  self.width,
+			height: self.height,
+			format: ClientFormat::U8U8U8U8,
+			data:   Cow::Borrowed(&self.pixels[..])
+		});
+		self.skyline = Skyline::new(self.width, self.height);
+		self.glyphs.clear();
+	}
+}
+
+/**
+ * Scalable text, drawn with a TrueType or OpenType font and any number of fallback
+ * fonts, which are used for the characters the fonts before them lack.
+ *
+ * Glyphs are rasterized the first time they're drawn at a given size, into a page
+ * that starts small and grows as needed. Once it can't grow any further, every glyph
+ * is dropped, and the ones still being drawn are rasterized again.
+ */
+pub struct ScalableFont{
+	faces: Vec<OutlineFont>, /* The main font first, then the fallbacks in order */
+	cache: GlyphCache
+}
+impl ScalableFont{
+	pub fn new<F: Facade>(facade: &F, font: OutlineFont) -> Result<ScalableFont, TextureError>{
+		Ok(ScalableFont{
+			faces: vec![font],
+			cache: try!(GlyphCache::new(facade.get_context().clone(), 256, 4096))
+		})
+	}
+
+	pub fn open<F: Facade>(facade: &F, path: &str) -> Result<ScalableFont, FontError>{
+		let font = try!(OutlineFont::open(path));
+		ScalableFont::new(facade, font).map_err(FontError::Texture)
+	}
+
+	/// Adds a font to look characters up in, when none of the fonts added before has them
+	pub fn add_fallback(&mut self, font: OutlineFont){ self.faces.push(font) }
+
+	/// Largest width and height the glyph page may grow to, 4096 by default
+	pub fn set_max_page_size(&mut self, size: u32){ self.cache.max_size = size }
+	pub fn page(&self) -> &Texture2d{ &self.cache.page }
+	pub fn page_size(&self) -> (u32, u32){ (self.cache.width, self.cache.height) }
+	pub fn cached_glyphs(&self) -> usize{ self.cache.glyphs.len() }
+
+	/// The font at a size in pixels, to lay rich text out with
+	pub fn at(&self, size: f32) -> FontSize{ FontSize{ font: self, size: size } }
+
+	/// Distance between the baselines of two lines of text
+	pub fn line_height(&self, size: f32) -> f32{
+		let metrics = self.faces[0].font.v_metrics(Scale::uniform(size));
+		metrics.ascent - metrics.descent + metrics.line_gap
+	}
+
+	/*
+	 * Walks through the glyphs of a text, calling back with the font each one is
+	 * taken from, the glyph itself and the position of the pen on the baseline.
+	 * Characters that no font has are drawn with the main font's missing glyph.
+	 */
+	fn shape<F: FnMut(usize, GlyphId, f32, f32)>(&self, text: &str, size: f32, mut place: F) -> (f32, f32){
+		let scale = Scale::uniform(size);
+		let ascent = self.faces[0].font.v_metrics(scale).ascent;
+		let line_height = self.line_height(size);
+
+		let (mut x, mut y) = (0.0, 0.0);
+		let mut width: f32 = 0.0;
+		let mut previous: Option<(usize, GlyphId)> = None;
+		for character in text.chars(){
+			match character{
+				'\n' => {
+					x = 0.0;
+					y += line_height;
+					previous = None;
+					continue
+				},
+				'\r' => continue,
+				_ => {}
+			}
+
+			let face = self.faces.iter().position(|face| face.has_glyph(character)).unwrap_or(0);
+			let glyph = self.faces[face].font.glyph(character).scaled(scale);
+			let id = glyph.id();
+			if let Some((previous_face, previous_id)) = previous{
+				if previous_face == face { x += self.faces[face].font.pair_kerning(scale, previous_id, id) }
+			}
+
+			place(face, id, x, y + ascent);
+			x += glyph.h_metrics().advance_width;
+			width = width.max(x);
+			previous = Some((face, id));
+		}
+		(width, y + line_height)
+	}
+
+	/// Width of the widest line and height of all lines of a text, at a size in pixels
+	pub fn measure(&self, text: &str, size: f32) -> (f32, f32){ self.shape(text, size, |_, _, _, _| {}) }
+
+	/// Rasterizes the glyphs of a text that aren't in the cache yet
+	pub fn prepare(&mut self, text: &str, size: f32) -> Result<(), TextureError>{
+		let quantized = quantize(size);
+		let mut keys = Vec::new();
+		{
+			let glyphs = &self.cache.glyphs;
+			self.shape(text, size, |face, id, _, _| {
+				let key = (face, id.0, quantized);
+				if !glyphs.contains_key(&key) && !keys.contains(&key) { keys.push(key) }
+			});
+		}
+		if keys.is_empty() { return Ok(()) }
+
+		if !try!(self.rasterize(&keys)){
+			// Start over with an empty page, keeping only what this text needs
+			self.cache.clear();
+			keys.clear();
+			self.shape(text, size, |face, id, _, _| {
+				let key = (face, id.0, quantized);
+				if !keys.contains(&key) { keys.push(key) }
+			});
+			if !try!(self.rasterize(&keys)){
+				error!("Glyphs of a text at {} pixels don't fit in a glyph cache page of {}x{}", size, self.cache.max_size, self.cache.max_size);
+				return Err(TextureError::DoesNotFit)
+			}
+		}
+		Ok(())
+	}
+
+	fn rasterize(&mut self, keys: &[Key]) -> Result<bool, TextureError>{
+		for &(face, id, size) in keys{
+			let glyph = self.faces[face].font.glyph(GlyphId(id))
+				.scaled(Scale::uniform(size as f32 / 64.0))
+				.positioned(point(0.0, 0.0));
+
+			let (offset, width, height, coverage) = match glyph.pixel_bounding_box(){
+				Some(bounds) => {
+					let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+					let mut coverage = vec![0u8; (width * height) as usize];
+					glyph.draw(|x, y, value| coverage[(y * width + x) as usize] = (value.min(1.0) * 255.0).round() as u8);
+					((bounds.min.x, bounds.min.y), width, height, coverage)
+				},
+				None => ((0, 0), 0, 0, Vec::new())
+			};
+			if !try!(self.cache.add((face, id, size), offset, width, height, &coverage)) { return Ok(false) }
+		}
+		Ok(true)
+	}
+
+	/**
+	 * Walks through the sprites of a text, calling back with each one and the position
+	 * of its top left corner, relative to the top left corner of the text. Glyphs are
+	 * placed on whole pixels, and only those already prepared are walked through.
+	 */
+	pub fn layout<'a, F: FnMut(&'a Sprite, f32, f32)>(&'a self, text: &str, size: f32, mut place: F) -> (f32, f32){
+		let quantized = quantize(size);
+		let glyphs = &self.cache.glyphs;
+		self.shape(text, size, |face, id, x, y| {
+			if let Some(&Cached{ sprite: Some(ref sprite), offset, .. }) = glyphs.get(&(face, id.0, quantized)){
+				place(sprite, x.round() + offset.0 as f32, y.round() + offset.1 as f32)
+			}
+		})
+	}
+}
+
+/// A scalable font at a given size
+pub struct FontSize<'a>{
+	pub font: &'a ScalableFont,
+	pub size: f32
+}
+
+#[cfg(test)]
+mod tests{
+	use super::OutlineFont;
+
+	#[test]
+	fn fallback(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let blocks  = OutlineFont::open("test/fonts/blocks.ttf").unwrap();
+		let accents = OutlineFont::open("test/fonts/accents.ttf").unwrap();
+		assert!(blocks.has_glyph('A') && !blocks.has_glyph('é'));
+		assert!(accents.has_glyph('é') && !accents.has_glyph('A'));
+		assert!(OutlineFont::from_bytes(b"not a font".to_vec()).is_err());
+	}
+
+	#[test]
+	fn layout(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Setup context
+		use glium::DisplayBuild;
+		use glium::glutin::WindowBuilder;
+		let display = WindowBuilder::new()
+			.with_dimensions(1280, 720)
+			.with_title("Automated test: outline::layout()")
+			.build_glium().unwrap();
+
+		use super::ScalableFont;
+		let mut font = ScalableFont::open(&display, "test/fonts/blocks.ttf").unwrap();
+		assert_eq!(font.line_height(20.0), 20.0);
+
+		// Kerning pulls the V closer to the A, and the accent comes from the fallback font
+		assert_eq!(font.measure("AV", 20.0), (18.0, 20.0));
+		assert_eq!(font.measure("A V\nA", 20.0), (26.0, 40.0));
+		assert_eq!(font.measure("é", 20.0), (12.0, 20.0));
+		font.add_fallback(OutlineFont::open("test/fonts/accents.ttf").unwrap());
+		assert_eq!(font.measure("é", 20.0), (12.0, 20.0));
+
+		// Glyphs are only rasterized once per size, and the space has nothing to draw
+		font.prepare("AVA Vé", 20.0).unwrap();
+		assert_eq!(font.cached_glyphs(), 4);
+		font.prepare("VA", 20.004).unwrap();
+		font.prepare("VA", 40.0).unwrap();
+		assert_eq!(font.cached_glyphs(), 6);
+
+		let mut placed = Vec::new();
+		font.layout("AVé", 20.0, |sprite, x, y| placed.push((sprite.width(), sprite.height(), x, y)));
+		assert_eq!(placed, vec![(10, 14, 0.0, 2.0), (10, 14, 8.0, 2.0), (10, 10, 19.0, 6.0)]);
+	}
+
+	#[test]
+	fn growth(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Setup context
+		use glium::DisplayBuild;
+		use glium::glutin::WindowBuilder;
+		let display = WindowBuilder::new()
+			.with_dimensions(1280, 720)
+			.with_title("Automated test: outline::growth()")
+			.build_glium().unwrap();
+
+		use super::ScalableFont;
+		let mut font = ScalableFont::open(&display, "test/fonts/blocks.ttf").unwrap();
+		font.set_max_page_size(512);
+
+		// Big glyphs make the page grow, until it can't and has to start over
+		font.prepare("AV", 300.0).unwrap();
+		assert_eq!(font.page_size(), (512, 256));
+		font.prepare("AV", 350.0).unwrap();
+		assert_eq!(font.page_size(), (512, 512));
+		assert_eq!(font.cached_glyphs(), 4);
+		font.prepare("AV", 500.0).unwrap();
+		assert_eq!(font.cached_glyphs(), 2);
+
+		// Nothing of the dropped glyphs is left on the page
+		let pixels: Vec<Vec<(u8, u8, u8, u8)>> = font.page().read();
+		let areas: Vec<(u32, u32, u32, u32)> = font.cache.glyphs.values().map(|cached| cached.area).collect();
+		for (y, row) in pixels.iter().enumerate(){
+			for (x, pixel) in row.iter().enumerate(){
+				let (x, y) = (x as u32, y as u32);
+				if areas.iter().any(|&(left, bottom, width, height)| x >= left && x < left + width && y >= bottom && y < bottom + height) { continue }
+				assert_eq!(pixel.3, 0);
+			}
+		}
+
+		assert!(font.prepare("A", 800.0).is_err());
+	}
+}
@@ -0,0 +1,382 @@
+use glium::{Surface, Texture2d, VertexBuffer, Program, DrawError};
+use glium::uniforms::{Uniforms, UniformValue, SamplerBehavior, SamplerWrapFunction, MinifySamplerFilter, MagnifySamplerFilter};
+use glium::index::{NoIndices, PrimitiveType};
+use glium::backend::Facade;
+
+use super::{Vertex, ShaderError, RendererError, compile};
+use super::target::RenderTarget;
+use super::material::Parameter;
+
+static POST_VERTEX_SHADER: &'static str = "
+	#version 140
+
+	in vec3 bbm_Position;
+	in vec2 bbm_TexCoord;
+	in vec3 bbm_Normal;
+
+	out vec2 texcoord;
+	void main(){ texcoord = bbm_TexCoord; gl_Position = vec4(bbm_Position, 1); }
+";
+static POST_COPY_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform sampler2D bbm_Texture;
+	void main(){ color = texture(bbm_Texture, texcoord); }
+";
+
+/// Curves the screen like an old CRT, darkening every other line and the corners
+pub static CRT_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform sampler2D bbm_Texture;
+	uniform vec2  bbm_Resolution;
+	uniform float bbm_Time;
+	uniform float bbm_Curvature;
+	uniform float bbm_Scanlines;
+	void main(){
+		vec2 centered = texcoord * 2.0 - 1.0;
+		centered *= 1.0 + bbm_Curvature * dot(centered.yx, centered.yx);
+		vec2 coord = centered * 0.5 + 0.5;
+		if(coord.x < 0.0 || coord.x > 1.0 || coord.y < 0.0 || coord.y > 1.0){ color = vec4(0, 0, 0, 1); return; }
+
+		// Colors bleed a little sideways
+		vec2 bleed = vec2(1.0 / bbm_Resolution.x, 0);
+		vec3 texel = vec3(texture(bbm_Texture, coord - bleed).r, texture(bbm_Texture, coord).g, texture(bbm_Texture, coord + bleed).b);
+
+		float line = 1.0 - bbm_Scanlines * (0.5 + 0.5 * sin(coord.y * bbm_Resolution.y * 3.14159 + bbm_Time * 2.0));
+		float vignette = clamp(pow(16.0 * coord.x * coord.y * (1.0 - coord.x) * (1.0 - coord.y), 0.2), 0.0, 1.0);
+		color = vec4(texel * line * vignette, 1);
+	}
+";
+
+/// Keeps what's brighter than a threshold and blurs it sideways, first step of a bloom
+pub static BLOOM_BRIGHT_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform sampler2D bbm_Texture;
+	uniform vec2  bbm_Resolution;
+	uniform float bbm_Threshold;
+	vec3 bright(vec2 coord){
+		vec3 texel = texture(bbm_Texture, coord).rgb;
+		return texel * step(bbm_Threshold, dot(texel, vec3(0.2126, 0.7152, 0.0722)));
+	}
+	void main(){
+		float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+		vec2 offset = vec2(1.0 / bbm_Resolution.x, 0);
+		vec3 sum = bright(texcoord) * weights[0];
+		for(int i = 1; i < 5; i++){ sum += (bright(texcoord + offset * float(i)) + bright(texcoord - offset * float(i))) * weights[i]; }
+		color = vec4(sum, 1);
+	}
+";
+
+/// Blurs the bright parts up and down, second step of a bloom
+pub static BLOOM_BLUR_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform sampler2D bbm_Texture;
+	uniform vec2 bbm_Resolution;
+	void main(){
+		float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+		vec2 offset = vec2(0, 1.0 / bbm_Resolution.y);
+		vec3 sum = texture(bbm_Texture, texcoord).rgb * weights[0];
+		for(int i = 1; i < 5; i++){ sum += (texture(bbm_Texture, texcoord + offset * float(i)).rgb + texture(bbm_Texture, texcoord - offset * float(i)).rgb) * weights[i]; }
+		color = vec4(sum, 1);
+	}
+";
+
+/// Adds the blurred bright parts back onto the scene, last step of a bloom
+pub static BLOOM_COMBINE_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform sampler2D bbm_Texture;
+	uniform sampler2D bbm_Input;
+	uniform float bbm_Intensity;
+	void main(){ color = vec4(texture(bbm_Input, texcoord).rgb + texture(bbm_Texture, texcoord).rgb * bbm_Intensity, 1); }
+";
+
+/// Washes the screen out into a color, as much as the alpha of the color says
+pub static FLASH_SHADER: &'static str = "
+	#version 140
+
+	in vec2 texcoord;
+	out vec4 color;
+
+	uniform sampler2D bbm_Texture;
+	uniform vec4 bbm_FlashColor;
+	void main(){ color = vec4(mix(texture(bbm_Texture, texcoord).rgb, bbm_FlashColor.rgb, bbm_FlashColor.a), 1); }
+";
+
+/**
+ * A full screen effect, made of one or more fragment shaders run over every pixel
+ * of the frame, one after the other.
+ *
+ * Shaders read `texcoord` and get `bbm_Texture`, the output of the shader or pass
+ * before them, `bbm_Input`, what the pass as a whole got, `bbm_Scene`, the frame as
+ * the scene drew it, `bbm_Time`, in seconds since the game started, and
+ * `bbm_Resolution`, the size of the frame in pixels. Any other uniform they declare
+ * can be set as a parameter of the pass, which all of its shaders share.
+ */
+pub struct Pass{
+	name: String,
+	stages: Vec<Program>,
+	parameters: Vec<(String, Parameter)>,
+	enabled: bool
+}
+impl Pass{
+	pub fn new<F: Facade>(facade: &F, name: &str, fragment: &str) -> Result<Pass, ShaderError>{
+		Pass::with_stages(facade, name, &[fragment])
+	}
+
+	/// A pass whose shaders run in the given order
+	pub fn with_stages<F: Facade>(facade: &F, name: &str, fragments: &[&str]) -> Result<Pass, ShaderError>{
+		let mut stages = Vec::with_capacity(fragments.len());
+		for fragment in fragments{
+			stages.push(try!(compile(facade, POST_VERTEX_SHADER, fragment, &format!(r#"shader of post processing pass "{}""#, name))));
+		}
+		Ok(Pass{
+			name: name.to_owned(),
+			stages: stages,
+			parameters: Vec::new(),
+			enabled: true
+		})
+	}
+
+	/// A CRT look, with parameters bbm_Curvature (0.1) and bbm_Scanlines (0.25)
+	pub fn crt<F: Facade>(facade: &F) -> Result<Pass, ShaderError>{
+		let mut pass = try!(Pass::new(facade, "crt", CRT_SHADER));
+		pass.set("bbm_Curvature", Parameter::Float(0.1));
+		pass.set("bbm_Scanlines", Parameter::Float(0.25));
+		Ok(pass)
+	}
+
+	/// A glow around bright colors, with parameters bbm_Threshold (0.7) and bbm_Intensity (1.0)
+	pub fn bloom<F: Facade>(facade: &F) -> Result<Pass, ShaderError>{
+		let mut pass = try!(Pass::with_stages(facade, "bloom", &[BLOOM_BRIGHT_SHADER, BLOOM_BLUR_SHADER, BLOOM_COMBINE_SHADER]));
+		pass.set("bbm_Threshold", Parameter::Float(0.7));
+		pass.set("bbm_Intensity", Parameter::Float(1.0));
+		Ok(pass)
+	}
+
+	/// A screen flash, whose color is bbm_FlashColor, fully transparent until the flash is triggered
+	pub fn flash<F: Facade>(facade: &F) -> Result<Pass, ShaderError>{
+		let mut pass = try!(Pass::new(facade, "flash", FLASH_SHADER));
+		pass.set("bbm_FlashColor", Parameter::Vec4([1.0, 1.0, 1.0, 0.0]));
+		Ok(pass)
+	}
+
+	pub fn name(&self) -> &str{ &self.name }
+
+	pub fn is_enabled(&self) -> bool{ self.enabled }
+	pub fn set_enabled(&mut self, enabled: bool){ self.enabled = enabled }
+
+	pub fn parameter(&self, name: &str) -> Option<Parameter>{
+		self.parameters.iter().find(|&&(ref parameter, _)| parameter == name).map(|&(_, value)| value)
+	}
+
+	pub fn set(&mut self, name: &str, value: Parameter){
+		match self.parameters.iter().position(|&(ref parameter, _)| parameter == name){
+			Some(index) => self.parameters[index].1 = value,
+			None => self.parameters.push((name.to_owned(), value))
+		}
+	}
+}
+
+/* Everything a pass's shader gets */
+struct PassUniforms<'b>{
+	texture: &'b Texture2d,
+	input:   &'b Texture2d,
+	scene:   &'b Texture2d,
+	time:  f32,
+	resolution: [f32; 2],
+	parameters: &'b [(String, Parameter)]
+}
+impl<'b> Uniforms for PassUniforms<'b>{
+	fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F){
+		// Frames are sampled smoothly, and never wrap around at the edges
+		let sampler = SamplerBehavior{
+			wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+			minify_filter:  MinifySamplerFilter::Linear,
+			magnify_filter: MagnifySamplerFilter::Linear,
+			..Default::default()
+		};
+		visit("bbm_Texture", UniformValue::Texture2d(self.texture, Some(sampler)));
+		visit("bbm_Input",   UniformValue::Texture2d(self.input,   Some(sampler)));
+		visit("bbm_Scene",   UniformValue::Texture2d(self.scene,   Some(sampler)));
+		visit("bbm_Time",    UniformValue::Float(self.time));
+		visit("bbm_Resolution", UniformValue::Vec2(self.resolution));
+		for &(ref name, ref value) in self.parameters{ visit(name, value.as_uniform()) }
+	}
+}
+
+/**
+ * A chain of full screen passes, each drawing the output of the one before it
+ * through its shader, the last one drawing straight onto the target surface.
+ */
+pub struct PostProcessor{
+	quad: VertexBuffer<Vertex>,
+	copy: Program, /* Draws the scene as it is, when no pass is enabled */
+	passes:  Vec<Pass>,
+	buffers: Vec<RenderTarget> /* Shaders draw into them in turns */
+}
+impl PostProcessor{
+	pub fn new<F: Facade>(facade: &F) -> Result<PostProcessor, RendererError>{
+		Ok(PostProcessor{
+			quad: match VertexBuffer::new(facade, &[
+				Vertex{ bbm_Position: [-1.0, -1.0, 0.0], bbm_TexCoord: [0.0, 0.0], bbm_Normal: [0.0, 0.0, 0.0] },
+				Vertex{ bbm_Position: [ 1.0, -1.0, 0.0], bbm_TexCoord: [1.0, 0.0], bbm_Normal: [0.0, 0.0, 0.0] },
+				Vertex{ bbm_Position: [-1.0,  1.0, 0.0], bbm_TexCoord: [0.0, 1.0], bbm_Normal: [0.0, 0.0, 0.0] },
+				Vertex{ bbm_Position: [ 1.0,  1.0, 0.0], bbm_TexCoord: [1.0, 1.0], bbm_Normal: [0.0, 0.0, 0.0] }
+			]){
+				Ok(vb) => vb,
+				Err(what) => {
+					error!("Could not register the post processing Vertex Buffer: {:?}", what);
+					return Err(RendererError::VertexBuffer(what))
+				}
+			},
+			copy: match compile(facade, POST_VERTEX_SHADER, POST_COPY_SHADER, "post processing copy shader"){
+				Ok(program) => program,
+				Err(what) => return Err(RendererError::Shader(what))
+			},
+			passes:  Vec::new(),
+			buffers: Vec::new()
+		})
+	}
+
+	/// Adds a pass at the end of the chain
+	pub fn push(&mut self, pass: Pass){ self.passes.push(pass) }
+	pub fn insert(&mut self, index: usize, pass: Pass){ self.passes.insert(index, pass) }
+
+	/// Takes the first pass with the given name out of the chain
+	pub fn remove(&mut self, name: &str) -> Option<Pass>{
+		match self.passes.iter().position(|pass| pass.name == name){
+			Some(index) => Some(self.passes.remove(index)),
+			None => None
+		}
+	}
+
+	pub fn passes(&self) -> &[Pass]{ &self.passes }
+	pub fn pass(&self, name: &str) -> Option<&Pass>{ self.passes.iter().find(|pass| pass.name == name) }
+	pub fn pass_mut(&mut self, name: &str) -> Option<&mut Pass>{ self.passes.iter_mut().find(|pass| pass.name == name) }
+
+	/// Runs a frame through every enabled pass, drawing the result onto the target
+	pub fn apply<F: Facade, S: Surface>(&mut self, facade: &F, scene: &Texture2d, target: &mut S, time: f32) -> Result<(), DrawError>{
+		let (width, height) = scene.dimensions();
+		let enabled = self.passes.iter().filter(|pass| pass.enabled && !pass.stages.is_empty()).collect::<Vec<&Pass>>();
+		let steps = enabled.iter().map(|pass| pass.stages.len()).sum::<usize>();
+		if steps == 0 { return self.draw(&self.copy, scene, scene, scene, target, time, &[]) }
+
+		// Every shader but the last one needs somewhere to draw into, apart from the input of its pass
+		let needed = if steps > 3 { 3 } else { steps - 1 };
+		let mut ready = true;
+		while ready && self.buffers.len() < needed{
+			match RenderTarget::new(facade, width, height){
+				Ok(buffer) => self.buffers.push(buffer),
+				Err(_) => ready = false
+			}
+		}
+		for buffer in self.buffers.iter_mut(){
+			if ready { ready = buffer.resize(facade, width, height).is_ok() }
+		}
+		if !ready{
+			warn!("Skipping post processing, could not create {}x{} buffers for it", width, height);
+			return self.draw(&self.copy, scene, scene, scene, target, time, &[])
+		}
+
+		// Buffers are referred to by index, with None standing for the scene itself
+		let texture = |buffer: Option<usize>| buffer.map(|index| self.buffers[index].texture()).unwrap_or(scene);
+		let mut current: Option<usize> = None;
+		let mut step = 0;
+		for pass in enabled{
+			let input = current;
+			for program in &pass.stages{
+				step += 1;
+				if step == steps{
+					return self.draw(program, texture(current), texture(input), scene, target, time, &pass.parameters)
+				}
+
+				let output = (0..self.buffers.len()).find(|index| Some(*index) != current && Some(*index) != input).unwrap();
+				try!(self.draw(program, texture(current), texture(input), scene, &mut self.buffers[output].surface(), time, &pass.parameters));
+				current = Some(output);
+			}
+		}
+		Ok(())
+	}
+
+	fn draw<S: Surface>(&self, program: &Program, texture: &Texture2d, input: &Texture2d, scene: &Texture2d, target: &mut S, time: f32, parameters: &[(String, Parameter)]) -> Result<(), DrawError>{
+		let (width, height) = scene.dimensions();
+		let uniforms = PassUniforms{
+			texture: texture,
+			input:   input,
+			scene:   scene,
+			time:    time,
+			resolution: [width as f32, height as f32],
+			parameters: parameters
+		};
+		target.draw(&self.quad, NoIndices(PrimitiveType::TriangleStrip), program, &uniforms, &Default::default())
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::{PostProcessor, Pass};
+	use super::super::{RenderTarget, Parameter, ShaderError};
+
+	#[test]
+	fn chain(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Setup context
+		use glium::DisplayBuild;
+		use glium::glutin::WindowBuilder;
+		let display = WindowBuilder::new()
+			.with_dimensions(1280, 720)
+			.with_title("Automated test: post::chain()")
+			.build_glium().unwrap();
+
+		let mut post = PostProcessor::new(&display).unwrap();
+		post.push(Pass::crt(&display).unwrap());
+		post.push(Pass::bloom(&display).unwrap());
+		post.push(Pass::flash(&display).unwrap());
+		assert_eq!(post.passes().iter().map(|pass| pass.name()).collect::<Vec<&str>>(), vec!["crt", "bloom", "flash"]);
+		match Pass::new(&display, "broken", "this is not glsl"){
+			Err(ShaderError::Compilation(_)) => {},
+			other => panic!("Expected a compilation error, got {:?}", other.err())
+		}
+
+		// A red scene flashed fully white comes out white
+		use glium::Surface;
+		let scene  = RenderTarget::new(&display, 64, 64).unwrap();
+		let output = RenderTarget::new(&display, 64, 64).unwrap();
+		scene.surface().clear_color(1.0, 0.0, 0.0, 1.0);
+
+		post.pass_mut("flash").unwrap().set("bbm_FlashColor", Parameter::Vec4([1.0, 1.0, 1.0, 1.0]));
+		post.apply(&display, scene.texture(), &mut output.surface(), 0.5).unwrap();
+		let pixels: Vec<Vec<(u8, u8, u8, u8)>> = output.texture().read();
+		assert_eq!(pixels[32][32], (255, 255, 255, 255));
+
+		// With every pass off, the scene comes out untouched
+		for name in &["crt", "bloom", "flash"]{ post.pass_mut(name).unwrap().set_enabled(false) }
+		post.apply(&display, scene.texture(), &mut output.surface(), 0.5).unwrap();
+		let pixels: Vec<Vec<(u8, u8, u8, u8)>> = output.texture().read();
+		assert_eq!(pixels[32][32], (255, 0, 0, 255));
+
+		assert_eq!(post.remove("crt").unwrap().parameter("bbm_Curvature"), Some(Parameter::Float(0.1)));
+		assert!(post.pass("crt").is_none());
+	}
+}
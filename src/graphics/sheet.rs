@@ -0,0 +1,408 @@
+use std::rc::Rc;
+use std::path::Path;
+
+use json::{self, JsonValue};
+use glium::backend::Facade;
+
+use super::{Texture, TextureError};
+use super::atlas::Sprite;
+use super::animation::{Clip, ClipFrame, PlaybackMode};
+
+/// Seconds a frame lasts when the exporter doesn't say
+const DEFAULT_DURATION: f32 = 0.1;
+
+/// Arrangement of a sheet of equally sized sprites
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SheetLayout{
+	pub sprite_width:  u32,
+	pub sprite_height: u32,
+	pub margin:  u32, /* Pixels between the border of the sheet and the sprites */
+	pub spacing: u32  /* Pixels between neighbouring sprites                     */
+}
+impl SheetLayout{
+	/// Sprites packed tightly against each other and the border of the sheet
+	pub fn new(sprite_width: u32, sprite_height: u32) -> SheetLayout{
+		SheetLayout{
+			sprite_width:  sprite_width,
+			sprite_height: sprite_height,
+			margin:  0,
+			spacing: 0
+		}
+	}
+
+	/// Number of columns and rows of sprites that fit in a sheet
+	pub fn dimensions(&self, width: u32, height: u32) -> (u32, u32){
+		let fit = |length: u32, sprite: u32| {
+			if length < self.margin * 2 + sprite || sprite == 0 { 0 }
+			else { (length - self.margin * 2 + self.spacing) / (sprite + self.spacing) }
+		};
+		(fit(width, self.sprite_width), fit(height, self.sprite_height))
+	}
+
+	/// Top left corner of the sprite at a column and row, in pixels
+	pub fn position(&self, column: u32, row: u32) -> (u32, u32){
+		(self.margin + column * (self.sprite_width + self.spacing), self.margin + row * (self.sprite_height + self.spacing))
+	}
+}
+
+use std::io::Error as IoError;
+#[derive(Debug)]
+pub enum SheetError{
+	UnableToReadFile(IoError),
+	Json(json::Error),
+
+	/// The descriptor is valid JSON, but not in a layout exported by Aseprite or TexturePacker
+	Malformed,
+	Texture(TextureError)
+}
+
+/// Where a frame is in its sheet, and how it's meant to be drawn
+#[derive(Clone, PartialEq, Debug)]
+pub struct FrameData{
+	pub name: String,
+	pub x: u32,
+	pub y: u32,
+	pub width:  u32, /* Size of the frame as it's drawn, even if it's rotated in the sheet */
+	pub height: u32,
+	pub rotated: bool, /* Whether the frame is stored turned 90 degrees clockwise */
+
+	pub offset:      (u32, u32), /* Position of the trimmed frame inside its original size */
+	pub source_size: (u32, u32), /* Size of the frame before its transparent border was trimmed */
+	pub pivot:       (f32, f32), /* Origin of the frame, relative to its original size */
+	pub duration:    f32
+}
+
+/// A range of frames that make up an animation
+#[derive(Clone, PartialEq, Debug)]
+pub struct Tag{
+	pub name: String,
+	pub from: usize,
+	pub to:   usize, /* Index of the last frame, which is part of the tag */
+	pub mode: PlaybackMode
+}
+
+/// Description of the frames in a sheet, without the image itself
+#[derive(Clone, PartialEq, Debug)]
+pub struct SheetData{
+	pub image:  Option<String>, /* Path of the image, relative to the descriptor */
+	pub frames: Vec<FrameData>,
+	pub tags:   Vec<Tag>
+}
+impl SheetData{
+	/// Frames of a uniform sheet, named after their index, left to right and top to bottom
+	pub fn grid(width: u32, height: u32, layout: &SheetLayout) -> SheetData{
+		let (columns, rows) = layout.dimensions(width, height);
+		let mut frames = Vec::new();
+		for row in 0..rows{
+			for column in 0..columns{
+				let (x, y) = layout.position(column, row);
+				frames.push(FrameData{
+					name: (row * columns + column).to_string(),
+					x: x,
+					y: y,
+					width:  layout.sprite_width,
+					height: layout.sprite_height,
+					rotated: false,
+
+					offset:      (0, 0),
+					source_size: (layout.sprite_width, layout.sprite_height),
+					pivot:       (0.0, 0.0),
+					duration:    DEFAULT_DURATION
+				});
+			}
+		}
+
+		SheetData{ image: None, frames: frames, tags: Vec::new() }
+	}
+
+	/**
+	 * Reads the JSON exported by Aseprite or TexturePacker, in either their hash
+	 * layout, where frames are keyed by name, or their array layout.
+	 */
+	pub fn from_json(source: &str) -> Result<SheetData, SheetError>{
+		let parsed = match json::parse(source){
+			Ok(parsed) => parsed,
+			Err(what) => {
+				error!("Could not parse sprite sheet descriptor: {:?}", what);
+				return Err(SheetError::Json(what))
+			}
+		};
+
+		let mut frames = Vec::new();
+		if parsed["frames"].is_object(){
+			for (name, frame) in parsed["frames"].entries(){ frames.push(try!(SheetData::frame(name, frame))) }
+		}else if parsed["frames"].is_array(){
+			for frame in parsed["frames"].members(){
+				let name = match frame["filename"].as_str(){
+					Some(name) => name,
+					None => {
+						error!("Sprite sheet frame is missing its file name");
+						return Err(SheetError::Malformed)
+					}
+				};
+				frames.push(try!(SheetData::frame(name, frame)));
+			}
+		}else{
+			error!("Sprite sheet descriptor has no frames");
+			return Err(SheetError::Malformed)
+		}
+
+		let mut tags = Vec::new();
+		for tag in parsed["meta"]["frameTags"].members(){
+			let (name, from, to) = match (tag["name"].as_str(), tag["from"].as_usize(), tag["to"].as_usize()){
+				(Some(name), Some(from), Some(to)) if from <= to && to < frames.len() => (name, from, to),
+				_ => {
+					error!("Sprite sheet tag {} is incomplete or out of range", tag.dump());
+					return Err(SheetError::Malformed)
+				}
+			};
+			let direction = tag["direction"].as_str().unwrap_or("forward");
+			tags.push(Tag{
+				name: name.to_owned(),
+				from: from,
+				to:   to,
+				mode: match PlaybackMode::from_direction(direction){
+					Some(mode) => mode,
+					None => {
+						warn!(r#"Unknown direction "{}" on tag "{}", playing it forward"#, direction, name);
+						PlaybackMode::Loop
+					}
+				}
+			});
+		}
+
+		Ok(SheetData{
+			image:  parsed["meta"]["image"].as_str().map(|image| image.to_owned()),
+			frames: frames,
+			tags:   tags
+		})
+	}
+
+	fn frame(name: &str, frame: &JsonValue) -> Result<FrameData, SheetError>{
+		let rectangle = |value: &JsonValue| match (value["x"].as_u32(), value["y"].as_u32(), value["w"].as_u32(), value["h"].as_u32()){
+			(Some(x), Some(y), Some(w), Some(h)) => Some((x, y, w, h)),
+			_ => None
+		};
+
+		let (x, y, width, height) = match rectangle(&frame["frame"]){
+			Some(rectangle) => rectangle,
+			None => {
+				error!(r#"Sprite sheet frame "{}" is missing its rectangle"#, name);
+				return Err(SheetError::Malformed)
+			}
+		};
+		let trimmed = rectangle(&frame["spriteSourceSize"]).unwrap_or((0, 0, width, height));
+
+		Ok(FrameData{
+			name: name.to_owned(),
+			x: x,
+			y: y,
+			width:  width,
+			height: height,
+			rotated: frame["rotated"].as_bool().unwrap_or(false),
+
+			offset: (trimmed.0, trimmed.1),
+			source_size: match (frame["sourceSize"]["w"].as_u32(), frame["sourceSize"]["h"].as_u32()){
+				(Some(w), Some(h)) => (w, h),
+				_ => (width, height)
+			},
+			pivot: (frame["pivot"]["x"].as_f32().unwrap_or(0.0), frame["pivot"]["y"].as_f32().unwrap_or(0.0)),
+			duration: frame["duration"].as_f32().map(|millis| millis / 1000.0).unwrap_or(DEFAULT_DURATION)
+		})
+	}
+}
+
+/// A named sprite from a sheet, along with how it's meant to be drawn
+#[derive(Clone)]
+pub struct Frame{
+	pub name:   String,
+	pub sprite: Sprite,
+
+	pub offset:      (f32, f32),
+	pub source_size: (f32, f32),
+	pub pivot:       (f32, f32),
+	pub duration:    f32
+}
+impl Frame{
+	/// Rectangle the sprite has to be drawn in for the pivot of the frame to land on a point
+	pub fn placement(&self, x: f32, y: f32) -> (f32, f32, f32, f32){
+		(
+			x - self.pivot.0 * self.source_size.0 + self.offset.0,
+			y - self.pivot.1 * self.source_size.1 + self.offset.1,
+			self.sprite.width()  as f32,
+			self.sprite.height() as f32
+		)
+	}
+}
+
+/**
+ * Named frames of a single texture, and the animations they make up.
+ *
+ * Every frame is a region of the same texture, so building a sheet reads nothing
+ * back from the GPU, and its sprites can all be drawn in the same batch.
+ */
+pub struct SpriteSheet{
+	frames: Vec<Frame>,
+	tags:   Vec<Tag>
+}
+impl SpriteSheet{
+	pub fn new(texture: Texture, data: SheetData) -> SpriteSheet{
+		let page = Rc::new(texture.0);
+		SpriteSheet{
+			frames: data.frames.into_iter().map(|frame| Frame{
+				sprite: if frame.rotated { Sprite::rotated_region(page.clone(), frame.x, frame.y, frame.width, frame.height) }
+					else { Sprite::region(page.clone(), frame.x, frame.y, frame.width, frame.height) },
+				name: frame.name,
+
+				offset:      (frame.offset.0 as f32, frame.offset.1 as f32),
+				source_size: (frame.source_size.0 as f32, frame.source_size.1 as f32),
+				pivot:       frame.pivot,
+				duration:    frame.duration
+			}).collect(),
+			tags: data.tags
+		}
+	}
+
+	/// Opens a descriptor exported by Aseprite or TexturePacker along with the image it refers to
+	pub fn open<F: Facade>(facade: &F, path: &str) -> Result<SpriteSheet, SheetError>{
+		use std::fs::File;
+		use std::io::Read;
+		let mut source = String::new();
+		if let Err(what) = File::open(path).and_then(|mut file| file.read_to_string(&mut source)){
+			error!(r#"Could not read sprite sheet descriptor at "{}": {:?}"#, path, what);
+			return Err(SheetError::UnableToReadFile(what))
+		}
+		let data = try!(SheetData::from_json(&source));
+
+		let image = match data.image{
+			Some(ref image) => Path::new(path).with_file_name(image),
+			None => {
+				error!(r#"Sprite sheet descriptor at "{}" doesn't say which image it belongs to"#, path);
+				return Err(SheetError::Malformed)
+			}
+		};
+		match Texture::open(facade, &image.to_string_lossy()){
+			Ok(texture) => Ok(SpriteSheet::new(texture, data)),
+			Err(what) => Err(SheetError::Texture(what))
+		}
+	}
+
+	pub fn frames(&self) -> &[Frame]{ &self.frames[..] }
+	pub fn frame(&self, name: &str) -> Option<&Frame>{ self.frames.iter().find(|frame| frame.name == name) }
+	pub fn sprite(&self, name: &str) -> Option<&Sprite>{ self.frame(name).map(|frame| &frame.sprite) }
+
+	pub fn tags(&self) -> &[Tag]{ &self.tags[..] }
+
+	/// The animation clip made up by the frames of a tag
+	pub fn clip(&self, tag: &str) -> Option<Clip>{
+		self.tags.iter().find(|t| t.name == tag).map(|tag| self.clip_of(tag))
+	}
+
+	/// Clips of every tag in the sheet
+	pub fn clips(&self) -> Vec<Clip>{ self.tags.iter().map(|tag| self.clip_of(tag)).collect() }
+
+	fn clip_of(&self, tag: &Tag) -> Clip{
+		Clip{
+			name:   tag.name.clone(),
+			frames: self.frames[tag.from..tag.to + 1].iter().map(|frame| ClipFrame{
+				sprite:   frame.sprite.clone(),
+				duration: frame.duration,
+				events:   Vec::new()
+			}).collect(),
+			mode: tag.mode,
+			loop_start: 0
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::{SheetData, SheetLayout, Tag};
+	use super::super::animation::PlaybackMode;
+
+	#[test]
+	fn layout(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut layout = SheetLayout::new(16, 16);
+		layout.margin  = 1;
+		layout.spacing = 2;
+
+		// 1 + 16 + 2 + 16 + 2 + 16 + 1 pixels wide, with room to spare for less than a sprite
+		assert_eq!(layout.dimensions(54, 30), (3, 1));
+		assert_eq!(layout.position(2, 1), (37, 19));
+
+		let data = SheetData::grid(54, 30, &layout);
+		assert_eq!(data.frames.len(), 3);
+		assert_eq!(data.frames[1].name, "1");
+		assert_eq!((data.frames[1].x, data.frames[1].y), (19, 1));
+		assert_eq!(SheetLayout::new(16, 16).dimensions(8, 8), (0, 0));
+	}
+
+	#[test]
+	fn aseprite(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let data = SheetData::from_json(r#"{
+			"frames": {
+				"bomber 0.aseprite": { "frame": { "x": 0, "y": 0, "w": 16, "h": 24 }, "rotated": false, "trimmed": false,
+					"spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 24 }, "sourceSize": { "w": 16, "h": 24 }, "duration": 100 },
+				"bomber 1.aseprite": { "frame": { "x": 16, "y": 0, "w": 16, "h": 24 }, "rotated": false, "trimmed": false,
+					"spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 24 }, "sourceSize": { "w": 16, "h": 24 }, "duration": 150 },
+				"bomber 2.aseprite": { "frame": { "x": 32, "y": 0, "w": 16, "h": 24 }, "rotated": false, "trimmed": false,
+					"spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 24 }, "sourceSize": { "w": 16, "h": 24 }, "duration": 100 }
+			},
+			"meta": {
+				"app": "http://www.aseprite.org/", "image": "bomber.png", "size": { "w": 48, "h": 24 },
+				"frameTags": [
+					{ "name": "idle", "from": 0, "to": 0, "direction": "forward" },
+					{ "name": "walk", "from": 1, "to": 2, "direction": "pingpong" }
+				]
+			}
+		}"#).unwrap();
+
+		assert_eq!(data.image, Some("bomber.png".to_owned()));
+		assert_eq!(data.frames.len(), 3);
+		assert_eq!(data.frames[1].name, "bomber 1.aseprite");
+		assert_eq!(data.frames[1].x, 16);
+		assert_eq!(data.frames[1].duration, 0.15);
+		assert_eq!(data.tags[1], Tag{ name: "walk".to_owned(), from: 1, to: 2, mode: PlaybackMode::PingPong });
+
+		// Tags pointing past the last frame are rejected
+		assert!(SheetData::from_json(r#"{ "frames": [], "meta": { "frameTags": [{ "name": "x", "from": 0, "to": 0 }] } }"#).is_err());
+		assert!(SheetData::from_json(r#"{ "meta": {} }"#).is_err());
+	}
+
+	#[test]
+	fn texture_packer(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let data = SheetData::from_json(r#"{
+			"frames": [
+				{ "filename": "flame.png", "frame": { "x": 2, "y": 2, "w": 12, "h": 30 }, "rotated": true, "trimmed": true,
+					"spriteSourceSize": { "x": 2, "y": 1, "w": 12, "h": 30 }, "sourceSize": { "w": 16, "h": 32 },
+					"pivot": { "x": 0.5, "y": 1 } },
+				{ "filename": "bomb.png", "frame": { "x": 34, "y": 2, "w": 16, "h": 16 }, "rotated": false, "trimmed": false,
+					"spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 }, "sourceSize": { "w": 16, "h": 16 } }
+			],
+			"meta": { "app": "https://www.codeandweb.com/texturepacker", "image": "actors.png" }
+		}"#).unwrap();
+
+		let flame = &data.frames[0];
+		assert_eq!(flame.name, "flame.png");
+		assert!(flame.rotated);
+		assert_eq!((flame.width, flame.height), (12, 30));
+		assert_eq!(flame.offset, (2, 1));
+		assert_eq!(flame.source_size, (16, 32));
+		assert_eq!(flame.pivot, (0.5, 1.0));
+		assert_eq!(flame.duration, 0.1);
+
+		let bomb = &data.frames[1];
+		assert!(!bomb.rotated);
+		assert_eq!(bomb.pivot, (0.0, 0.0));
+		assert!(data.tags.is_empty());
+	}
+}
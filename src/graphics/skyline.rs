@@ -0,0 +1,100 @@
+/// Free space of a texture page, kept as the height of the packed area along its width
+#[derive(Clone, Debug)]
+pub struct Skyline{
+	width:  u32,
+	height: u32,
+	segments: Vec<(u32, u32, u32)> /* Start, height and width of each level of the skyline */
+}
+impl Skyline{
+	pub fn new(width: u32, height: u32) -> Skyline{
+		Skyline{
+			width:  width,
+			height: height,
+			segments: vec![(0, 0, width)]
+		}
+	}
+
+	/// Finds room for a rectangle as low and then as far left as possible, returning its top left corner
+	pub fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)>{
+		let mut best: Option<(usize, u32, u32)> = None;
+		for (index, &(x, _, _)) in self.segments.iter().enumerate(){
+			if x + width > self.width { break }
+
+			// The rectangle rests on the highest level it spans
+			let mut y = 0;
+			let mut covered = 0;
+			for &(_, level, span) in &self.segments[index..]{
+				if level > y { y = level }
+				covered += span;
+				if covered >= width { break }
+			}
+			if y + height > self.height { continue }
+
+			match best{
+				Some((_, best_x, best_y)) if (y, x) >= (best_y, best_x) => {},
+				_ => best = Some((index, x, y))
+			}
+		}
+
+		best.map(|(index, x, y)| {
+			// Raise every level under the rectangle, splitting the last one if it sticks out
+			let mut segments = self.segments[..index].to_vec();
+			segments.push((x, y + height, width));
+			for &(start, level, span) in &self.segments[index..]{
+				if start + span <= x + width { continue }
+				if start < x + width { segments.push((x + width, level, start + span - x - width)) }
+				else { segments.push((start, level, span)) }
+			}
+
+			// Merge neighbouring levels at the same height
+			self.segments = segments.into_iter().fold(Vec::new(), |mut merged: Vec<(u32, u32, u32)>, segment| {
+				match merged.last_mut(){
+					Some(last) if last.1 == segment.1 => { last.2 += segment.2; return merged },
+					_ => {}
+				}
+				merged.push(segment);
+				merged
+			});
+			(x, y)
+		})
+	}
+
+	/// Makes room for more rectangles to the right and below, keeping the ones already packed in place
+	pub fn grow(&mut self, width: u32, height: u32){
+		if width > self.width{
+			let extended = match self.segments.last_mut(){
+				Some(last) if last.1 == 0 => { last.2 += width - self.width; true },
+				_ => false
+			};
+			if !extended { self.segments.push((self.width, 0, width - self.width)) }
+			self.width = width;
+		}
+		if height > self.height { self.height = height }
+	}
+}
+
+#[cfg(test)]
+mod tests{
+	use super::Skyline;
+
+	#[test]
+	fn packing(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let mut skyline = Skyline::new(16, 16);
+		assert_eq!(skyline.insert(8, 4), Some((0, 0)));
+		assert_eq!(skyline.insert(4, 8), Some((8, 0)));
+		assert_eq!(skyline.insert(4, 2), Some((12, 0)));
+
+		// The lowest spot wins, even if it's further right
+		assert_eq!(skyline.insert(4, 4), Some((12, 2)));
+		assert_eq!(skyline.insert(8, 8), Some((0, 4)));
+		assert_eq!(skyline.segments, vec![(0, 12, 8), (8, 8, 4), (12, 6, 4)]);
+
+		assert_eq!(skyline.insert(17, 1), None);
+		assert_eq!(skyline.insert(16, 5), None);
+		assert_eq!(skyline.insert(16, 4), Some((0, 12)));
+		assert_eq!(skyline.segments, vec![(0, 16, 16)]);
+	}
+}
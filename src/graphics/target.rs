@@ -0,0 +1,41 @@
+use glium::Texture2d;
+use glium::texture::{UncompressedFloatFormat, MipmapsOption};
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::backend::Facade;
+
+use super::{TextureProvider, TextureError};
+
+/**
+ * A texture that can be drawn into like the window, and then drawn onto something
+ * else like any other texture, which is how whole frames get post processed.
+ */
+pub struct RenderTarget{
+	texture: Texture2d
+}
+impl RenderTarget{
+	pub fn new<F: Facade>(facade: &F, width: u32, height: u32) -> Result<RenderTarget, TextureError>{
+		match Texture2d::empty_with_format(facade, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, width, height){
+			Ok(texture) => Ok(RenderTarget{ texture: texture }),
+			Err(what) => {
+				error!("Could not create {}x{} render target: {:?}", width, height, what);
+				Err(TextureError::TextureCreation(what))
+			}
+		}
+	}
+
+	/// Surface to draw into, which needs clearing before every frame like the window does
+	pub fn surface(&self) -> SimpleFrameBuffer{ self.texture.as_surface() }
+	pub fn texture(&self) -> &Texture2d{ &self.texture }
+
+	pub fn dimensions(&self) -> (u32, u32){ self.texture.dimensions() }
+
+	/// Replaces the texture with one of another size, throwing away whatever was drawn
+	pub fn resize<F: Facade>(&mut self, facade: &F, width: u32, height: u32) -> Result<(), TextureError>{
+		if self.dimensions() == (width, height) { return Ok(()) }
+		*self = try!(RenderTarget::new(facade, width, height));
+		Ok(())
+	}
+}
+impl TextureProvider for RenderTarget{
+	fn get_texture(&self) -> &Texture2d{ &self.texture }
+}
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+
+use super::atlas::Sprite;
+use super::font::BitmapFont;
+use super::outline::FontSize;
+
+/// Fonts rich text can be laid out and drawn with
+pub trait TextFont{
+	/// Distance between the tops of two lines of text
+	fn line_height(&self) -> f32;
+
+	/// Width of the widest line and height of all lines of a text
+	fn measure(&self, text: &str) -> (f32, f32);
+
+	/// Walks through the sprites of a text, calling back with each one and the position of its top left corner
+	fn layout<'a, F: FnMut(&'a Sprite, f32, f32)>(&'a self, text: &str, place: F) -> (f32, f32);
+}
+impl TextFont for BitmapFont{
+	fn line_height(&self) -> f32{ BitmapFont::line_height(self) }
+	fn measure(&self, text: &str) -> (f32, f32){ BitmapFont::measure(self, text) }
+	fn layout<'a, F: FnMut(&'a Sprite, f32, f32)>(&'a self, text: &str, place: F) -> (f32, f32){ BitmapFont::layout(self, text, place) }
+}
+impl<'b> TextFont for FontSize<'b>{
+	fn line_height(&self) -> f32{ self.font.line_height(self.size) }
+	fn measure(&self, text: &str) -> (f32, f32){ self.font.measure(text, self.size) }
+	fn layout<'a, F: FnMut(&'a Sprite, f32, f32)>(&'a self, text: &str, place: F) -> (f32, f32){ self.font.layout(text, self.size, place) }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MarkupError{
+	/// A tag that isn't one of `color`, `wave`, `shake` or `icon`
+	UnknownTag(String),
+	/// A tag that needs a value, like `[color=#fff]`, was given none
+	MissingValue(String),
+	InvalidColor(String),
+	/// A tag was closed while another one, opened after it, was still open
+	MismatchedClose(String),
+	/// The text ended with tags still open
+	UnclosedTag(String),
+	/// A `[` without its `]`, literal brackets are written as `[[`
+	UnterminatedTag
+}
+
+/// How text looks, and how it moves, from one tag to the next
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Style{
+	pub color: (f32, f32, f32, f32),
+	pub wave:  bool, /* Glyphs bob up and down, one after the other */
+	pub shake: bool  /* Glyphs jitter around in place              */
+}
+impl Default for Style{
+	fn default() -> Style{
+		Style{
+			color: (1.0, 1.0, 1.0, 1.0),
+			wave:  false,
+			shake: false
+		}
+	}
+}
+
+/* Effects are measured in line heights, so they look the same at any size */
+const WAVE_AMPLITUDE:  f32 = 0.12;
+const WAVE_SPEED:      f32 = 6.0;  /* Radians per second                 */
+const WAVE_PHASE:      f32 = 0.6;  /* Radians each glyph lags behind     */
+const SHAKE_AMPLITUDE: f32 = 0.06;
+const SHAKE_RATE:      f32 = 20.0; /* Times per second glyphs jump about */
+
+/* Hashes a number into another one between -1 and 1 */
+fn noise(seed: u32) -> f32{
+	let mut x = seed.wrapping_mul(0x9E3779B9);
+	x ^= x >> 16;
+	x = x.wrapping_mul(0x85EBCA6B);
+	x ^= x >> 13;
+	(x & 0xFFFF) as f32 / 32767.5 - 1.0
+}
+
+impl Style{
+	/// How far a glyph, numbered from the start of the text, is moved by the effects at some point in time
+	pub fn offset(&self, glyph: usize, time: f32, line_height: f32) -> (f32, f32){
+		let (mut x, mut y) = (0.0, 0.0);
+		if self.wave{
+			y += (time * WAVE_SPEED - glyph as f32 * WAVE_PHASE).sin() * WAVE_AMPLITUDE * line_height;
+		}
+		if self.shake{
+			let seed = (glyph as u32).wrapping_mul(7919).wrapping_add((time * SHAKE_RATE) as u32);
+			x += noise(seed.wrapping_mul(2)) * SHAKE_AMPLITUDE * line_height;
+			y += noise(seed.wrapping_mul(2).wrapping_add(1)) * SHAKE_AMPLITUDE * line_height;
+		}
+		(x, y)
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Span{
+	Text(String, Style),
+	Icon(String, Style)
+}
+
+/**
+ * Text with inline markup, which styles whatever is between an opening and a
+ * closing tag, like in `Press [color=#ff0][icon=bomb] to drop a [wave]bomb[/wave][/color]`.
+ *
+ * Tags are `[color=#rgb]` (or `#rgba`, `#rrggbb` and `#rrggbbaa`), `[wave]` and
+ * `[shake]`, which have to be closed in the opposite order they were opened, and
+ * `[icon=name]`, which is drawn in place of a sprite, and doesn't need closing.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct RichText{
+	spans: Vec<Span>
+}
+impl RichText{
+	pub fn parse(markup: &str) -> Result<RichText, MarkupError>{
+		let mut spans = Vec::new();
+		let mut text  = String::new();
+		let mut tags: Vec<(String, Style)> = Vec::new();
+		let mut style = Style::default();
+
+		let mut characters = markup.chars().peekable();
+		while let Some(character) = characters.next(){
+			if character != '[' { text.push(character); continue }
+			if characters.peek() == Some(&'['){
+				characters.next();
+				text.push('[');
+				continue
+			}
+
+			let mut tag = String::new();
+			loop{
+				match characters.next(){
+					Some(']') => break,
+					Some(character) => tag.push(character),
+					None => {
+						error!(r#"Tag "[{}" is missing its closing bracket"#, tag);
+						return Err(MarkupError::UnterminatedTag)
+					}
+				}
+			}
+			if !text.is_empty() { spans.push(Span::Text(text.clone(), style)); text.clear() }
+
+			// Closing tags bring back the style from before the tag was opened
+			if tag.starts_with('/'){
+				let name = tag.trim_left_matches('/').trim().to_owned();
+				match tags.pop(){
+					Some((ref open, previous)) if *open == name => style = previous,
+					_ => {
+						error!(r#"Closing tag "[{}]" doesn't match the last open tag"#, tag);
+						return Err(MarkupError::MismatchedClose(name))
+					}
+				}
+				continue
+			}
+
+			let (name, value) = match tag.find('='){
+				Some(split) => (tag[..split].trim().to_owned(), Some(tag[split + 1..].trim().to_owned())),
+				None => (tag.trim().to_owned(), None)
+			};
+			let value = match (name.as_str(), value){
+				("color", Some(value)) | ("icon", Some(value)) => value,
+				("color", None) | ("icon", None) => {
+					error!(r#"Tag "[{}]" needs a value"#, tag);
+					return Err(MarkupError::MissingValue(name.clone()))
+				},
+				("wave", _) | ("shake", _) => String::new(),
+				_ => {
+					error!(r#"Unknown tag "[{}]""#, tag);
+					return Err(MarkupError::UnknownTag(name.clone()))
+				}
+			};
+
+			let previous = style;
+			match name.as_str(){
+				"icon" => { spans.push(Span::Icon(value, style)); continue },
+				"color" => style.color = try!(parse_color(&value)),
+				"wave"  => style.wave  = true,
+				_       => style.shake = true
+			}
+			tags.push((name, previous));
+		}
+
+		if let Some((name, _)) = tags.pop(){
+			error!(r#"Tag "[{}]" is never closed"#, name);
+			return Err(MarkupError::UnclosedTag(name))
+		}
+		if !text.is_empty() { spans.push(Span::Text(text, style)) }
+		Ok(RichText{ spans: spans })
+	}
+
+	/// The text without any of its markup, or its icons
+	pub fn plain(&self) -> String{
+		self.spans.iter().fold(String::new(), |mut plain, span| {
+			if let Span::Text(ref text, _) = *span { plain.push_str(text) }
+			plain
+		})
+	}
+
+	/**
+	 * Breaks the text into lines no wider than the given width, if any, and places
+	 * every word on them. Words are split at spaces and newlines, and words too long
+	 * for a line of their own are split wherever they have to be. Icons are as tall
+	 * as a line, and icons missing from the given set are left out.
+	 */
+	pub fn layout<T: TextFont>(&self, font: &T, icons: &HashMap<String, Sprite>, width: Option<f32>, align: Align) -> TextLayout{
+		let line_height = font.line_height();
+		let space = font.measure(" ").0;
+
+		// Collect the words of every paragraph
+		let mut paragraphs: Vec<Vec<Word>> = vec![Vec::new()];
+		let mut word = Word::new();
+		let mut glyphs = 0;
+		for span in &self.spans{
+			match *span{
+				Span::Text(ref text, style) => for character in text.chars(){
+					match character{
+						' ' | '\t' | '\n' => {
+							if !word.pieces.is_empty() { paragraphs.last_mut().unwrap().push(word); word = Word::new() }
+							if character == '\n' { paragraphs.push(Vec::new()) }
+						},
+						'\r' => {},
+						_ => {
+							word.push_char(character, style, glyphs);
+							glyphs += 1;
+						}
+					}
+				},
+				Span::Icon(ref name, style) => match icons.get(name){
+					Some(sprite) => {
+						let width = sprite.width() as f32 * line_height / sprite.height() as f32;
+						word.pieces.push(Piece{ item: Item::Icon(sprite.clone()), style: style, glyph: glyphs, width: width });
+						word.width += width;
+						glyphs += 1;
+					},
+					None => warn!(r#"Leaving out icon "{}", which is not in the icon set"#, name)
+				}
+			}
+		}
+		if !word.pieces.is_empty() { paragraphs.last_mut().unwrap().push(word) }
+
+		// Fill lines with as many words as they fit
+		let mut lines: Vec<(Vec<Word>, bool)> = Vec::new();
+		for paragraph in paragraphs{
+			let mut line: Vec<Word> = Vec::new();
+			let mut used = 0.0;
+			for mut word in paragraph{
+				word.measure(font);
+				if let Some(width) = width{
+					if !line.is_empty() && used + space + word.width > width{
+						lines.push((line, false));
+						line = Vec::new();
+					}
+					while line.is_empty() && word.width > width && !word.pieces.is_empty(){
+						let rest = word.split(font, width);
+						lines.push((vec![word], false));
+						word = rest;
+					}
+					if word.pieces.is_empty() { continue }
+				}
+				used = if line.is_empty() { word.width } else { used + space + word.width };
+				line.push(word);
+			}
+			lines.push((line, true));
+		}
+
+		// Place the words on every line
+		let line_width = |line: &[Word]| line.iter().map(|word| word.width).sum::<f32>() + space * line.len().saturating_sub(1) as f32;
+		let widest = lines.iter().map(|line| line_width(&line.0)).fold(0.0, f32::max);
+		let width = width.unwrap_or(widest);
+
+		let mut items = Vec::new();
+		for (row, &(ref line, last)) in lines.iter().enumerate(){
+			let extra = width - line_width(line);
+			let (mut x, gap) = match align{
+				Align::Left   => (0.0, space),
+				Align::Center => (extra / 2.0, space),
+				Align::Right  => (extra, space),
+				Align::Justify if !last && line.len() > 1 => (0.0, space + extra / (line.len() - 1) as f32),
+				Align::Justify => (0.0, space)
+			};
+			for word in line{
+				for piece in &word.pieces{
+					items.push(Placed{
+						item:   piece.item.clone(),
+						style:  piece.style,
+						glyph:  piece.glyph,
+						x:      x,
+						y:      row as f32 * line_height,
+						width:  piece.width,
+						height: line_height
+					});
+					x += piece.width;
+				}
+				x += gap;
+			}
+		}
+
+		TextLayout{
+			items:  items,
+			lines:  lines.len(),
+			width:  widest,
+			height: lines.len() as f32 * line_height,
+			line_height: line_height
+		}
+	}
+}
+
+/* Parses colors in the #rgb, #rgba, #rrggbb and #rrggbbaa forms */
+fn parse_color(color: &str) -> Result<(f32, f32, f32, f32), MarkupError>{
+	let digits = color.trim_left_matches('#');
+	let values = digits.chars().map(|digit| digit.to_digit(16)).collect::<Option<Vec<u32>>>();
+
+	let channels = match values{
+		Some(ref values) if color.starts_with('#') && (values.len() == 3 || values.len() == 4) =>
+			values.iter().map(|value| value * 17).collect::<Vec<u32>>(),
+		Some(ref values) if color.starts_with('#') && (values.len() == 6 || values.len() == 8) =>
+			values.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect::<Vec<u32>>(),
+		_ => {
+			error!(r#"Color "{}" is not in any of the #rgb, #rgba, #rrggbb or #rrggbbaa forms"#, color);
+			return Err(MarkupError::InvalidColor(color.to_owned()))
+		}
+	};
+	let alpha = if channels.len() == 4 { channels[3] } else { 255 };
+	Ok((channels[0] as f32 / 255.0, channels[1] as f32 / 255.0, channels[2] as f32 / 255.0, alpha as f32 / 255.0))
+}
+
+/// How lines are placed in the width of a layout
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Align{
+	Left,
+	Center,
+	Right,
+	/// Words are spread out to fill the whole width, except on the last line of every paragraph
+	Justify
+}
+
+#[derive(Clone)]
+pub enum Item{
+	Text(String),
+	Icon(Sprite)
+}
+
+/// A stretch of text in a single style, or an icon, placed in a layout
+#[derive(Clone)]
+pub struct Placed{
+	pub item:  Item,
+	pub style: Style,
+	pub glyph: usize, /* Number of the first glyph in the whole text, which effects are timed by */
+
+	pub x: f32,
+	pub y: f32,
+	pub width:  f32,
+	pub height: f32
+}
+
+/* A run of characters and icons without spaces in between, broken into pieces of the same style */
+struct Piece{
+	item:  Item,
+	style: Style,
+	glyph: usize,
+	width: f32
+}
+impl Piece{
+	fn glyph_count(&self) -> usize{
+		match self.item{
+			Item::Text(ref text) => text.chars().count(),
+			Item::Icon(_) => 1
+		}
+	}
+}
+struct Word{
+	pieces: Vec<Piece>,
+	width:  f32
+}
+impl Word{
+	fn new() -> Word{ Word{ pieces: Vec::new(), width: 0.0 } }
+
+	fn push_char(&mut self, character: char, style: Style, glyph: usize){
+		if let Some(&mut Piece{ item: Item::Text(ref mut text), style: ref last, .. }) = self.pieces.last_mut(){
+			if *last == style { text.push(character); return }
+		}
+		let mut text = String::new();
+		text.push(character);
+		self.pieces.push(Piece{ item: Item::Text(text), style: style, glyph: glyph, width: 0.0 });
+	}
+
+	fn measure<T: TextFont>(&mut self, font: &T){
+		for piece in self.pieces.iter_mut(){
+			if let Item::Text(ref text) = piece.item { piece.width = font.measure(text).0 }
+		}
+		self.width = self.pieces.iter().map(|piece| piece.width).sum();
+	}
+
+	/* Keeps as much of the word as fits in a width, at least one glyph, returning the rest of it */
+	fn split<T: TextFont>(&mut self, font: &T, width: f32) -> Word{
+		let pieces = self.pieces.split_off(0);
+		let mut rest = Word::new();
+		let mut used = 0.0;
+		for piece in pieces{
+			if !rest.pieces.is_empty() || (used + piece.width > width && !self.pieces.is_empty() && piece.glyph_count() == 1){
+				rest.pieces.push(piece);
+				continue
+			}
+			if used + piece.width <= width || piece.glyph_count() == 1{
+				used += piece.width;
+				self.pieces.push(piece);
+				continue
+			}
+
+			// Split the piece that sticks out between its characters
+			let text = match piece.item{ Item::Text(ref text) => text.clone(), Item::Icon(_) => unreachable!() };
+			let mut kept = 0;
+			for (offset, character) in text.char_indices(){
+				let end = offset + character.len_utf8();
+				if used + font.measure(&text[..end]).0 > width { break }
+				kept = end;
+			}
+			if kept == 0 && self.pieces.is_empty() { kept = text.chars().next().unwrap().len_utf8() }
+
+			if kept > 0{
+				self.pieces.push(Piece{ item: Item::Text(text[..kept].to_owned()), style: piece.style, glyph: piece.glyph, width: 0.0 });
+			}
+			if kept < text.len(){
+				let glyph = piece.glyph + text[..kept].chars().count();
+				rest.pieces.push(Piece{ item: Item::Text(text[kept..].to_owned()), style: piece.style, glyph: glyph, width: 0.0 });
+			}
+		}
+		self.measure(font);
+		rest.measure(font);
+		rest
+	}
+}
+
+/// Rich text broken into lines, ready to be drawn
+pub struct TextLayout{
+	items: Vec<Placed>,
+	lines: usize,
+	width:  f32,
+	height: f32,
+	line_height: f32
+}
+impl TextLayout{
+	pub fn items(&self) -> &[Placed]{ &self.items }
+	pub fn lines(&self) -> usize{ self.lines }
+	pub fn line_height(&self) -> f32{ self.line_height }
+
+	/// Width of the widest line and height of all lines
+	pub fn size(&self) -> (f32, f32){ (self.width, self.height) }
+}
+
+#[cfg(test)]
+mod tests{
+	use std::collections::HashMap;
+	use super::{RichText, MarkupError, TextFont, Style, Align, Item};
+	use super::super::Sprite;
+
+	/* Every character is 10 pixels wide, without drawing anything */
+	struct Monospace;
+	impl TextFont for Monospace{
+		fn line_height(&self) -> f32{ 20.0 }
+		fn measure(&self, text: &str) -> (f32, f32){ (text.chars().count() as f32 * 10.0, 20.0) }
+		fn layout<'a, F: FnMut(&'a Sprite, f32, f32)>(&'a self, text: &str, _: F) -> (f32, f32){ self.measure(text) }
+	}
+
+	fn words(text: &RichText, width: Option<f32>, align: Align) -> Vec<(String, f32, f32)>{
+		text.layout(&Monospace, &HashMap::new(), width, align).items().iter().map(|placed| match placed.item{
+			Item::Text(ref text) => (text.clone(), placed.x, placed.y),
+			Item::Icon(_) => (String::from("icon"), placed.x, placed.y)
+		}).collect()
+	}
+
+	#[test]
+	fn markup(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let text = RichText::parse("Press [color=#ff0]X[/color] to [wave]drop [shake]a[/shake][/wave] [[bomb]").unwrap();
+		assert_eq!(text.plain(), "Press X to drop a [bomb]");
+
+		let layout = text.layout(&Monospace, &HashMap::new(), None, Align::Left);
+		let styles = layout.items().iter().map(|placed| placed.style).collect::<Vec<Style>>();
+		assert_eq!(styles[1].color, (1.0, 1.0, 0.0, 1.0));
+		assert!(styles[3].wave && !styles[3].shake);
+		assert!(styles[4].wave && styles[4].shake);
+		assert_eq!(styles[5], Style::default());
+
+		// Glyphs are numbered through the whole text, spaces left out
+		assert_eq!(layout.items().iter().map(|placed| placed.glyph).collect::<Vec<usize>>(), vec![0, 5, 6, 8, 12, 13]);
+
+		assert_eq!(RichText::parse("[bold]x[/bold]"), Err(MarkupError::UnknownTag("bold".to_owned())));
+		assert_eq!(RichText::parse("[wave][shake]x[/wave]"), Err(MarkupError::MismatchedClose("wave".to_owned())));
+		assert_eq!(RichText::parse("[wave]x"), Err(MarkupError::UnclosedTag("wave".to_owned())));
+		assert_eq!(RichText::parse("[color=#12]x[/color]"), Err(MarkupError::InvalidColor("#12".to_owned())));
+		assert_eq!(RichText::parse("[icon]"), Err(MarkupError::MissingValue("icon".to_owned())));
+		assert_eq!(RichText::parse("[wave"), Err(MarkupError::UnterminatedTag));
+
+		// Icons missing from the set take up no room
+		let text = RichText::parse("a[icon=bomb]b").unwrap();
+		assert_eq!(words(&text, None, Align::Left), vec![("ab".to_owned(), 0.0, 0.0)]);
+	}
+
+	#[test]
+	fn wrapping(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let text = RichText::parse("the quick brown fox\njumps").unwrap();
+		let layout = text.layout(&Monospace, &HashMap::new(), Some(100.0), Align::Left);
+		assert_eq!(layout.lines(), 3);
+		assert_eq!(layout.size(), (90.0, 60.0));
+
+		let line = |align| words(&text, Some(100.0), align).into_iter().map(|(_, x, _)| x).collect::<Vec<f32>>();
+		assert_eq!(line(Align::Left),    vec![0.0, 40.0,  0.0,  60.0, 0.0]);
+		assert_eq!(line(Align::Right),   vec![10.0, 50.0, 10.0, 70.0, 50.0]);
+		assert_eq!(line(Align::Center),  vec![5.0, 45.0,  5.0,  65.0, 25.0]);
+		assert_eq!(line(Align::Justify), vec![0.0, 50.0,  0.0,  60.0, 0.0]);
+
+		// Words too long for a line are split between their characters
+		let text = RichText::parse("a [color=#f00]abc[/color]defghijkl").unwrap();
+		assert_eq!(words(&text, Some(50.0), Align::Left), vec![
+			("a".to_owned(), 0.0, 0.0),
+			("abc".to_owned(), 0.0, 20.0),
+			("de".to_owned(), 30.0, 20.0),
+			("fghij".to_owned(), 0.0, 40.0),
+			("kl".to_owned(), 0.0, 60.0)
+		]);
+	}
+
+	#[test]
+	fn effects(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		let still = Style::default();
+		assert_eq!(still.offset(3, 1.5, 20.0), (0.0, 0.0));
+
+		// Waves move glyphs up and down only, and never further than a fraction of a line
+		let wave = Style{ wave: true, ..Style::default() };
+		let (x, y) = wave.offset(3, 1.5, 20.0);
+		assert_eq!(x, 0.0);
+		assert!(y != 0.0 && y.abs() <= 20.0 * 0.12);
+		assert!(wave.offset(4, 1.5, 20.0) != (x, y));
+
+		// Shaking stays put for a moment, and moves every glyph its own way
+		let shake = Style{ shake: true, ..Style::default() };
+		assert_eq!(shake.offset(3, 1.5, 20.0), shake.offset(3, 1.51, 20.0));
+		assert!(shake.offset(3, 1.5, 20.0) != shake.offset(4, 1.5, 20.0));
+		assert!(shake.offset(3, 1.5, 20.0) != shake.offset(3, 1.6, 20.0));
+
+		// Seeds wrap around in long texts instead of overflowing
+		let (x, y) = shake.offset(300000, 1.5, 20.0);
+		assert!(x.abs() <= 20.0 * 0.06 && y.abs() <= 20.0 * 0.06);
+	}
+}
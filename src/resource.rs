@@ -1,15 +1,20 @@
 use std::collections::hash_map::{HashMap, Entry};
 use std::path::{PathBuf};
+use std::rc::Rc;
 
-use super::graphics::{Texture, TextureProvider};
+use super::graphics::{Texture, TextureProvider, BitmapFont};
+use super::audio::{Sound, Codec};
 pub enum Resource{
 	Text(String),
 	Texture(Texture),
+	Sound(Rc<Sound>),
+	Font(Rc<BitmapFont>),
 	Raw(Vec<u8>)
 }
 
 use std::io::Error as IoError;
-use super::graphics::TextureError;
+use super::graphics::{TextureError, FontError};
+use super::audio::AudioError;
 #[derive(Debug)]
 pub enum ResourceError{
 	DifferentTypeAlreadyCached,
@@ -18,6 +23,12 @@ pub enum ResourceError{
 	UnableToReadFile(IoError),
 
 	UnableToCreateTexture(TextureError),
+
+	/// The file isn't in any of the supported audio containers
+	UnknownSoundFormat,
+	UnableToDecodeSound(AudioError),
+
+	UnableToLoadFont(FontError)
 }
 
 pub struct Resources{
@@ -106,6 +117,111 @@ impl Resources{
 		}
 	}
 
+	/**
+	 * Decodes a sound and caches it for later reuse. The codec is picked by looking
+	 * at the first bytes of the file, so the extension doesn't matter.
+	 */
+	pub fn sound(&mut self, uri: &str) -> Result<Rc<Sound>, ResourceError>{
+		match self.cache.entry(uri.to_owned()){
+			Entry::Occupied(entry) =>
+				if let &mut Resource::Sound(ref sound) = entry.into_mut() { Ok(sound.clone()) }
+				else {
+					error!(r#"Cached element at ID "{}" is not a Resource::Sound"#, uri);
+					Err(ResourceError::DifferentTypeAlreadyCached)
+				},
+			Entry::Vacant(entry) => {
+				let mut path = self.root.clone();
+				path.push(uri);
+
+				// Open and read the file into a buffer
+				if !path.exists(){
+					error!(r#"Path {:?} does not exist"#, path);
+					return Err(ResourceError::FileNotFound)
+				}
+				use std::fs::File;
+				use std::io::{Read, Cursor};
+				let mut buffer = Vec::<u8>::new();
+				match File::open(path.clone()){
+					Ok(mut file) => {
+						if let Err(what) = file.read_to_end(&mut buffer){
+							error!(r#"Could not read raw data from path {:?}: {:?}"#, path, what);
+							return Err(ResourceError::UnableToReadFile(what))
+						}
+					},
+					Err(what) => {
+						error!(r#"Could not open file at path {:?}: {:?}"#, path, what);
+						return Err(ResourceError::UnableToReadFile(what))
+					}
+				}
+
+				let codec = match Codec::detect(&buffer){
+					Some(codec) => codec,
+					None => {
+						error!(r#"File at path {:?} is not in any of the supported audio formats"#, path);
+						return Err(ResourceError::UnknownSoundFormat)
+					}
+				};
+				let sound = match Sound::from_reader(codec, Cursor::new(buffer)){
+					Ok(sound) => Rc::new(sound),
+					Err(what) => return Err(ResourceError::UnableToDecodeSound(what))
+				};
+
+				if let &mut Resource::Sound(ref sound) = entry.insert(Resource::Sound(sound)){
+					Ok(sound.clone())
+				}else{ panic!("Iconsistency! Wasn't able to destructure reference to cached value") }
+			}
+		}
+	}
+
+	/**
+	 * Loads a BMFont descriptor, in either its text or binary form, along with the
+	 * pages it refers to, which are looked up next to the descriptor.
+	 */
+	pub fn font<F: Facade>(&mut self, facade: &F, uri: &str) -> Result<Rc<BitmapFont>, ResourceError>{
+		match self.cache.entry(uri.to_owned()){
+			Entry::Occupied(entry) =>
+				if let &mut Resource::Font(ref font) = entry.into_mut() { Ok(font.clone()) }
+				else {
+					error!(r#"Cached element at ID "{}" is not a Resource::Font"#, uri);
+					Err(ResourceError::DifferentTypeAlreadyCached)
+				},
+			Entry::Vacant(entry) => {
+				let mut path = self.root.clone();
+				path.push(uri);
+
+				// Open and read the file into a buffer
+				if !path.exists(){
+					error!(r#"Path {:?} does not exist"#, path);
+					return Err(ResourceError::FileNotFound)
+				}
+				use std::fs::File;
+				use std::io::Read;
+				let mut buffer = Vec::<u8>::new();
+				match File::open(path.clone()){
+					Ok(mut file) => {
+						if let Err(what) = file.read_to_end(&mut buffer){
+							error!(r#"Could not read raw data from path {:?}: {:?}"#, path, what);
+							return Err(ResourceError::UnableToReadFile(what))
+						}
+					},
+					Err(what) => {
+						error!(r#"Could not open file at path {:?}: {:?}"#, path, what);
+						return Err(ResourceError::UnableToReadFile(what))
+					}
+				}
+
+				let font = match BitmapFont::from_memory(facade, &buffer, &path){
+					Ok(font) => Rc::new(font),
+					Err(what) => return Err(ResourceError::UnableToLoadFont(what))
+				};
+
+				if let &mut Resource::Font(ref font) = entry.insert(Resource::Font(font)){
+					Ok(font.clone())
+				}else{ panic!("Iconsistency! Wasn't able to destructure reference to cached value") }
+			}
+		}
+	}
+
 	/** Opens a file and caches it for later reuse, returning a slice reference */
 	pub fn raw(&mut self, uri: &str) -> Result<&[u8], ResourceError>{
 		match self.cache.entry(uri.to_owned()){
@@ -177,6 +293,41 @@ mod tests{
 		]);
 	}
 
+	#[test]
+	fn sound(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		use std::env;
+		use std::fs::File;
+		use std::io::Write;
+		let root = env::temp_dir();
+		let mut path = root.clone();
+		path.push("bbman_resource_sound.bin");
+
+		// The extension says nothing about the codec, only the contents do
+		File::create(&path).unwrap().write_all(&[
+			0x52, 0x49, 0x46, 0x46, 0x2A, 0x00, 0x00, 0x00, 0x57, 0x41, 0x56, 0x45, /* RIFF, WAVE        */
+			0x66, 0x6D, 0x74, 0x20, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, /* fmt, PCM, mono    */
+			0x40, 0x1F, 0x00, 0x00, 0x80, 0x3E, 0x00, 0x00, 0x02, 0x00, 0x10, 0x00, /* 8000Hz, 16 bits   */
+			0x64, 0x61, 0x74, 0x61, 0x06, 0x00, 0x00, 0x00,                         /* data, 6 bytes     */
+			0x01, 0x00, 0x02, 0x00, 0x03, 0x00
+		]).unwrap();
+
+		let mut resources = Resources::new(root.to_str().unwrap());
+		let sound = resources.sound("bbman_resource_sound.bin").unwrap();
+		assert_eq!(sound.data(), &[1, 2, 3]);
+
+		// Decoded only once, then shared
+		let again = resources.sound("bbman_resource_sound.bin").unwrap();
+		assert_eq!(&*sound as *const _, &*again as *const _);
+
+		match resources.text("bbman_resource_sound.bin"){
+			Err(super::ResourceError::DifferentTypeAlreadyCached) => {},
+			other => panic!("Expected a cache type error, got {:?}", other)
+		}
+	}
+
 	#[test]
 	fn texture(){
 		// Setup logger
@@ -192,4 +343,28 @@ mod tests{
 
 		let _ = Resources::new("./test/").texture(&display, "resources/texture.png").unwrap();
 	}
+
+	#[test]
+	fn font(){
+		// Setup logger
+		let _ = ::setup_logger();
+
+		// Setup context
+		use glium::DisplayBuild;
+		use glium::glutin::WindowBuilder;
+		let display = WindowBuilder::new()
+			.with_dimensions(1280, 720)
+			.with_title("Automated test: resource::font()")
+			.build_glium().unwrap();
+
+		// The page is looked up next to the descriptor
+		let mut resources = Resources::new("./test/");
+		let font = resources.font(&display, "resources/font.fnt").unwrap();
+		assert_eq!(font.data().face, "Resource Test");
+		assert_eq!(font.measure("A A"), (20.0, 18.0));
+
+		// Loaded only once, then shared
+		let again = resources.font(&display, "resources/font.fnt").unwrap();
+		assert_eq!(&*font as *const _, &*again as *const _);
+	}
 }
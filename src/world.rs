@@ -1,7 +1,6 @@
 use super::grid::Grid;
 
 use json;
-use glium::Texture2d;
 use glium::backend::Facade;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -21,9 +20,9 @@ impl Block{
 	}
 }
 
-use super::graphics::Texture;
+use super::graphics::{Texture, Sprite, RenderTarget};
 pub struct Room{
-    pub images: Vec<Texture>, /* Images available to the room's texture mapping */
+    pub images: Vec<Sprite>, /* Images available to the room's texture mapping */
 
     pub blocks:  Grid<Block>, /* Colision mapping */
     pub texture: Grid<usize>  /* Texture mapping  */
@@ -75,10 +74,8 @@ impl Room{
 				};
 
 				// Create a new sprite sheet and append it flattened
-				if let Ok(sheet) = texture.sprite_sheet(facade, tile_dimension.0, tile_dimension.1){
-					let mut flat = sheet.flatten();
-					imgs.append(&mut flat);
-				}
+				let mut flat = texture.sprite_sheet(tile_dimension.0, tile_dimension.1).flatten();
+				imgs.append(&mut flat);
 			}
 		} else if let Some(sources) = obj.get("images") {
 	        if !sources.is_null(){
@@ -86,7 +83,7 @@ impl Room{
 					if let Some(stringfied) = sources.as_str(){
 						// Load and push the images
 						imgs.push(match Texture::open(facade, stringfied){
-							Ok(loaded) => loaded,
+							Ok(loaded) => loaded.into_sprite(),
 							Err(what) => {
 								error!("Could not load texture: {:?}", what);
 								return None
@@ -136,30 +133,27 @@ impl Room{
     }
 
     /* Renders the room's texture grid into a single texture */
-    pub fn render<F: Facade>(&self, facade: &F) -> Option<Texture2d>{
+    pub fn render<F: Facade>(&self, facade: &F) -> Option<RenderTarget>{
         // Setup renderer
 		use super::graphics::Renderer2d;
 		let mut renderer = match Renderer2d::new(facade, self.texture.width as f32, self.texture.height as f32){
-			Some(renderer) => renderer,
-            None => {
-                error!("Could not create renderer");
+			Ok(renderer) => renderer,
+            Err(what) => {
+                error!("Could not create renderer: {:?}", what);
                 return None
             }
 		};
 
-        // Generate a new empty texture
-        let result = match Texture2d::empty(facade, self.texture.absolute_width() as u32, self.texture.absolute_height() as u32){
-            Ok(texture) => texture,
-            Err(err) => {
-                error!("Could not create texture: {:?}", err);
-                return None
-            }
+        // Generate a new empty render target
+        let result = match RenderTarget::new(facade, self.texture.absolute_width() as u32, self.texture.absolute_height() as u32){
+            Ok(target) => target,
+            Err(_) => return None
         };
 
         // Start new lexical context for canvas
         {
             // Get and clear the texture frame buffer
-            let mut canvas = result.as_surface();
+            let mut canvas = result.surface();
 
             use glium::Surface;
             canvas.clear_color(0.0, 0.0, 0.0, 0.0);
@@ -0,0 +1,332 @@
+# Writes a ProTracker and a FastTracker II module, along with reference renders of them
+# made by an implementation of FastTracker II playback that shares no code with the engine.
+#
+# The renders are 16-bit stereo at 11025Hz, with linear interpolation between sample points
+# and the mix scaled by 0.5 before rounding.
+# Run from the repository root with Python 3 to regenerate every file in this directory:
+#
+#     python3 test/modules/reference.py test/modules
+import math, struct, wave
+
+RATE = 11025
+AMPLIFICATION = 0.5
+SINE = [0,24,49,74,97,120,141,161,180,197,212,224,235,244,250,253,255,253,250,244,235,224,212,197,180,161,141,120,97,74,49,24]
+KEY_OFF = 97
+
+def f32(x): return struct.unpack('<f', struct.pack('<f', x))[0]
+
+# ---------- song descriptions ----------
+# A sample: dict(data=[floats], loop=(kind,start,end), volume, finetune(128ths), panning, relative)
+# A cell: (note 1..96/97/0, instrument, volcol, effect, param)
+
+def pt_note(period):
+	s = round(48 - 12 * math.log2(period * 4 / 1712))
+	return max(0, min(95, s)) + 1
+
+# ProTracker module
+square = [64 if i < 16 else -64 for i in range(32)]
+saw = [int(-128 + i * 4) for i in range(64)]
+mod_samples = [
+	# name, bytes, finetune nibble, volume, loop start words, loop length words
+	("square", square, 0, 48, 0, 16),
+	("saw", saw, 2, 64, 0, 1),
+]
+E = lambda: [[None]*4 for _ in range(64)]
+p0 = E(); p1 = E()
+def put(p, row, ch, period, ins, eff, param): p[row][ch] = (period, ins, eff, param)
+put(p0, 0, 0, 428, 1, 0, 0)
+put(p0, 0, 1, 320, 2, 0x0, 0x37)
+put(p0, 0, 2, 254, 1, 0xC, 0x20)
+put(p0, 4, 0, 0, 0, 0xA, 0x02)
+put(p0, 4, 3, 285, 1, 0x1, 0x03)
+put(p0, 5, 3, 0, 0, 0x1, 0x00)
+put(p0, 8, 0, 453, 2, 0xF, 0x04)
+put(p0, 8, 1, 0, 0, 0xC, 0x00)
+put(p0, 12, 2, 0, 0, 0x2, 0x04)
+put(p0, 15, 1, 0, 0, 0xD, 0x00)
+put(p1, 0, 0, 214, 1, 0, 0)
+put(p1, 0, 1, 0, 2, 0, 0)
+put(p1, 2, 3, 428, 0, 0x3, 0x08)
+put(p1, 3, 3, 0, 0, 0x3, 0x00)
+put(p1, 6, 0, 0, 0, 0xE, 0xC3)
+put(p1, 9, 2, 0, 0, 0xD, 0x00)
+
+def write_mod(path):
+	data = bytearray(b"reference".ljust(20, b"\0"))
+	for i in range(31):
+		if i < len(mod_samples):
+			name, raw, ft, vol, ls, ll = mod_samples[i]
+			h = name.encode().ljust(22, b"\0") + struct.pack(">HBBHH", len(raw)//2, ft & 0x0F, vol, ls, ll)
+		else:
+			h = bytes(22) + struct.pack(">HBBHH", 0, 0, 0, 0, 1)
+		data += h
+	data += bytes([2, 127]) + bytes([0, 1] + [0]*126) + b"M.K."
+	for p in (p0, p1):
+		for row in p:
+			for c in row:
+				if c is None: data += bytes(4); continue
+				period, ins, eff, param = c
+				data += bytes([(ins & 0xF0) | (period >> 8), period & 0xFF, ((ins & 0x0F) << 4) | eff, param])
+	for s in mod_samples: data += bytes(b & 0xFF for b in s[1])
+	open(path, "wb").write(data)
+
+def mod_song():
+	samples = []
+	for name, raw, ft, vol, ls, ll in mod_samples:
+		ft = ft - 16 if ft > 7 else ft
+		looping = ll > 1
+		samples.append(dict(data=[b/128.0 for b in raw], loop=("forward" if looping else None, ls*2, ls*2 + ll*2),
+			volume=vol, finetune=ft*16, panning=128, relative=0))
+	instruments = [dict(samples=[s], venv=None, fadeout=0) for s in samples]
+	def convert(p):
+		rows = []
+		for row in p:
+			cells = []
+			for c in row:
+				if c is None: cells.append((0,0,0,0,0)); continue
+				period, ins, eff, param = c
+				cells.append((pt_note(period) if period else 0, ins, 0, eff, param))
+			rows.append(cells)
+		return rows
+	return dict(kind="mod", linear=False, channels=4, orders=[0,1], patterns=[convert(p0), convert(p1)],
+		instruments=instruments, speed=6, tempo=125)
+
+# FastTracker II module
+sine = [int(round(100 * math.sin(2 * math.pi * i / 32))) for i in range(32)]
+tri16 = [int(-24000 + i * 3000) for i in range(16)] + [int(24000 - i * 3000) for i in range(16)]
+xm_instruments = [
+	dict(samples=[dict(data=[v/128.0 for v in sine], raw=sine, bits=8, loop=("forward", 0, 32), volume=64, finetune=0, panning=0x80, relative=0)],
+		venv=dict(points=[(0,64),(4,32),(8,48),(16,0)], sustain=2, repeat=None), fadeout=2048),
+	dict(samples=[dict(data=[v/32768.0 for v in tri16], raw=tri16, bits=16, loop=("pingpong", 8, 32), volume=40, finetune=16, panning=0x40, relative=12)],
+		venv=None, fadeout=0),
+]
+xm_rows = [[(0,0,0,0,0)]*2 for _ in range(16)]
+def xput(row, ch, cell): xm_rows[row] = list(xm_rows[row]); xm_rows[row][ch] = cell
+xput(0, 0, (49, 1, 0, 0, 0))
+xput(0, 1, (53, 2, 0x30, 0, 0))
+xput(2, 1, (0, 0, 0x62, 0, 0))
+xput(4, 0, (0, 0, 0, 0x4, 0x44))
+xput(5, 0, (0, 0, 0, 0x4, 0x00))
+xput(6, 0, (KEY_OFF, 0, 0, 0, 0))
+xput(8, 1, (0, 0, 0, 0x8, 0xC0))
+xput(10, 1, (KEY_OFF, 0, 0, 0, 0))
+xput(12, 0, (51, 1, 0, 0, 0))
+xput(12, 1, (56, 2, 0xC8, 0x1, 0x02))
+
+def write_xm(path):
+	u16 = lambda v: struct.pack("<H", v)
+	u32 = lambda v: struct.pack("<I", v)
+	data = bytearray(b"Extended Module: " + b"reference".ljust(20, b" ") + b"\x1A" + b"FastTracker v2.00".ljust(20, b" "))
+	data += u16(0x0104) + u32(276)
+	data += u16(1) + u16(0) + u16(2) + u16(1) + u16(len(xm_instruments)) + u16(1) + u16(4) + u16(140)
+	data += bytes(256)
+	packed = bytearray()
+	for row in xm_rows:
+		for (n, i, v, e, p) in row:
+			flags = 0x80 | (1 if n else 0) | (2 if i else 0) | (4 if v else 0) | (8 if e else 0) | (16 if p else 0)
+			if flags == 0x9F: packed += bytes([n, i, v, e, p]); continue
+			packed.append(flags)
+			for bit, value in ((1,n),(2,i),(4,v),(8,e),(16,p)):
+				if flags & bit: packed.append(value)
+	data += u32(9) + bytes([0]) + u16(16) + u16(len(packed)) + packed
+	for ins in xm_instruments:
+		header = bytearray(u32(263) + b"instrument".ljust(22, b"\0") + bytes([0]) + u16(1) + u32(40))
+		header += bytes(96)
+		points = bytearray(48)
+		env = ins["venv"]
+		if env:
+			for k, (t, v) in enumerate(env["points"]): points[k*4:k*4+4] = u16(t) + u16(v)
+		header += points + bytes(48)
+		header += bytes([len(env["points"]) if env else 0, 0, env["sustain"] if env else 0, 0, 0, 0, 0, 0, (3 if env else 0), 0])
+		header += bytes(4) + u16(ins["fadeout"])
+		header += bytes(263 - len(header))
+		data += header
+		s = ins["samples"][0]
+		unit = 2 if s["bits"] == 16 else 1
+		kind = {"forward": 1, "pingpong": 2, None: 0}[s["loop"][0]] | (0x10 if unit == 2 else 0)
+		data += u32(len(s["raw"]) * unit) + u32(s["loop"][1] * unit) + u32((s["loop"][2] - s["loop"][1]) * unit)
+		data += bytes([s["volume"], s["finetune"] & 0xFF, kind, s["panning"], s["relative"] & 0xFF, 0]) + b"sample".ljust(22, b"\0")
+		previous = 0
+		for v in s["raw"]:
+			if unit == 2: data += struct.pack("<h", ((v - previous + 32768) & 0xFFFF) - 32768)
+			else: data.append((v - previous) & 0xFF)
+			previous = v
+	open(path, "wb").write(data)
+
+def xm_song():
+	instruments = []
+	for ins in xm_instruments:
+		s = dict(ins["samples"][0]); s["loop"] = s["loop"]
+		instruments.append(dict(samples=[s], venv=ins["venv"], fadeout=ins["fadeout"]))
+	return dict(kind="xm", linear=True, channels=2, orders=[0], patterns=[xm_rows], instruments=instruments, speed=4, tempo=140)
+
+# ---------- reference player ----------
+def render(song, rate):
+	linear = song["linear"]
+	def period(note, ft):
+		if linear: return 7680.0 - note * 64.0 - ft / 2.0
+		return 1712.0 * 2 ** ((48 - note) / 12.0 - ft / 1536.0)
+	def frequency(per, semis):
+		base = 8363.0 * 2 ** ((4608.0 - per) / 768.0) if linear else 8363.0 * 1712.0 / per
+		return base * 2 ** (semis / 12.0)
+	def osc(wave, pos):
+		pos &= 63
+		return SINE[pos] if pos < 32 else -SINE[pos - 32]
+	def env_value(env, t):
+		pts = env["points"]
+		if t >= pts[-1][0]: return pts[-1][1] / 64.0
+		for a, b in zip(pts, pts[1:]):
+			if t < b[0] and b[0] > a[0]:
+				return (a[1] + (b[1] - a[1]) * max(t - a[0], 0) / (b[0] - a[0])) / 64.0
+		return pts[0][1] / 64.0
+	def env_advance(env, t, key_on):
+		if key_on and env["sustain"] is not None and t == env["points"][env["sustain"]][0]: return t
+		return min(t + 1, env["points"][-1][0])
+
+	chans = []
+	for c in range(song["channels"]):
+		pan = (0x40 if c % 4 in (0, 3) else 0xC0) if song["kind"] == "mod" else 0x80
+		chans.append(dict(ins=None, smp=None, active=False, pos=0.0, back=False, note=0, ft=0, per=0.0, target=0.0,
+			vol=0, pan=pan, key_on=False, fade=32768, vtick=0, arp=0, vib=0.0, pu=0, pd=0, ts=0, vs=0, vd=0, vp=0, slide=0, cell=(0,0,0,0,0)))
+	speed, tempo = song["speed"], song["tempo"]
+	out = []
+	remaining = 0.0
+	order, row, tick = 0, 0, 0
+	finished = False
+	brk = None
+	def sample_of(ch):
+		if ch["ins"] is None or ch["smp"] is None: return None
+		return song["instruments"][ch["ins"]]["samples"][ch["smp"]]
+	while True:
+		if remaining < 1.0:
+			if finished: break
+			# --- tick ---
+			if tick == 0:
+				pat = song["patterns"][song["orders"][order]]
+				for c, ch in enumerate(chans):
+					note, ins, vc, eff, par = pat[row][c]
+					ch["cell"] = (note, ins, vc, eff, par)
+					porta = eff in (3, 5) or vc >= 0xF0
+					if ins: ch["ins"] = ins - 1
+					if note == KEY_OFF:
+						ch["key_on"] = False
+						if not (ch["ins"] is not None and song["instruments"][ch["ins"]]["venv"]): ch["vol"] = 0
+					elif note:
+						if ch["ins"] is None: ch["active"] = False
+						elif porta and ch["active"]:
+							s = song["instruments"][ch["ins"]]["samples"][0]
+							ch["target"] = period(note - 1 + s["relative"], ch["ft"])
+						else:
+							ch["smp"] = 0
+							s = sample_of(ch)
+							ch["note"] = note - 1 + s["relative"]; ch["ft"] = s["finetune"]
+							ch["per"] = period(ch["note"], ch["ft"]); ch["target"] = ch["per"]
+							ch["pos"] = 0.0; ch["back"] = False; ch["active"] = True; ch["vp"] = 0
+					if ins and note != KEY_OFF:
+						s = sample_of(ch)
+						if s:
+							ch["vol"] = s["volume"]
+							if song["kind"] == "xm": ch["pan"] = s["panning"]
+						ch["key_on"] = True; ch["fade"] = 32768; ch["vtick"] = 0
+					# row effects
+					ch["arp"] = 0
+					if eff not in (4, 6): ch["vib"] = 0.0
+					if 0x10 <= vc <= 0x50: ch["vol"] = min(vc - 0x10, 64)
+					if vc >> 4 == 0xC: ch["pan"] = (vc & 15) * 17
+					x, y = par >> 4, par & 15
+					if eff == 1 and par: ch["pu"] = par
+					if eff == 2 and par: ch["pd"] = par
+					if eff == 3 and par: ch["ts"] = par
+					if eff == 4:
+						if x: ch["vs"] = x
+						if y: ch["vd"] = y
+					if eff == 0xA and par: ch["slide"] = par
+					if eff == 8: ch["pan"] = par
+					if eff == 0xC: ch["vol"] = min(par, 64)
+					if eff == 0xF and par: 
+						if par < 0x20: speed = par
+						else: tempo = par
+					if eff == 0xD: brk = x * 10 + y
+			else:
+				t = tick % speed
+				for ch in chans:
+					note, ins, vc, eff, par = ch["cell"]
+					x, y = par >> 4, par & 15
+					ch["arp"] = 0
+					if vc >> 4 == 6: ch["vol"] = max(ch["vol"] - (vc & 15), 0)
+					if vc >> 4 == 7: ch["vol"] = min(ch["vol"] + (vc & 15), 64)
+					if eff == 0 and par: ch["arp"] = [0, x, y][t % 3]
+					if eff == 1: ch["per"] = min(max(ch["per"] - 4 * ch["pu"], 1.0), 32000.0)
+					if eff == 2: ch["per"] = min(max(ch["per"] + 4 * ch["pd"], 1.0), 32000.0)
+					if eff == 3:
+						sp = ch["ts"] * 4
+						if ch["per"] < ch["target"]: ch["per"] = min(ch["per"] + sp, ch["target"])
+						elif ch["per"] > ch["target"]: ch["per"] = max(ch["per"] - sp, ch["target"])
+					if eff == 4:
+						ch["vib"] = osc(0, ch["vp"]) * ch["vd"] / 32.0
+						ch["vp"] = (ch["vp"] + ch["vs"]) & 63
+					if eff == 0xA:
+						up, down = ch["slide"] >> 4, ch["slide"] & 15
+						ch["vol"] = min(max(ch["vol"] + (up if up else -down), 0), 64)
+					if eff == 0xE and x == 0xC and t == y: ch["vol"] = 0
+			for ch in chans:
+				if ch["ins"] is None: continue
+				ins = song["instruments"][ch["ins"]]
+				if ins["venv"]:
+					ch["vtick"] = env_advance(ins["venv"], ch["vtick"], ch["key_on"])
+					if not ch["key_on"]: ch["fade"] = max(ch["fade"] - ins["fadeout"], 0)
+			tick += 1
+			if tick >= speed:
+				tick = 0
+				if brk is not None:
+					order += 1; row = brk; brk = None
+				else:
+					row += 1
+					if row >= len(song["patterns"][song["orders"][order]]): row = 0; order += 1
+				if order >= len(song["orders"]): finished = True
+			remaining += rate * 2.5 / tempo
+		count = int(remaining)
+		frames = [[0.0, 0.0] for _ in range(count)]
+		for ch in chans:
+			if not ch["active"]: continue
+			s = sample_of(ch)
+			ins = song["instruments"][ch["ins"]]
+			vol = min(max(ch["vol"], 0), 64) / 64.0
+			if ins["venv"]: vol *= env_value(ins["venv"], ch["vtick"]) * ch["fade"] / 32768.0
+			pan = ch["pan"]
+			left, right = vol * (256 - pan) / 256.0, vol * pan / 256.0
+			step = f32(f32(frequency(ch["per"] + ch["vib"], ch["arp"])) / rate)
+			data = s["data"]; kind, ls, le = s["loop"]
+			for f in frames:
+				if ch["pos"] >= len(data) or ch["pos"] < 0: ch["active"] = False; break
+				i = int(math.floor(ch["pos"]))
+				nxt = ls if kind == "forward" and i + 1 >= le else min(i + 1, len(data) - 1)
+				v = data[i] + (data[nxt] - data[i]) * (ch["pos"] - i)
+				f[0] += v * left; f[1] += v * right
+				ch["pos"] += -step if ch["back"] else step
+				if kind == "forward" and ch["pos"] >= le:
+					ch["pos"] = ls + math.fmod(ch["pos"] - le, le - ls)
+				elif kind == "pingpong":
+					start, end = float(ls), le - 1.0
+					if not ch["back"] and ch["pos"] > end: ch["pos"] = end - (ch["pos"] - end); ch["back"] = True
+					if ch["back"] and ch["pos"] < start: ch["pos"] = start + (start - ch["pos"]); ch["back"] = False
+					if ch["pos"] < start or ch["pos"] > end: ch["pos"] = start
+		for f in frames:
+			for v in f:
+				v = v * AMPLIFICATION * 32767.0
+				out.append(32767 if v >= 32767 else -32768 if v <= -32768 else int(math.floor(v + 0.5)) if v >= 0 else -int(math.floor(-v + 0.5)))
+		remaining -= count
+	return out
+
+def write_wav(path, samples, rate):
+	w = wave.open(path, "wb")
+	w.setnchannels(2); w.setsampwidth(2); w.setframerate(rate)
+	w.writeframes(struct.pack("<%dh" % len(samples), *samples))
+	w.close()
+
+import sys
+base = sys.argv[1]
+write_mod(base + "/reference.mod")
+write_xm(base + "/reference.xm")
+write_wav(base + "/reference.mod.wav", render(mod_song(), RATE), RATE)
+write_wav(base + "/reference.xm.wav", render(xm_song(), RATE), RATE)